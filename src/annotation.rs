@@ -0,0 +1,371 @@
+//! GTF and GFF3 annotation import
+//!
+//! Reads the exon features of a GTF (Ensembl/GENCODE style, grouped by `transcript_id`) or
+//! GFF3 (grouped by `Parent`) file into [`TranscriptRecord`]s, without going through
+//! `paftools.js gff2bed`. [`Aligner::read_annotation`] loads the introns of those
+//! transcripts into the index the same way [`Aligner::read_junction`] does for `-j`.
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//!
+//! let aligner = Aligner::builder()
+//!     .splice()
+//!     .with_cigar()
+//!     .with_index("reference.fa", None)
+//!     .expect("Unable to build index");
+//!
+//! let n_introns = aligner.read_annotation("gencode.gtf").unwrap();
+//! ```
+//!
+//! Only uncompressed files are supported.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::junctions::{JunctionError, JunctionRecord, TranscriptRecord};
+use crate::{Aligner, Built, Strand};
+
+/// Annotation file flavour
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum AnnotationFormat {
+    Gtf,
+    Gff3,
+}
+
+impl AnnotationFormat {
+    /// Guess the format from the file extension (`.gtf`, `.gff` or `.gff3`)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gtf" => Some(AnnotationFormat::Gtf),
+            "gff" | "gff3" => Some(AnnotationFormat::Gff3),
+            _ => None,
+        }
+    }
+}
+
+/// Errors from reading or loading an annotation file
+#[derive(Debug)]
+pub enum AnnotationError {
+    Io(io::Error),
+    /// The format could not be guessed from the file name
+    UnknownFormat(String),
+    /// A malformed line, numbered from 1
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// Loading the introns into the index failed
+    Junction(JunctionError),
+}
+
+impl std::fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnnotationError::Io(e) => write!(f, "Unable to read annotation: {e}"),
+            AnnotationError::UnknownFormat(path) => {
+                write!(f, "Unable to tell whether {path} is GTF or GFF3")
+            }
+            AnnotationError::Parse { line, message } => write!(f, "Line {line}: {message}"),
+            AnnotationError::Junction(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for AnnotationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnnotationError::Io(e) => Some(e),
+            AnnotationError::Junction(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AnnotationError {
+    fn from(e: io::Error) -> Self {
+        AnnotationError::Io(e)
+    }
+}
+
+impl From<JunctionError> for AnnotationError {
+    fn from(e: JunctionError) -> Self {
+        AnnotationError::Junction(e)
+    }
+}
+
+/// Read the transcripts of a GTF or GFF3 stream
+///
+/// Only `exon` features are used. Transcripts are returned in the order they are first
+/// seen, with exons in file order.
+pub fn read_transcripts<R: BufRead>(
+    reader: R,
+    format: AnnotationFormat,
+) -> Result<Vec<TranscriptRecord>, AnnotationError> {
+    let mut transcripts: Vec<TranscriptRecord> = Vec::new();
+    let mut by_id: HashMap<String, usize> = HashMap::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_no = i + 1;
+
+        // GFF3 may end with the reference sequences
+        if format == AnnotationFormat::Gff3 && line.starts_with("##FASTA") {
+            break;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parse_error = |message: String| AnnotationError::Parse {
+            line: line_no,
+            message,
+        };
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 9 {
+            return Err(parse_error(format!(
+                "expected 9 tab-separated columns, found {}",
+                fields.len()
+            )));
+        }
+        if fields[2] != "exon" {
+            continue;
+        }
+
+        let start: u32 = fields[3]
+            .parse()
+            .map_err(|_| parse_error(format!("invalid start {:?}", fields[3])))?;
+        let end: u32 = fields[4]
+            .parse()
+            .map_err(|_| parse_error(format!("invalid end {:?}", fields[4])))?;
+        if start == 0 || end < start {
+            return Err(parse_error(format!("invalid interval {start}-{end}")));
+        }
+        let strand = match fields[6] {
+            "+" => Some(Strand::Forward),
+            "-" => Some(Strand::Reverse),
+            _ => None,
+        };
+
+        let parents = match format {
+            AnnotationFormat::Gtf => gtf_attribute(fields[8], "transcript_id")
+                .map(|id| vec![id.to_string()])
+                .ok_or_else(|| parse_error("exon without transcript_id".to_string()))?,
+            AnnotationFormat::Gff3 => gff3_attribute(fields[8], "Parent")
+                .map(|ids| ids.split(',').map(str::to_string).collect())
+                .ok_or_else(|| parse_error("exon without Parent".to_string()))?,
+        };
+
+        for id in parents {
+            let index = *by_id.entry(id.clone()).or_insert_with(|| {
+                transcripts.push(TranscriptRecord {
+                    name: Some(id.clone()),
+                    contig: fields[0].to_string(),
+                    strand,
+                    exons: Vec::new(),
+                });
+                transcripts.len() - 1
+            });
+
+            let transcript = &mut transcripts[index];
+            if transcript.contig != fields[0] {
+                return Err(parse_error(format!(
+                    "transcript {id} has exons on both {} and {}",
+                    transcript.contig, fields[0]
+                )));
+            }
+            // 1-based closed to 0-based half-open
+            transcript.exons.push((start - 1, end));
+        }
+    }
+
+    Ok(transcripts)
+}
+
+/// Read the transcripts of a GTF or GFF3 file, guessing the format from its extension
+pub fn read_transcripts_file<P: AsRef<Path>>(
+    path: P,
+) -> Result<Vec<TranscriptRecord>, AnnotationError> {
+    let path = path.as_ref();
+    let format = AnnotationFormat::from_path(path)
+        .ok_or_else(|| AnnotationError::UnknownFormat(path.display().to_string()))?;
+    read_transcripts(BufReader::new(File::open(path)?), format)
+}
+
+/// `key "value";` pairs
+fn gtf_attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    attributes.split(';').find_map(|attribute| {
+        let (k, v) = attribute.trim().split_once(' ')?;
+        (k == key).then(|| v.trim().trim_matches('"'))
+    })
+}
+
+/// `key=value` pairs
+fn gff3_attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    attributes.split(';').find_map(|attribute| {
+        let (k, v) = attribute.trim().split_once('=')?;
+        (k == key).then_some(v)
+    })
+}
+
+impl TranscriptRecord {
+    /// The introns between consecutive exons, with the transcript's strand and no score
+    pub fn introns(&self) -> Vec<JunctionRecord> {
+        let mut exons = self.exons.clone();
+        exons.sort_unstable();
+        exons
+            .windows(2)
+            .filter(|pair| pair[0].1 < pair[1].0)
+            .map(|pair| JunctionRecord {
+                contig: self.contig.clone(),
+                start: pair[0].1,
+                end: pair[1].0,
+                strand: self.strand,
+                score: 0,
+            })
+            .collect()
+    }
+}
+
+impl Aligner<Built> {
+    /// Load the introns of a GTF or GFF3 file into the index as annotated junctions,
+    /// the same as [`read_junction`](Self::read_junction) with the equivalent BED file.
+    ///
+    /// Transcripts on contigs that are not in the index are skipped, so a genome-wide
+    /// annotation can be used with a partial reference. Returns the number of distinct
+    /// introns loaded.
+    pub fn read_annotation<P: AsRef<Path>>(&self, path: P) -> Result<usize, AnnotationError> {
+        let transcripts = read_transcripts_file(path)?;
+
+        let mut introns: Vec<JunctionRecord> = Vec::new();
+        for transcript in transcripts.iter() {
+            for intron in transcript.introns() {
+                match self.validate_interval(&intron.contig, intron.start, intron.end) {
+                    Ok(()) => introns.push(intron),
+                    Err(JunctionError::UnknownContig(_)) => break,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        // Transcripts of the same gene share most of their introns
+        introns.sort_by(|a, b| {
+            (&a.contig, a.start, a.end, a.strand).cmp(&(&b.contig, b.start, b.end, b.strand))
+        });
+        introns.dedup();
+
+        self.load_junctions(&introns)?;
+        Ok(introns.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_gtf() {
+        let gtf = "\
+#comment
+chr1\tsrc\ttranscript\t11\t300\t.\t-\t.\tgene_id \"g1\"; transcript_id \"t1\";
+chr1\tsrc\texon\t201\t300\t.\t-\t.\tgene_id \"g1\"; transcript_id \"t1\"; exon_number \"1\";
+chr1\tsrc\texon\t11\t100\t.\t-\t.\tgene_id \"g1\"; transcript_id \"t1\"; exon_number \"2\";
+chr2\tsrc\texon\t5\t50\t.\t+\t.\tgene_id \"g2\"; transcript_id \"t2\";
+";
+        let transcripts = read_transcripts(Cursor::new(gtf), AnnotationFormat::Gtf).unwrap();
+        assert_eq!(transcripts.len(), 2);
+        assert_eq!(transcripts[0].name.as_deref(), Some("t1"));
+        assert_eq!(transcripts[0].strand, Some(Strand::Reverse));
+        assert_eq!(transcripts[0].exons, vec![(200, 300), (10, 100)]);
+        assert_eq!(
+            transcripts[0].introns(),
+            vec![JunctionRecord {
+                contig: "chr1".to_string(),
+                start: 100,
+                end: 200,
+                strand: Some(Strand::Reverse),
+                score: 0,
+            }]
+        );
+        assert!(transcripts[1].introns().is_empty());
+
+        let missing_id = "chr1\tsrc\texon\t1\t10\t.\t+\t.\tgene_id \"g1\";\n";
+        assert!(matches!(
+            read_transcripts(Cursor::new(missing_id), AnnotationFormat::Gtf),
+            Err(AnnotationError::Parse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_read_gff3() {
+        let gff3 = "\
+##gff-version 3
+chr1\tsrc\tmRNA\t1\t300\t.\t+\t.\tID=t1;Parent=g1
+chr1\tsrc\texon\t1\t100\t.\t+\t.\tID=e1;Parent=t1,t2
+chr1\tsrc\texon\t151\t200\t.\t+\t.\tID=e2;Parent=t2
+chr1\tsrc\texon\t251\t300\t.\t+\t.\tID=e3;Parent=t1,t2
+##FASTA
+>chr1
+ACGT
+";
+        let transcripts = read_transcripts(Cursor::new(gff3), AnnotationFormat::Gff3).unwrap();
+        assert_eq!(transcripts.len(), 2);
+        assert_eq!(transcripts[0].exons, vec![(0, 100), (250, 300)]);
+        assert_eq!(transcripts[1].exons, vec![(0, 100), (150, 200), (250, 300)]);
+        assert_eq!(transcripts[1].introns().len(), 2);
+
+        let bad_start = "chr1\tsrc\texon\tone\t100\t.\t+\t.\tParent=t1\n";
+        assert!(matches!(
+            read_transcripts(Cursor::new(bad_start), AnnotationFormat::Gff3),
+            Err(AnnotationError::Parse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            AnnotationFormat::from_path("gencode.v44.annotation.gtf"),
+            Some(AnnotationFormat::Gtf)
+        );
+        assert_eq!(
+            AnnotationFormat::from_path("genes.GFF3"),
+            Some(AnnotationFormat::Gff3)
+        );
+        assert_eq!(AnnotationFormat::from_path("genes.bed"), None);
+    }
+
+    #[test]
+    fn test_read_annotation() {
+        let aligner = Aligner::builder()
+            .splice()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+
+        // Same exon structure as cdna.fwd in test_data/cDNA_vs_genome.sam
+        assert_eq!(
+            aligner.read_annotation("test_data/annotation.gtf").unwrap(),
+            3
+        );
+        assert_eq!(
+            aligner
+                .read_annotation("test_data/annotation.gff3")
+                .unwrap(),
+            3
+        );
+
+        let gtf = read_transcripts_file("test_data/annotation.gtf").unwrap();
+        let gff3 = read_transcripts_file("test_data/annotation.gff3").unwrap();
+        assert_eq!(gtf[0].exons, gff3[0].exons);
+
+        assert!(matches!(
+            aligner.read_annotation("test_data/genome.fa"),
+            Err(AnnotationError::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,397 @@
+//! Columnar output of mappings as Apache Arrow record batches, and Parquet files
+//!
+//! [`MappingBatchBuilder`] collects [`Mapping`]s into [`RecordBatch`]es with a fixed
+//! schema ([`mapping_schema`]): one row per mapping, one column per field, with the
+//! alignment fields null when alignment was not requested. With the `parquet` feature,
+//! [`ParquetWriter`] writes the same batches to a Parquet file.
+//!
+//! ```
+//! use minimap2::Mapping;
+//! use minimap2::arrow::MappingBatchBuilder;
+//!
+//! let mut builder = MappingBatchBuilder::new();
+//! builder.push(&Mapping::default());
+//! let batch = builder.finish();
+//! assert_eq!(batch.num_rows(), 1);
+//! assert_eq!(batch.schema().field(0).name(), "query_name");
+//! ```
+
+use std::sync::{Arc, LazyLock};
+
+use arrow_array::builder::{
+    BooleanBuilder, Int32Builder, StringBuilder, UInt8Builder, UInt32Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use crate::{Mapping, Strand};
+
+static SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    let field = |name, data_type, nullable| Field::new(name, data_type, nullable);
+    Arc::new(Schema::new(vec![
+        field("query_name", DataType::Utf8, true),
+        field("query_len", DataType::Int32, true),
+        field("query_start", DataType::Int32, false),
+        field("query_end", DataType::Int32, false),
+        field("strand", DataType::Utf8, false),
+        field("target_name", DataType::Utf8, true),
+        field("target_id", DataType::Int32, false),
+        field("target_len", DataType::Int32, false),
+        field("target_start", DataType::Int32, false),
+        field("target_end", DataType::Int32, false),
+        field("match_len", DataType::Int32, false),
+        field("block_len", DataType::Int32, false),
+        field("mapq", DataType::UInt32, false),
+        field("is_primary", DataType::Boolean, false),
+        field("is_supplementary", DataType::Boolean, false),
+        field("is_spliced", DataType::Boolean, false),
+        field("is_alt", DataType::Boolean, false),
+        field("trans_strand", DataType::Utf8, true),
+        field("segment_id", DataType::UInt8, false),
+        field("nm", DataType::Int32, true),
+        field("cigar", DataType::Utf8, true),
+        field("md", DataType::Utf8, true),
+        field("cs", DataType::Utf8, true),
+        field("alignment_score", DataType::Int32, true),
+    ]))
+});
+
+/// The schema of the batches built by [`MappingBatchBuilder`]
+///
+/// Strands are `+`/`-`, `cigar` is the CIGAR string and the alignment columns (`nm` to
+/// `alignment_score`) are null for mappings without an alignment.
+pub fn mapping_schema() -> SchemaRef {
+    Arc::clone(&SCHEMA)
+}
+
+fn strand_str(strand: Strand) -> &'static str {
+    match strand {
+        Strand::Forward => "+",
+        Strand::Reverse => "-",
+    }
+}
+
+/// Builds Arrow record batches from mappings
+#[derive(Debug, Default)]
+pub struct MappingBatchBuilder {
+    query_name: StringBuilder,
+    query_len: Int32Builder,
+    query_start: Int32Builder,
+    query_end: Int32Builder,
+    strand: StringBuilder,
+    target_name: StringBuilder,
+    target_id: Int32Builder,
+    target_len: Int32Builder,
+    target_start: Int32Builder,
+    target_end: Int32Builder,
+    match_len: Int32Builder,
+    block_len: Int32Builder,
+    mapq: UInt32Builder,
+    is_primary: BooleanBuilder,
+    is_supplementary: BooleanBuilder,
+    is_spliced: BooleanBuilder,
+    is_alt: BooleanBuilder,
+    trans_strand: StringBuilder,
+    segment_id: UInt8Builder,
+    nm: Int32Builder,
+    cigar: StringBuilder,
+    md: StringBuilder,
+    cs: StringBuilder,
+    alignment_score: Int32Builder,
+    len: usize,
+}
+
+impl MappingBatchBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one mapping as a row
+    pub fn push(&mut self, mapping: &Mapping) {
+        self.query_name.append_option(mapping.query_name.as_deref());
+        self.query_len
+            .append_option(mapping.query_len.map(|len| len.get()));
+        self.query_start.append_value(mapping.query_start);
+        self.query_end.append_value(mapping.query_end);
+        self.strand.append_value(strand_str(mapping.strand));
+        self.target_name
+            .append_option(mapping.target_name.as_deref());
+        self.target_id.append_value(mapping.target_id);
+        self.target_len.append_value(mapping.target_len);
+        self.target_start.append_value(mapping.target_start);
+        self.target_end.append_value(mapping.target_end);
+        self.match_len.append_value(mapping.match_len);
+        self.block_len.append_value(mapping.block_len);
+        self.mapq.append_value(mapping.mapq);
+        self.is_primary.append_value(mapping.is_primary);
+        self.is_supplementary.append_value(mapping.is_supplementary);
+        self.is_spliced.append_value(mapping.is_spliced);
+        self.is_alt.append_value(mapping.is_alt);
+        self.trans_strand
+            .append_option(mapping.trans_strand.map(strand_str));
+        self.segment_id.append_value(mapping.segment_id);
+
+        let alignment = mapping.alignment.as_ref();
+        self.nm.append_option(alignment.map(|a| a.nm));
+        self.cigar
+            .append_option(alignment.and_then(|a| a.cigar_str.as_deref()));
+        self.md
+            .append_option(alignment.and_then(|a| a.md.as_deref()));
+        self.cs
+            .append_option(alignment.and_then(|a| a.cs.as_deref()));
+        self.alignment_score
+            .append_option(alignment.and_then(|a| a.alignment_score));
+
+        self.len += 1;
+    }
+
+    /// Number of rows added since the last [`finish`](Self::finish)
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Build a batch from the rows added so far and reset the builder
+    pub fn finish(&mut self) -> RecordBatch {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.query_name.finish()),
+            Arc::new(self.query_len.finish()),
+            Arc::new(self.query_start.finish()),
+            Arc::new(self.query_end.finish()),
+            Arc::new(self.strand.finish()),
+            Arc::new(self.target_name.finish()),
+            Arc::new(self.target_id.finish()),
+            Arc::new(self.target_len.finish()),
+            Arc::new(self.target_start.finish()),
+            Arc::new(self.target_end.finish()),
+            Arc::new(self.match_len.finish()),
+            Arc::new(self.block_len.finish()),
+            Arc::new(self.mapq.finish()),
+            Arc::new(self.is_primary.finish()),
+            Arc::new(self.is_supplementary.finish()),
+            Arc::new(self.is_spliced.finish()),
+            Arc::new(self.is_alt.finish()),
+            Arc::new(self.trans_strand.finish()),
+            Arc::new(self.segment_id.finish()),
+            Arc::new(self.nm.finish()),
+            Arc::new(self.cigar.finish()),
+            Arc::new(self.md.finish()),
+            Arc::new(self.cs.finish()),
+            Arc::new(self.alignment_score.finish()),
+        ];
+        self.len = 0;
+        RecordBatch::try_new(mapping_schema(), columns)
+            .expect("Mapping columns always match the mapping schema")
+    }
+}
+
+impl<'a> Extend<&'a Mapping> for MappingBatchBuilder {
+    fn extend<T: IntoIterator<Item = &'a Mapping>>(&mut self, iter: T) {
+        for mapping in iter {
+            self.push(mapping);
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub use parquet_writer::ParquetWriter;
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+    use std::io::Write;
+
+    use parquet::arrow::ArrowWriter;
+    use parquet::errors::ParquetError;
+    use parquet::file::properties::WriterProperties;
+
+    use super::{MappingBatchBuilder, mapping_schema};
+    use crate::Mapping;
+
+    /// Writes mappings to a Parquet file with the [`mapping_schema`], in row groups of
+    /// `batch_size` mappings
+    ///
+    /// ```no_run
+    /// # use minimap2::*;
+    /// use minimap2::arrow::ParquetWriter;
+    ///
+    /// let aligner = Aligner::builder().map_ont().with_cigar().with_index("ref.fa", None).unwrap();
+    /// let mut writer = ParquetWriter::new(std::fs::File::create("mappings.parquet").unwrap()).unwrap();
+    /// for mapping in aligner.map(b"ACGT", true, false, None, None, None).unwrap() {
+    ///     writer.write(&mapping).unwrap();
+    /// }
+    /// writer.finish().unwrap();
+    /// ```
+    pub struct ParquetWriter<W: Write + Send> {
+        writer: ArrowWriter<W>,
+        builder: MappingBatchBuilder,
+        batch_size: usize,
+    }
+
+    impl<W: Write + Send> ParquetWriter<W> {
+        /// Write to `writer` with default writer properties
+        pub fn new(writer: W) -> Result<Self, ParquetError> {
+            Self::with_properties(writer, WriterProperties::default())
+        }
+
+        /// Write to `writer` with the given properties (compression, statistics, ...)
+        pub fn with_properties(
+            writer: W,
+            properties: WriterProperties,
+        ) -> Result<Self, ParquetError> {
+            Ok(ParquetWriter {
+                writer: ArrowWriter::try_new(writer, mapping_schema(), Some(properties))?,
+                builder: MappingBatchBuilder::new(),
+                batch_size: 65_536,
+            })
+        }
+
+        /// Number of mappings buffered before they are written as a batch
+        pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+            assert!(batch_size > 0, "Batch size must be at least 1");
+            self.batch_size = batch_size;
+            self
+        }
+
+        pub fn write(&mut self, mapping: &Mapping) -> Result<(), ParquetError> {
+            self.builder.push(mapping);
+            if self.builder.len() >= self.batch_size {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        pub fn write_all<'a, I>(&mut self, mappings: I) -> Result<(), ParquetError>
+        where
+            I: IntoIterator<Item = &'a Mapping>,
+        {
+            mappings
+                .into_iter()
+                .try_for_each(|mapping| self.write(mapping))
+        }
+
+        /// Write the buffered mappings
+        pub fn flush(&mut self) -> Result<(), ParquetError> {
+            if !self.builder.is_empty() {
+                self.writer.write(&self.builder.finish())?;
+            }
+            Ok(())
+        }
+
+        /// Write the remaining mappings and the file footer, returning the inner writer
+        pub fn finish(mut self) -> Result<W, ParquetError> {
+            self.flush()?;
+            self.writer.into_inner()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Alignment;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int32Type, UInt32Type};
+    use std::num::NonZeroI32;
+
+    fn mappings() -> Vec<Mapping> {
+        let aligned = Mapping {
+            query_name: Some(Arc::new("read1".to_string())),
+            query_len: NonZeroI32::new(100),
+            query_start: 0,
+            query_end: 100,
+            strand: Strand::Reverse,
+            target_name: Some(Arc::new("chr1".to_string())),
+            target_len: 1000,
+            target_start: 200,
+            target_end: 300,
+            target_id: 0,
+            match_len: 98,
+            block_len: 100,
+            mapq: 60,
+            is_primary: true,
+            alignment: Some(Alignment {
+                nm: 2,
+                cigar: Some(vec![(100, 0)]),
+                cigar_str: Some("100M".to_string()),
+                md: Some("40A10C48".to_string()),
+                cs: None,
+                alignment_score: Some(180),
+            }),
+            ..Default::default()
+        };
+        let unaligned = Mapping {
+            query_name: Some(Arc::new("read2".to_string())),
+            trans_strand: Some(Strand::Forward),
+            segment_id: 1,
+            ..Default::default()
+        };
+        vec![aligned, unaligned]
+    }
+
+    #[test]
+    fn batch_columns() {
+        let mut builder = MappingBatchBuilder::new();
+        builder.extend(&mappings());
+        assert_eq!(builder.len(), 2);
+
+        let batch = builder.finish();
+        assert!(builder.is_empty());
+        assert_eq!(batch.schema(), mapping_schema());
+        assert_eq!(batch.num_rows(), 2);
+
+        let column = |name: &str| batch.column_by_name(name).unwrap();
+        assert_eq!(column("query_name").as_string::<i32>().value(1), "read2");
+        assert_eq!(column("strand").as_string::<i32>().value(0), "-");
+        assert_eq!(column("mapq").as_primitive::<UInt32Type>().value(0), 60);
+        assert_eq!(column("cigar").as_string::<i32>().value(0), "100M");
+        assert!(column("cigar").is_null(1));
+        assert!(column("cs").is_null(0));
+        assert!(column("query_len").is_null(1));
+        assert_eq!(
+            column("alignment_score")
+                .as_primitive::<Int32Type>()
+                .value(0),
+            180
+        );
+        assert!(column("trans_strand").is_null(0));
+        assert_eq!(column("trans_strand").as_string::<i32>().value(1), "+");
+
+        // The builder is reusable after finish
+        builder.push(&mappings()[0]);
+        assert_eq!(builder.finish().num_rows(), 1);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_round_trip() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let path = std::env::temp_dir().join(format!("minimap2-rs-{}.parquet", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        let mut writer = ParquetWriter::new(file).unwrap().with_batch_size(1);
+        writer.write_all(&mappings()).unwrap();
+        writer.finish().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut builder = MappingBatchBuilder::new();
+        builder.extend(&mappings());
+        let expected = builder.finish();
+
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 2);
+        assert_eq!(batches[0].schema().fields(), expected.schema().fields());
+        assert_eq!(
+            batches[0].column(20),
+            &expected.column(20).slice(0, batches[0].num_rows())
+        );
+    }
+}
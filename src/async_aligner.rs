@@ -0,0 +1,290 @@
+//! Async mapping on tokio
+//!
+//! [`AsyncAligner`] wraps a built aligner for use from async code, e.g. HTTP or gRPC
+//! handlers. Every request runs on tokio's blocking pool, with at most `max_concurrent`
+//! requests mapping at a time; the rest wait for a slot without blocking the runtime.
+//!
+//! Requests are cancelled by dropping their future: a request that has not started mapping
+//! yet is skipped, one that has already started finishes in the background and its result
+//! is discarded (minimap2 cannot be interrupted). A timeout, set with
+//! [`AsyncAligner::with_timeout`], covers both the wait for a slot and the mapping itself.
+//! Clones share the index and the concurrency limit, so per-request timeouts are a cheap
+//! `aligner.clone().with_timeout(...)`.
+//!
+//! ```no_run
+//! # async fn run() {
+//! use std::time::Duration;
+//! use minimap2::Aligner;
+//! use minimap2::async_aligner::AsyncAligner;
+//!
+//! let aligner = Aligner::builder().map_ont().with_index("ref.fa", None).unwrap();
+//! let aligner = AsyncAligner::new(aligner, 4).with_timeout(Duration::from_secs(10));
+//!
+//! let mappings = aligner
+//!     .map(b"ACGGTAGAGAGGAAGCAAT".to_vec(), false, false, None, None, None)
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{Semaphore, oneshot};
+
+use crate::{Aligner, Built, Mapping};
+
+/// Errors from [`AsyncAligner`] requests
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsyncMapError {
+    /// The aligner returned an error
+    Map(&'static str),
+    /// The request did not complete within the timeout
+    Timeout(Duration),
+    /// The request stopped without a result (it panicked, or the runtime is shutting down)
+    Aborted,
+}
+
+impl std::fmt::Display for AsyncMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsyncMapError::Map(e) => write!(f, "{e}"),
+            AsyncMapError::Timeout(timeout) => write!(f, "Mapping timed out after {timeout:?}"),
+            AsyncMapError::Aborted => write!(f, "Mapping was aborted"),
+        }
+    }
+}
+
+impl std::error::Error for AsyncMapError {}
+
+/// An aligner for async code, running requests on tokio's blocking pool
+#[derive(Clone)]
+pub struct AsyncAligner {
+    aligner: Arc<Aligner<Built>>,
+    permits: Arc<Semaphore>,
+    timeout: Option<Duration>,
+}
+
+impl AsyncAligner {
+    /// Run at most `max_concurrent` requests at a time on `aligner`
+    pub fn new(aligner: Aligner<Built>, max_concurrent: usize) -> Self {
+        Self::from_arc(Arc::new(aligner), max_concurrent)
+    }
+
+    /// Like [`new`](Self::new), for an aligner that is shared with synchronous code
+    pub fn from_arc(aligner: Arc<Aligner<Built>>, max_concurrent: usize) -> Self {
+        assert!(max_concurrent > 0, "max_concurrent must be at least 1");
+        AsyncAligner {
+            aligner,
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            timeout: None,
+        }
+    }
+
+    /// Fail requests that take longer than `timeout`, including the wait for a free slot
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Remove the timeout
+    pub fn without_timeout(mut self) -> Self {
+        self.timeout = None;
+        self
+    }
+
+    /// The wrapped aligner
+    pub fn aligner(&self) -> &Arc<Aligner<Built>> {
+        &self.aligner
+    }
+
+    /// Number of requests that could start mapping right now
+    pub fn available_slots(&self) -> usize {
+        self.permits.available_permits()
+    }
+
+    /// Async [`Aligner::map`]
+    pub async fn map(
+        &self,
+        seq: Vec<u8>,
+        cs: bool,
+        md: bool,
+        max_frag_len: Option<usize>,
+        extra_flags: Option<Vec<u64>>,
+        query_name: Option<Vec<u8>>,
+    ) -> Result<Vec<Mapping>, AsyncMapError> {
+        self.run(move |aligner| {
+            aligner.map(
+                &seq,
+                cs,
+                md,
+                max_frag_len,
+                extra_flags.as_deref(),
+                query_name.as_deref(),
+            )
+        })
+        .await
+    }
+
+    /// Async [`Aligner::map_pair`]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn map_pair(
+        &self,
+        seq1: Vec<u8>,
+        seq2: Vec<u8>,
+        cs: bool,
+        md: bool,
+        max_frag_len: Option<usize>,
+        extra_flags: Option<Vec<u64>>,
+        query_name: Option<Vec<u8>>,
+    ) -> Result<(Vec<Mapping>, Vec<Mapping>), AsyncMapError> {
+        self.run(move |aligner| {
+            aligner.map_pair(
+                &seq1,
+                &seq2,
+                cs,
+                md,
+                max_frag_len,
+                extra_flags.as_deref(),
+                query_name.as_deref(),
+            )
+        })
+        .await
+    }
+
+    /// Run any work on the aligner (e.g. [`Aligner::map_fragment`]) under the same
+    /// concurrency limit, timeout and cancellation rules as [`map`](Self::map)
+    pub async fn run<T, F>(&self, f: F) -> Result<T, AsyncMapError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Aligner<Built>) -> Result<T, &'static str> + Send + 'static,
+    {
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.run_inner(f))
+                .await
+                .unwrap_or(Err(AsyncMapError::Timeout(timeout))),
+            None => self.run_inner(f).await,
+        }
+    }
+
+    async fn run_inner<T, F>(&self, f: F) -> Result<T, AsyncMapError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Aligner<Built>) -> Result<T, &'static str> + Send + 'static,
+    {
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| AsyncMapError::Aborted)?;
+        let aligner = Arc::clone(&self.aligner);
+        let (tx, rx) = oneshot::channel();
+
+        // The permit moves into the task, so the slot stays taken until mapping finishes
+        // even if the caller has gone away. It is released before the result is sent, so
+        // the slot is free again by the time the caller sees the result.
+        tokio::task::spawn_blocking(move || {
+            if tx.is_closed() {
+                return;
+            }
+            let result = f(&aligner);
+            drop(permit);
+            let _ = tx.send(result);
+        });
+
+        match rx.await {
+            Ok(result) => result.map_err(AsyncMapError::Map),
+            Err(_) => Err(AsyncMapError::Aborted),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const QUERY: &[u8] = b"GTTTATGTAGCTTATTCTATCCAAAGCAATGCACTGAAAATGTCTCGACGGGCCCACACGCCCCATAAACAAATAGGTTTGGTCCTAGCCTTTCTATTAGCTCTTAGTGAGGTTACACATGCAAGCATCCCCGCCCCAGTGAGTCGCCCTCCAAGTCACTCTGACTAAGAGGAGCAAGCATCAAGCACGCAACAGCGCAG";
+
+    fn aligner(max_concurrent: usize) -> AsyncAligner {
+        let aligner = Aligner::builder()
+            .map_ont()
+            .with_cigar()
+            .with_index("test_data/MT-human.fa", None)
+            .unwrap();
+        AsyncAligner::new(aligner, max_concurrent)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn map_concurrently() {
+        let aligner = aligner(2);
+        let expected = aligner
+            .aligner()
+            .map(QUERY, true, false, None, None, None)
+            .unwrap();
+
+        let requests = (0..8).map(|_| {
+            let aligner = aligner.clone();
+            tokio::spawn(async move {
+                aligner
+                    .map(QUERY.to_vec(), true, false, None, None, Some(b"q".to_vec()))
+                    .await
+            })
+        });
+        for request in requests {
+            let mappings = request.await.unwrap().unwrap();
+            assert_eq!(mappings.len(), expected.len());
+            assert_eq!(mappings[0].target_start, expected[0].target_start);
+            assert_eq!(mappings[0].query_name.as_deref().unwrap(), "q");
+        }
+        assert_eq!(aligner.available_slots(), 2);
+
+        let err = aligner
+            .map(Vec::new(), false, false, None, None, None)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, AsyncMapError::Map(_)));
+    }
+
+    #[tokio::test]
+    async fn timeout_and_cancellation() {
+        let aligner = aligner(1);
+
+        // Occupy the only slot
+        let busy = aligner.clone();
+        let blocker = tokio::spawn(async move {
+            busy.run(|_| {
+                std::thread::sleep(Duration::from_millis(300));
+                Ok(())
+            })
+            .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Times out while waiting for the slot, and never runs
+        let ran = Arc::new(AtomicBool::new(false));
+        let ran2 = Arc::clone(&ran);
+        let err = aligner
+            .clone()
+            .with_timeout(Duration::from_millis(20))
+            .run(move |_| {
+                ran2.store(true, Ordering::SeqCst);
+                Ok(())
+            })
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err, AsyncMapError::Timeout(Duration::from_millis(20)));
+
+        blocker.await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!ran.load(Ordering::SeqCst));
+        assert_eq!(aligner.available_slots(), 1);
+
+        let mappings = aligner
+            .map(QUERY.to_vec(), false, false, None, None, None)
+            .await
+            .unwrap();
+        assert!(!mappings.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+//! Base modification (`MM`/`ML`) tags of copied FASTQ comments
+//!
+//! ONT and PacBio basecallers write methylation calls as `MM:Z` / `ML:B:C` SAM tags in the
+//! FASTQ comment, which minimap2 copies into its output with `-y` (`MM_F_COPY_COMMENT`).
+//! The tags refer to the read as sequenced, so reverse-strand records need no change, but
+//! hard-clipped records (supplementary alignments by default) only keep part of the read:
+//! [`clip_base_mods`] drops the calls outside the kept part and rebases the skip counts,
+//! and [`split_comment`] / [`clip_comment`] handle whole comments.
+//!
+//! ```
+//! use minimap2::base_mods::clip_base_mods;
+//!
+//! // 5mC calls on the 1st and 3rd C of the read, with probabilities 200 and 10
+//! let seq = b"ACGTCCGTCA";
+//! let (mm, ml) = clip_base_mods("C+m?,0,1;", &[200, 10], seq, 3, 10).unwrap();
+//! // Keeping bases 3..10 leaves CCGTCA: the old 3rd C is now the 2nd
+//! assert_eq!(mm, "C+m?,1;");
+//! assert_eq!(ml, [10]);
+//! ```
+
+/// Errors from parsing `MM`/`ML` tags
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BaseModError {
+    /// An `MM` entry is malformed
+    InvalidEntry(String),
+    /// The skip counts of an `MM` entry go past the end of the read
+    OutOfRange(String),
+    /// `ML` does not have one probability per call and modification code
+    LengthMismatch { expected: usize, found: usize },
+}
+
+impl std::fmt::Display for BaseModError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BaseModError::InvalidEntry(entry) => write!(f, "Invalid MM entry: {entry}"),
+            BaseModError::OutOfRange(entry) => {
+                write!(f, "MM entry refers past the end of the read: {entry}")
+            }
+            BaseModError::LengthMismatch { expected, found } => {
+                write!(f, "ML has {found} values, MM needs {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BaseModError {}
+
+/// One `MM` entry, e.g. `C+mh?,0,3`
+struct Entry<'a> {
+    /// Everything before the skip counts, e.g. `C+mh?`
+    head: &'a str,
+    base: u8,
+    /// Modification codes per call (each letter, or one ChEBI id)
+    n_codes: usize,
+    skips: Vec<usize>,
+}
+
+fn parse_entries(mm: &str) -> Result<Vec<Entry<'_>>, BaseModError> {
+    let invalid = |entry: &str| BaseModError::InvalidEntry(entry.to_string());
+    mm.split(';')
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut parts = entry.split(',');
+            let head = parts.next().unwrap();
+            let bytes = head.as_bytes();
+            if bytes.len() < 3 || !b"ACGTUN".contains(&bytes[0]) || !b"+-".contains(&bytes[1]) {
+                return Err(invalid(entry));
+            }
+            let codes = head[2..].trim_end_matches(['.', '?']);
+            let n_codes = if codes.bytes().all(|b| b.is_ascii_digit()) {
+                1
+            } else if codes.bytes().all(|b| b.is_ascii_alphabetic()) {
+                codes.len()
+            } else {
+                return Err(invalid(entry));
+            };
+            if codes.is_empty() {
+                return Err(invalid(entry));
+            }
+            let skips = parts
+                .map(|skip| skip.parse().map_err(|_| invalid(entry)))
+                .collect::<Result<_, _>>()?;
+            Ok(Entry {
+                head,
+                base: bytes[0],
+                n_codes,
+                skips,
+            })
+        })
+        .collect()
+}
+
+/// Whether read base `b` counts for an entry on `base`
+fn matches(base: u8, b: u8) -> bool {
+    let b = b.to_ascii_uppercase();
+    base == b'N' || b == base || (base == b'U' && b == b'T')
+}
+
+/// Keep the calls of `MM`/`ML` within `seq[start..end]` and rebase them to that range
+///
+/// `seq` is the read as sequenced (not reverse complemented), as are `start` and `end`.
+/// Returns the new `MM` value and `ML` probabilities.
+pub fn clip_base_mods(
+    mm: &str,
+    ml: &[u8],
+    seq: &[u8],
+    start: usize,
+    end: usize,
+) -> Result<(String, Vec<u8>), BaseModError> {
+    let entries = parse_entries(mm)?;
+    let expected = entries.iter().map(|e| e.skips.len() * e.n_codes).sum();
+    if ml.len() != expected {
+        return Err(BaseModError::LengthMismatch {
+            expected,
+            found: ml.len(),
+        });
+    }
+
+    let mut new_mm = String::new();
+    let mut new_ml = Vec::new();
+    let mut probs = ml.iter().copied();
+    for entry in &entries {
+        // Positions of every base the entry counts, in read order
+        let bases: Vec<usize> = (0..seq.len())
+            .filter(|&i| matches(entry.base, seq[i]))
+            .collect();
+        let first_kept = bases.partition_point(|&i| i < start);
+
+        new_mm.push_str(entry.head);
+        let mut occurrence = 0;
+        let mut last_kept = None;
+        for &skip in &entry.skips {
+            occurrence += skip;
+            let Some(&pos) = bases.get(occurrence) else {
+                return Err(BaseModError::OutOfRange(entry.head.to_string()));
+            };
+            let call: Vec<u8> = probs.by_ref().take(entry.n_codes).collect();
+            if pos >= start && pos < end {
+                let kept = occurrence - first_kept;
+                let skip = match last_kept {
+                    Some(last) => kept - last - 1,
+                    None => kept,
+                };
+                new_mm.push_str(&format!(",{skip}"));
+                new_ml.extend(call);
+                last_kept = Some(kept);
+            }
+            occurrence += 1;
+        }
+        new_mm.push(';');
+    }
+    Ok((new_mm, new_ml))
+}
+
+/// Split a copied comment into its tab-separated SAM tags, `(tag, type, value)`
+///
+/// Fields that are not SAM tags are skipped.
+pub fn split_comment(comment: &str) -> Vec<(&str, &str, &str)> {
+    comment
+        .split('\t')
+        .filter_map(|field| {
+            let mut parts = field.splitn(3, ':');
+            let (tag, ty, value) = (parts.next()?, parts.next()?, parts.next()?);
+            let valid = tag.len() == 2
+                && tag.bytes().all(|b| b.is_ascii_alphanumeric())
+                && ty.len() == 1
+                && "AifZHB".contains(ty);
+            valid.then_some((tag, ty, value))
+        })
+        .collect()
+}
+
+/// Rewrite the `MM`/`ML` tags of a copied comment for a record that keeps only
+/// `seq[start..end]` of the read, adding `MN:i` with the kept length
+///
+/// Other tags are kept as they are; a comment without `MM` is returned unchanged.
+pub fn clip_comment(
+    comment: &str,
+    seq: &[u8],
+    start: usize,
+    end: usize,
+) -> Result<String, BaseModError> {
+    let tags = split_comment(comment);
+    let tag = |name: &str| tags.iter().find(|(t, _, _)| *t == name);
+    let Some((_, _, mm)) = tag("MM") else {
+        return Ok(comment.to_string());
+    };
+    let ml: Vec<u8> = match tag("ML") {
+        Some((_, "B", value)) => {
+            let mut values = value.split(',');
+            if values.next() != Some("C") {
+                return Err(BaseModError::InvalidEntry(value.to_string()));
+            }
+            values
+                .map(|v| {
+                    v.parse()
+                        .map_err(|_| BaseModError::InvalidEntry(v.to_string()))
+                })
+                .collect::<Result<_, _>>()?
+        }
+        Some((_, _, value)) => return Err(BaseModError::InvalidEntry(value.to_string())),
+        None => Vec::new(),
+    };
+    let (mm, ml) = clip_base_mods(mm, &ml, seq, start, end)?;
+
+    let mut fields = Vec::with_capacity(tags.len() + 1);
+    for (tag, ty, value) in &tags {
+        match *tag {
+            "MM" => fields.push(format!("MM:Z:{mm}")),
+            "ML" => {
+                let values: String = ml.iter().map(|p| format!(",{p}")).collect();
+                fields.push(format!("ML:B:C{values}"));
+            }
+            "MN" => {}
+            _ => fields.push(format!("{tag}:{ty}:{value}")),
+        }
+    }
+    fields.push(format!("MN:i:{}", end - start));
+    Ok(fields.join("\t"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_entries() {
+        let seq = b"ACGTCCGTCAGC";
+        // C at 1, 4, 5, 8, 11; G at 2, 6, 10
+        let mm = "C+mh?,0,1,1;G-m,1;";
+        let ml = [1, 2, 3, 4, 5, 6, 7];
+        let (full_mm, full_ml) = clip_base_mods(mm, &ml, seq, 0, seq.len()).unwrap();
+        assert_eq!((full_mm.as_str(), full_ml.as_slice()), (mm, &ml[..]));
+
+        // Keep 3..9: the C call at 5 is the 2nd kept C, those at 1 and 11 go
+        let (clipped_mm, clipped_ml) = clip_base_mods(mm, &ml, seq, 3, 9).unwrap();
+        assert_eq!(clipped_mm, "C+mh?,1;G-m,0;");
+        assert_eq!(clipped_ml, [3, 4, 7]);
+
+        // No calls left
+        let (mm, ml) = clip_base_mods("C+m,0;", &[9], seq, 2, 4).unwrap();
+        assert_eq!((mm.as_str(), ml.len()), ("C+m;", 0));
+
+        // ChEBI codes count as a single modification
+        let (mm, ml) = clip_base_mods("N+17596,3;", &[42], seq, 2, 12).unwrap();
+        assert_eq!((mm.as_str(), ml.as_slice()), ("N+17596,1;", &[42][..]));
+    }
+
+    #[test]
+    fn errors() {
+        let seq = b"ACGT";
+        assert!(matches!(
+            clip_base_mods("X+m,0;", &[1], seq, 0, 4),
+            Err(BaseModError::InvalidEntry(_))
+        ));
+        assert!(matches!(
+            clip_base_mods("C+m,1;", &[1], seq, 0, 4),
+            Err(BaseModError::OutOfRange(_))
+        ));
+        assert_eq!(
+            clip_base_mods("C+mh,0;", &[1], seq, 0, 4).err().unwrap(),
+            BaseModError::LengthMismatch {
+                expected: 2,
+                found: 1
+            }
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let seq = b"ACGTCCGTCAGC";
+        let comment = "RG:Z:rg1\tMM:Z:C+m?,0,1,1;\tML:B:C,10,20,30\tnot a tag";
+        assert_eq!(
+            split_comment(comment),
+            [
+                ("RG", "Z", "rg1"),
+                ("MM", "Z", "C+m?,0,1,1;"),
+                ("ML", "B", "C,10,20,30")
+            ]
+        );
+        assert_eq!(
+            clip_comment(comment, seq, 3, 9).unwrap(),
+            "RG:Z:rg1\tMM:Z:C+m?,1;\tML:B:C,20\tMN:i:6"
+        );
+        assert_eq!(clip_comment("RG:Z:rg1", seq, 3, 9).unwrap(), "RG:Z:rg1");
+    }
+}
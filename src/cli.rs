@@ -0,0 +1,963 @@
+//! Build an aligner from minimap2 command line options
+//!
+//! [`Aligner::from_cli_args`] follows the option handling of minimap2's `main.c`: presets
+//! given with `-x` are applied first (in order), then every other option in the order it
+//! appears, and the result is checked with [`Aligner::validate_opts`]. Short options can be
+//! clustered (`-ax sr`, `-k17`), long options take their value either as `--opt=value` or
+//! as the next argument, and sizes accept the same `K`/`M`/`G` suffixes as minimap2.
+//!
+//! Options that only make sense for the command line tool (output files, index dumps,
+//! debugging output, ...) and positional file arguments are rejected with a [`CliError`]
+//! rather than silently ignored, so a stored command line either reproduces the run
+//! exactly or fails loudly. The `@RG` line of `-R` is checked, and returned by
+//! [`read_group`] for the SAM/BAM writers.
+//!
+//! ```
+//! use minimap2::Aligner;
+//! use minimap2::cli::CliError;
+//!
+//! let aligner = Aligner::from_cli_args(&["-x", "map-ont", "-k17", "--secondary=no", "-c"]).unwrap();
+//! assert_eq!(aligner.idxopt.k, 17);
+//!
+//! let err = Aligner::from_cli_args(&["-x", "map-ont", "-o", "out.paf"]).err().unwrap();
+//! assert!(matches!(err, CliError::Unsupported { .. }));
+//! ```
+
+use minimap2_sys::*;
+
+use crate::options::OptionError;
+use crate::sam::ReadGroup;
+use crate::{Aligner, PresetSet};
+
+/// Short options taking a value, as in `main.c`'s option string
+const SHORT_WITH_VALUE: &str = "wkKtrfvgGIdTsxpMnzABOEmNuRFCoeUJbj";
+/// Short options without a value
+const SHORT_FLAGS: &str = "2aSDVXHcQhLyYP";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Arg {
+    None,
+    Required,
+    Optional,
+}
+
+/// Long options known to minimap2 and whether they take a value
+const LONG_OPTIONS: &[(&str, Arg)] = &[
+    ("alt", Arg::Required),
+    ("alt-drop", Arg::Required),
+    ("all-chain", Arg::None),
+    ("bucket-bits", Arg::Required),
+    ("cap-kalloc", Arg::Required),
+    ("cap-sw-mem", Arg::Required),
+    ("chain-gap-scale", Arg::Required),
+    ("chain-skip-scale", Arg::Required),
+    ("cost-non-gt-ag", Arg::Required),
+    ("cs", Arg::Optional),
+    ("dbg-seed-occ", Arg::None),
+    ("ds", Arg::None),
+    ("dual", Arg::Required),
+    ("end-bonus", Arg::Required),
+    ("end-seed-pen", Arg::Required),
+    ("eqx", Arg::None),
+    ("for-only", Arg::None),
+    ("frag", Arg::Required),
+    ("hard-mask-level", Arg::None),
+    ("heap-sort", Arg::Required),
+    ("help", Arg::None),
+    ("idx-no-seq", Arg::None),
+    ("jump-min-match", Arg::Required),
+    ("junc-bed", Arg::Required),
+    ("junc-bonus", Arg::Required),
+    ("junc-pen", Arg::Required),
+    ("lj-min-ratio", Arg::Required),
+    ("mask-level", Arg::Required),
+    ("mask-len", Arg::Required),
+    ("max-chain-iter", Arg::Required),
+    ("max-chain-skip", Arg::Required),
+    ("max-clip-ratio", Arg::Required),
+    ("max-intron-len", Arg::Required),
+    ("max-qlen", Arg::Required),
+    ("mb-size", Arg::Required),
+    ("MD", Arg::None),
+    ("min-chain-score", Arg::Required),
+    ("min-count", Arg::Required),
+    ("min-dp-len", Arg::Required),
+    ("min-dp-score", Arg::Required),
+    ("min-occ-floor", Arg::Required),
+    ("no-end-flt", Arg::None),
+    ("no-hash-name", Arg::None),
+    ("no-kalloc", Arg::None),
+    ("no-long-join", Arg::None),
+    ("no-pairing", Arg::None),
+    ("no-self", Arg::None),
+    ("paf-no-hit", Arg::None),
+    ("pairing", Arg::Required),
+    ("pass1", Arg::Required),
+    ("print-aln-seq", Arg::None),
+    ("print-chains", Arg::None),
+    ("print-qname", Arg::None),
+    ("print-seeds", Arg::None),
+    ("q-occ-frac", Arg::Required),
+    ("qstrand", Arg::None),
+    ("rev-only", Arg::None),
+    ("rmq", Arg::Optional),
+    ("rmq-inner", Arg::Required),
+    ("sam", Arg::None),
+    ("sam-hit-only", Arg::None),
+    ("score-N", Arg::Required),
+    ("secondary", Arg::Required),
+    ("secondary-seq", Arg::None),
+    ("seed", Arg::Required),
+    ("splice", Arg::None),
+    ("splice-flank", Arg::Required),
+    ("split-prefix", Arg::Required),
+    ("spsc", Arg::Required),
+    ("sr", Arg::Optional),
+    ("version", Arg::None),
+    ("write-junc", Arg::None),
+];
+
+/// Errors from [`Aligner::from_cli_args`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliError {
+    /// Not a minimap2 option
+    UnknownOption(String),
+    /// A minimap2 option that has no library equivalent
+    Unsupported {
+        option: String,
+        reason: &'static str,
+    },
+    /// The option requires a value but none was given
+    MissingValue(String),
+    /// The value could not be parsed for this option
+    InvalidValue { option: String, value: String },
+    /// `-x` named a preset minimap2 does not know
+    UnknownPreset(String),
+    /// A positional argument (target or query file)
+    UnexpectedArgument(String),
+    /// The options parsed but the combination is invalid
+    Invalid(OptionError),
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::UnknownOption(option) => write!(f, "Unknown option {option}"),
+            CliError::Unsupported { option, reason } => {
+                write!(f, "Option {option} is not supported: {reason}")
+            }
+            CliError::MissingValue(option) => write!(f, "Option {option} requires a value"),
+            CliError::InvalidValue { option, value } => {
+                write!(f, "Invalid value '{value}' for option {option}")
+            }
+            CliError::UnknownPreset(preset) => write!(f, "Unknown preset '{preset}'"),
+            CliError::UnexpectedArgument(arg) => write!(
+                f,
+                "Unexpected argument '{arg}': target and query files are not part of the options"
+            ),
+            CliError::Invalid(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CliError::Invalid(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<OptionError> for CliError {
+    fn from(e: OptionError) -> Self {
+        CliError::Invalid(e)
+    }
+}
+
+/// A single parsed option, `name` without its leading dashes
+struct Opt<'a> {
+    name: &'a str,
+    long: bool,
+    value: Option<&'a str>,
+}
+
+impl Opt<'_> {
+    fn display(&self) -> String {
+        if self.long {
+            format!("--{}", self.name)
+        } else {
+            format!("-{}", self.name)
+        }
+    }
+
+    fn invalid(&self) -> CliError {
+        CliError::InvalidValue {
+            option: self.display(),
+            value: self.value.unwrap_or_default().to_string(),
+        }
+    }
+
+    fn unsupported(&self, reason: &'static str) -> CliError {
+        CliError::Unsupported {
+            option: self.display(),
+            reason,
+        }
+    }
+
+    fn str(&self) -> &str {
+        self.value.unwrap_or_default()
+    }
+
+    fn parse<T: std::str::FromStr>(&self) -> Result<T, CliError> {
+        self.str().parse().map_err(|_| self.invalid())
+    }
+
+    /// A size with an optional K/M/G suffix, like `mm_parse_num`
+    fn num(&self) -> Result<i64, CliError> {
+        parse_num(self.str()).ok_or_else(|| self.invalid())
+    }
+
+    /// `first[,second]`, each side parsed with `parse`
+    fn pair<T>(&self, parse: impl Fn(&str) -> Option<T>) -> Result<(T, Option<T>), CliError> {
+        let mut parts = self.str().splitn(2, ',');
+        let first = parse(parts.next().unwrap_or_default()).ok_or_else(|| self.invalid())?;
+        let second = match parts.next() {
+            Some(s) => Some(parse(s).ok_or_else(|| self.invalid())?),
+            None => None,
+        };
+        Ok((first, second))
+    }
+
+    /// `yes`/`no` switches; a missing optional value means yes
+    fn yes(&self) -> Result<bool, CliError> {
+        match self.value {
+            None | Some("yes") | Some("y") => Ok(true),
+            Some("no") | Some("n") => Ok(false),
+            Some(_) => Err(self.invalid()),
+        }
+    }
+}
+
+/// Parse a number with an optional K/M/G suffix, rounding like minimap2's `mm_parse_num`
+fn parse_num(s: &str) -> Option<i64> {
+    let (digits, scale) = match s.chars().last()? {
+        'G' | 'g' => (&s[..s.len() - 1], 1e9),
+        'M' | 'm' => (&s[..s.len() - 1], 1e6),
+        'K' | 'k' => (&s[..s.len() - 1], 1e3),
+        _ => (s, 1.0),
+    };
+    let x: f64 = digits.parse().ok()?;
+    Some((x * scale + 0.499) as i64)
+}
+
+/// Split the arguments into options, following `ketopt`'s rules
+fn tokenize<'a>(args: &[&'a str]) -> Result<Vec<Opt<'a>>, CliError> {
+    let mut opts = Vec::new();
+    let mut args = args.iter().copied();
+
+    while let Some(arg) = args.next() {
+        if let Some(long) = arg.strip_prefix("--") {
+            if long.is_empty() {
+                // Everything after "--" is positional
+                return match args.next() {
+                    Some(arg) => Err(CliError::UnexpectedArgument(arg.to_string())),
+                    None => Ok(opts),
+                };
+            }
+            let (name, inline) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let kind = LONG_OPTIONS
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, kind)| *kind)
+                .ok_or_else(|| CliError::UnknownOption(format!("--{name}")))?;
+            let value = match kind {
+                Arg::None if inline.is_some() => {
+                    return Err(CliError::InvalidValue {
+                        option: format!("--{name}"),
+                        value: inline.unwrap().to_string(),
+                    });
+                }
+                Arg::None | Arg::Optional => inline,
+                Arg::Required => match inline {
+                    Some(value) => Some(value),
+                    None => Some(
+                        args.next()
+                            .ok_or_else(|| CliError::MissingValue(format!("--{name}")))?,
+                    ),
+                },
+            };
+            opts.push(Opt {
+                name,
+                long: true,
+                value,
+            });
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            let cluster = &arg[1..];
+            for (i, c) in cluster.char_indices() {
+                let name = &cluster[i..i + c.len_utf8()];
+                if SHORT_FLAGS.contains(c) {
+                    opts.push(Opt {
+                        name,
+                        long: false,
+                        value: None,
+                    });
+                } else if SHORT_WITH_VALUE.contains(c) {
+                    let rest = &cluster[i + c.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next()
+                            .ok_or_else(|| CliError::MissingValue(format!("-{name}")))?
+                    } else {
+                        rest
+                    };
+                    opts.push(Opt {
+                        name,
+                        long: false,
+                        value: Some(value),
+                    });
+                    break;
+                } else {
+                    return Err(CliError::UnknownOption(format!("-{name}")));
+                }
+            }
+        } else {
+            return Err(CliError::UnexpectedArgument(arg.to_string()));
+        }
+    }
+
+    Ok(opts)
+}
+
+fn set_flag(flag: &mut i64, bits: u64, on: bool) {
+    if on {
+        *flag |= bits as i64;
+    } else {
+        *flag &= !(bits as i64);
+    }
+}
+
+/// Apply one option the way `main.c` does
+fn apply(aligner: &mut Aligner<PresetSet>, opt: &Opt) -> Result<(), CliError> {
+    let idxopt = &mut aligner.idxopt;
+    let mapopt = &mut aligner.mapopt;
+    let flag = &mut mapopt.flag;
+
+    if !opt.long {
+        match opt.name {
+            // Indexing
+            "k" => idxopt.k = opt.parse()?,
+            "w" => idxopt.w = opt.parse()?,
+            "H" => idxopt.flag |= MM_I_HPC as i16,
+            "I" => idxopt.batch_size = opt.num()? as u64,
+            "t" => aligner.threads = opt.parse()?,
+
+            // Mapping
+            "K" => mapopt.mini_batch_size = opt.num()?,
+            "f" => {
+                let (occ, max_occ) = opt.pair(|s| s.parse::<f64>().ok())?;
+                if occ < 1.0 {
+                    mapopt.mid_occ_frac = occ as f32;
+                    mapopt.mid_occ = 0;
+                } else {
+                    mapopt.mid_occ = (occ + 0.499) as i32;
+                }
+                if let Some(max_occ) = max_occ {
+                    mapopt.max_occ = (max_occ + 0.499) as i32;
+                }
+            }
+            "U" => {
+                let (min, max) = opt.pair(|s| s.parse::<i32>().ok())?;
+                mapopt.min_mid_occ = min;
+                if let Some(max) = max {
+                    mapopt.max_mid_occ = max;
+                }
+            }
+            "g" => mapopt.max_gap = opt.num()? as i32,
+            "G" => unsafe { mm_mapopt_max_intron_len(mapopt, opt.num()? as i32) },
+            "F" => mapopt.max_frag_len = opt.num()? as i32,
+            "r" => {
+                let (bw, bw_long) = opt.pair(parse_num)?;
+                mapopt.bw = bw as i32;
+                if let Some(bw_long) = bw_long {
+                    mapopt.bw_long = bw_long as i32;
+                }
+            }
+            "n" => mapopt.min_cnt = opt.parse()?,
+            "m" => mapopt.min_chain_score = opt.parse()?,
+            "p" => mapopt.pri_ratio = opt.parse()?,
+            "N" => mapopt.best_n = opt.parse()?,
+            "M" => mapopt.mask_level = opt.parse()?,
+            "e" => mapopt.occ_dist = opt.num()? as i32,
+            "D" => set_flag(flag, MM_F_NO_DIAG as u64, true),
+            "P" => set_flag(flag, MM_F_ALL_CHAINS as u64, true),
+            "X" => set_flag(
+                flag,
+                (MM_F_ALL_CHAINS | MM_F_NO_DIAG | MM_F_NO_DUAL | MM_F_NO_LJOIN) as u64,
+                true,
+            ),
+            "T" => mapopt.sdust_thres = opt.parse()?,
+
+            // Alignment
+            "A" => mapopt.a = opt.parse()?,
+            "B" => mapopt.b = opt.parse()?,
+            "b" => mapopt.transition = opt.parse()?,
+            "O" => {
+                let (q, q2) = opt.pair(|s| s.parse::<i32>().ok())?;
+                mapopt.q = q;
+                mapopt.q2 = q2.unwrap_or(q);
+            }
+            "E" => {
+                let (e, e2) = opt.pair(|s| s.parse::<i32>().ok())?;
+                mapopt.e = e;
+                mapopt.e2 = e2.unwrap_or(e);
+            }
+            "z" => {
+                let (zdrop, zdrop_inv) = opt.pair(|s| s.parse::<i32>().ok())?;
+                mapopt.zdrop = zdrop;
+                mapopt.zdrop_inv = zdrop_inv.unwrap_or(zdrop);
+            }
+            "s" => mapopt.min_dp_max = opt.parse()?,
+            "u" => match opt.str() {
+                "b" => set_flag(flag, (MM_F_SPLICE_FOR | MM_F_SPLICE_REV) as u64, true),
+                "f" => {
+                    set_flag(flag, MM_F_SPLICE_FOR as u64, true);
+                    set_flag(flag, MM_F_SPLICE_REV as u64, false);
+                }
+                "r" => {
+                    set_flag(flag, MM_F_SPLICE_REV as u64, true);
+                    set_flag(flag, MM_F_SPLICE_FOR as u64, false);
+                }
+                "n" => set_flag(flag, (MM_F_SPLICE_FOR | MM_F_SPLICE_REV) as u64, false),
+                _ => return Err(opt.invalid()),
+            },
+            "C" => mapopt.noncan = opt.parse()?,
+            "J" => match opt.str() {
+                "0" => set_flag(flag, MM_F_SPLICE_OLD, true),
+                "1" => set_flag(flag, MM_F_SPLICE_OLD, false),
+                _ => return Err(opt.invalid()),
+            },
+
+            // Output
+            "a" => set_flag(flag, (MM_F_OUT_SAM | MM_F_CIGAR) as u64, true),
+            "c" => set_flag(flag, (MM_F_OUT_CG | MM_F_CIGAR) as u64, true),
+            "S" => set_flag(
+                flag,
+                (MM_F_OUT_CS | MM_F_CIGAR | MM_F_OUT_CS_LONG) as u64,
+                true,
+            ),
+            "Q" => set_flag(flag, MM_F_NO_QUAL as u64, true),
+            "L" => set_flag(flag, MM_F_LONG_CIGAR as u64, true),
+            "Y" => set_flag(flag, MM_F_SOFTCLIP as u64, true),
+            "y" => set_flag(flag, MM_F_COPY_COMMENT as u64, true),
+            "2" => set_flag(flag, MM_F_2_IO_THREADS as u64, true),
+
+            "d" => return Err(opt.unsupported("pass the output path to with_index()")),
+            "o" => return Err(opt.unsupported("output is written by the caller")),
+            // Kept by the caller, see read_group()
+            "R" => {
+                ReadGroup::parse(opt.str()).map_err(|_| opt.invalid())?;
+            }
+            "j" => return Err(opt.unsupported("load jumps with read_junction()")),
+            "v" => return Err(opt.unsupported("verbosity is a process-wide setting")),
+            "V" | "h" => return Err(opt.unsupported("not a mapping option")),
+            _ => return Err(CliError::UnknownOption(opt.display())),
+        }
+        return Ok(());
+    }
+
+    match opt.name {
+        // Indexing
+        "bucket-bits" => idxopt.bucket_bits = opt.parse()?,
+        "idx-no-seq" => idxopt.flag |= MM_I_NO_SEQ as i16,
+
+        // Mapping
+        "seed" => mapopt.seed = opt.parse()?,
+        "mb-size" => mapopt.mini_batch_size = opt.num()?,
+        "max-qlen" => mapopt.max_qlen = opt.num()? as i32,
+        "max-intron-len" => unsafe { mm_mapopt_max_intron_len(mapopt, opt.num()? as i32) },
+        "min-count" => mapopt.min_cnt = opt.parse()?,
+        "min-chain-score" => mapopt.min_chain_score = opt.parse()?,
+        "mask-level" => mapopt.mask_level = opt.parse()?,
+        "no-self" => set_flag(flag, MM_F_NO_DIAG as u64, true),
+        "max-chain-skip" => mapopt.max_chain_skip = opt.parse()?,
+        "max-chain-iter" => mapopt.max_chain_iter = opt.parse()?,
+        "chain-gap-scale" => mapopt.chain_gap_scale = opt.parse()?,
+        "chain-skip-scale" => mapopt.chain_skip_scale = opt.parse()?,
+        "min-occ-floor" => mapopt.min_mid_occ = opt.parse()?,
+        "q-occ-frac" => mapopt.q_occ_frac = opt.parse()?,
+        "mask-len" => mapopt.mask_len = opt.num()? as i32,
+        "alt-drop" => mapopt.alt_drop = opt.parse()?,
+        "rmq" => set_flag(flag, MM_F_RMQ as u64, opt.yes()?),
+        "rmq-inner" => mapopt.rmq_inner_dist = opt.num()? as i32,
+        "hard-mask-level" => set_flag(flag, MM_F_HARD_MLEVEL as u64, true),
+        "no-long-join" => set_flag(flag, MM_F_NO_LJOIN as u64, true),
+        "all-chain" => set_flag(flag, MM_F_ALL_CHAINS as u64, true),
+        "dual" => set_flag(flag, MM_F_NO_DUAL as u64, !opt.yes()?),
+        "heap-sort" => set_flag(flag, MM_F_HEAP_SORT as u64, opt.yes()?),
+        "for-only" => set_flag(flag, MM_F_FOR_ONLY as u64, true),
+        "rev-only" => set_flag(flag, MM_F_REV_ONLY as u64, true),
+        "qstrand" => set_flag(flag, MM_F_QSTRAND | MM_F_NO_INV, true),
+        "no-hash-name" => set_flag(flag, MM_F_NO_HASH_NAME, true),
+        "sr" => match opt.value {
+            None | Some("dna") => set_flag(flag, MM_F_SR as u64, true),
+            Some("rna") => set_flag(flag, MM_F_SR_RNA, true),
+            Some("no") => set_flag(flag, MM_F_SR as u64 | MM_F_SR_RNA, false),
+            Some(_) => return Err(opt.invalid()),
+        },
+        "frag" => set_flag(flag, MM_F_FRAG_MODE as u64, opt.yes()?),
+        "no-pairing" => set_flag(flag, MM_F_INDEPEND_SEG as u64, true),
+        "pairing" => match opt.str() {
+            "no" => set_flag(flag, MM_F_INDEPEND_SEG as u64, true),
+            "weak" => {
+                set_flag(flag, MM_F_WEAK_PAIRING, true);
+                set_flag(flag, MM_F_INDEPEND_SEG as u64, false);
+            }
+            "strong" => set_flag(flag, MM_F_INDEPEND_SEG as u64 | MM_F_WEAK_PAIRING, false),
+            _ => return Err(opt.invalid()),
+        },
+        "lj-min-ratio" => {} // deprecated in minimap2 and ignored
+        "jump-min-match" => mapopt.jump_min_match = opt.parse()?,
+
+        // Alignment
+        "splice" => set_flag(flag, MM_F_SPLICE as u64, true),
+        "splice-flank" => set_flag(flag, MM_F_SPLICE_FLANK as u64, opt.yes()?),
+        "cost-non-gt-ag" => mapopt.noncan = opt.parse()?,
+        "junc-bonus" => mapopt.junc_bonus = opt.parse()?,
+        "junc-pen" => mapopt.junc_pen = opt.parse()?,
+        "end-bonus" => mapopt.end_bonus = opt.parse()?,
+        "end-seed-pen" => mapopt.anchor_ext_shift = opt.parse()?,
+        "score-N" => mapopt.sc_ambi = opt.parse()?,
+        "min-dp-len" => mapopt.min_ksw_len = opt.parse()?,
+        "min-dp-score" => mapopt.min_dp_max = opt.parse()?,
+        "max-clip-ratio" => mapopt.max_clip_ratio = opt.parse()?,
+        "no-end-flt" => set_flag(flag, MM_F_NO_END_FLT as u64, true),
+        "cap-sw-mem" => mapopt.max_sw_mat = opt.num()?,
+        "cap-kalloc" => mapopt.cap_kalloc = opt.num()?,
+
+        // Output
+        "cs" => {
+            set_flag(flag, (MM_F_OUT_CS | MM_F_CIGAR) as u64, true);
+            match opt.value {
+                None | Some("short") => set_flag(flag, MM_F_OUT_CS_LONG as u64, false),
+                Some("long") => set_flag(flag, MM_F_OUT_CS_LONG as u64, true),
+                Some("none") => set_flag(flag, MM_F_OUT_CS as u64, false),
+                Some(_) => return Err(opt.invalid()),
+            }
+        }
+        "sam" => set_flag(flag, (MM_F_OUT_SAM | MM_F_CIGAR) as u64, true),
+        "MD" => set_flag(flag, MM_F_OUT_MD as u64, true),
+        "ds" => set_flag(flag, MM_F_OUT_DS, true),
+        "eqx" => set_flag(flag, MM_F_EQX as u64, true),
+        "secondary" => set_flag(flag, MM_F_NO_PRINT_2ND as u64, !opt.yes()?),
+        "secondary-seq" => set_flag(flag, MM_F_SECONDARY_SEQ, true),
+        "paf-no-hit" => set_flag(flag, MM_F_PAF_NO_HIT as u64, true),
+        "sam-hit-only" => set_flag(flag, MM_F_SAM_HIT_ONLY as u64, true),
+        "write-junc" => set_flag(flag, MM_F_OUT_JUNC | MM_F_CIGAR as u64, true),
+
+        "alt" => return Err(opt.unsupported("load ALT contigs with read_alt_contigs()")),
+        "junc-bed" => return Err(opt.unsupported("load junctions with read_junction_lr()")),
+        "pass1" => return Err(opt.unsupported("load junctions with read_pass1()")),
+        "spsc" => return Err(opt.unsupported("load splice scores with read_splice_scores()")),
+        "split-prefix" => return Err(opt.unsupported("split index merging is not supported")),
+        "no-kalloc" | "print-qname" | "print-seeds" | "print-aln-seq" | "print-chains"
+        | "dbg-seed-occ" => {
+            return Err(opt.unsupported("debugging output is not supported"));
+        }
+        "version" | "help" => return Err(opt.unsupported("not a mapping option")),
+        _ => return Err(CliError::UnknownOption(opt.display())),
+    }
+    Ok(())
+}
+
+/// The read group given with `-R`, e.g. `-R '@RG\tID:rg1\tSM:sample1'`
+///
+/// The aligner built by [`Aligner::from_cli_args`] does not keep it: pass it to
+/// `sam_header()` and `map_query_to_sam()` (or `SortedBamWriter::with_read_group()`) with
+/// the `htslib` feature, or to the `noodles` functions, to write `@RG` and tag records.
+///
+/// ```
+/// let rg = minimap2::cli::read_group(&["-ax", "sr", "-R", "@RG\\tID:rg1\\tSM:s1"]).unwrap();
+/// assert_eq!(rg.unwrap().sample.as_deref(), Some("s1"));
+/// ```
+pub fn read_group(args: &[&str]) -> Result<Option<ReadGroup>, CliError> {
+    let mut read_group = None;
+    for opt in tokenize(args)? {
+        if !opt.long && opt.name == "R" {
+            read_group = Some(ReadGroup::parse(opt.str()).map_err(|_| opt.invalid())?);
+        }
+    }
+    Ok(read_group)
+}
+
+impl Aligner<()> {
+    /// Create an aligner from minimap2 command line options, e.g. `-x map-ont -k17 -c`
+    ///
+    /// Presets (`-x`) are applied first, the remaining options in order, and the result is
+    /// validated. Only options are accepted: target and query files, output and debugging
+    /// options return a [`CliError`]. See the [`cli`](crate::cli) module for details.
+    ///
+    /// ```
+    /// # use minimap2::*;
+    /// let aligner = Aligner::from_cli_args(&["-ax", "sr", "-r", "500,20k"]).unwrap();
+    /// assert_eq!(aligner.mapopt.bw_long, 20_000);
+    /// ```
+    pub fn from_cli_args(args: &[&str]) -> Result<Aligner<PresetSet>, CliError> {
+        let opts = tokenize(args)?;
+
+        let presets = opts.iter().filter(|o| !o.long && o.name == "x");
+        let mut aligner =
+            Aligner::from_presets(presets.map(Opt::str)).map_err(CliError::UnknownPreset)?;
+
+        let mut old_best_n = aligner.mapopt.best_n;
+        for opt in opts.iter().filter(|o| o.long || o.name != "x") {
+            if !opt.long && opt.name == "N" {
+                old_best_n = aligner.mapopt.best_n;
+            }
+            apply(&mut aligner, opt)?;
+        }
+
+        aligner.validate_opts()?;
+        // minimap2 turns `-N 0` into the previous -N and `--secondary=no`
+        if aligner.mapopt.best_n == 0 {
+            aligner.mapopt.best_n = old_best_n;
+            aligner.mapopt.flag |= MM_F_NO_PRINT_2ND as i64;
+        }
+        Ok(aligner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn has(aligner: &Aligner<PresetSet>, bits: u64) -> bool {
+        aligner.mapopt.flag as u64 & bits == bits
+    }
+
+    #[test]
+    fn matches_builder() {
+        let parsed = Aligner::from_cli_args(&[
+            "-x",
+            "map-ont",
+            "-k",
+            "17",
+            "-w",
+            "11",
+            "--secondary=no",
+            "-N",
+            "5",
+            "-c",
+            "--cs",
+        ])
+        .unwrap();
+
+        let mut built = Aligner::builder().map_ont().with_cigar();
+        built.idxopt.k = 17;
+        built.idxopt.w = 11;
+        built.mapopt.best_n = 5;
+        built.mapopt.flag |= (MM_F_NO_PRINT_2ND | MM_F_OUT_CG) as i64;
+
+        assert_eq!(parsed.idxopt.k, built.idxopt.k);
+        assert_eq!(parsed.idxopt.w, built.idxopt.w);
+        assert_eq!(parsed.mapopt.best_n, built.mapopt.best_n);
+        assert_eq!(parsed.mapopt.flag, built.mapopt.flag);
+        assert_eq!(parsed.mapopt.bw, built.mapopt.bw);
+        assert_eq!(parsed.mapopt.min_chain_score, built.mapopt.min_chain_score);
+    }
+
+    #[test]
+    fn clustered_and_suffixed() {
+        let aligner = Aligner::from_cli_args(&[
+            "-ax",
+            "sr",
+            "-k21",
+            "-r",
+            "500,20k",
+            "-I4G",
+            "-t",
+            "4",
+            "-O4,24",
+            "-f",
+            "1000,5000",
+        ])
+        .unwrap();
+
+        assert!(has(&aligner, (MM_F_OUT_SAM | MM_F_CIGAR | MM_F_SR) as u64));
+        assert_eq!(aligner.idxopt.k, 21);
+        assert_eq!(aligner.mapopt.bw, 500);
+        assert_eq!(aligner.mapopt.bw_long, 20_000);
+        assert_eq!(aligner.idxopt.batch_size, 4_000_000_000);
+        assert_eq!(aligner.threads, 4);
+        assert_eq!((aligner.mapopt.q, aligner.mapopt.q2), (4, 24));
+        assert_eq!(
+            (aligner.mapopt.mid_occ, aligner.mapopt.max_occ),
+            (1000, 5000)
+        );
+
+        let aligner = Aligner::from_cli_args(&["-x", "map-hifi", "-f", "0.0002"]).unwrap();
+        assert_eq!(aligner.mapopt.mid_occ_frac, 0.0002);
+        assert_eq!(aligner.mapopt.mid_occ, 0);
+    }
+
+    #[test]
+    fn splice_options() {
+        let aligner =
+            Aligner::from_cli_args(&["-x", "splice", "-uf", "--splice-flank=no", "-G", "200k"])
+                .unwrap();
+        assert!(has(&aligner, MM_F_SPLICE_FOR as u64));
+        assert!(!has(&aligner, MM_F_SPLICE_REV as u64));
+        assert!(!has(&aligner, MM_F_SPLICE_FLANK as u64));
+        assert_eq!(aligner.mapopt.max_gap_ref, 200_000);
+
+        let aligner = Aligner::from_cli_args(&["-x", "splice", "--cs=long"]).unwrap();
+        assert!(has(&aligner, (MM_F_OUT_CS | MM_F_OUT_CS_LONG) as u64));
+    }
+
+    #[test]
+    fn no_preset_uses_defaults() {
+        let aligner = Aligner::from_cli_args(&[]).unwrap();
+        let default = Aligner::builder();
+        assert_eq!(aligner.idxopt.k, default.idxopt.k);
+        assert_eq!(aligner.mapopt.flag, default.mapopt.flag);
+    }
+
+    #[test]
+    fn errors() {
+        let err = |args: &[&str]| Aligner::from_cli_args(args).err().unwrap();
+
+        assert!(
+            matches!(err(&["-o", "out.paf"]), CliError::Unsupported { option, .. } if option == "-o")
+        );
+        assert!(matches!(
+            err(&["--junc-bed", "a.bed"]),
+            CliError::Unsupported { .. }
+        ));
+        assert_eq!(err(&["--foo"]), CliError::UnknownOption("--foo".into()));
+        assert_eq!(err(&["-Z"]), CliError::UnknownOption("-Z".into()));
+        assert_eq!(err(&["-k"]), CliError::MissingValue("-k".into()));
+        assert_eq!(
+            err(&["-k", "abc"]),
+            CliError::InvalidValue {
+                option: "-k".into(),
+                value: "abc".into()
+            }
+        );
+        assert_eq!(
+            err(&["-x", "map-foo"]),
+            CliError::UnknownPreset("map-foo".into())
+        );
+        assert_eq!(
+            err(&["-x", "map-ont", "ref.fa"]),
+            CliError::UnexpectedArgument("ref.fa".into())
+        );
+        assert!(matches!(
+            err(&["--secondary=maybe"]),
+            CliError::InvalidValue { .. }
+        ));
+
+        match err(&["-x", "map-ont", "-r", "600,500"]) {
+            CliError::Invalid(e) => assert_eq!(e.flag, Some("-r")),
+            e => panic!("unexpected error {e}"),
+        }
+    }
+
+    #[test]
+    fn read_group_option() {
+        let args = ["-x", "map-ont", "-R", "@RG\\tID:rg1\\tSM:sample1", "-c"];
+        let aligner = Aligner::from_cli_args(&args).unwrap();
+        assert!(has(&aligner, MM_F_OUT_CG as u64));
+        assert_eq!(
+            read_group(&args).unwrap(),
+            Some(ReadGroup::new("rg1").with_sample("sample1"))
+        );
+        assert_eq!(read_group(&["-x", "map-ont"]).unwrap(), None);
+
+        let args = ["-R", "ID:rg1"];
+        let invalid = CliError::InvalidValue {
+            option: "-R".into(),
+            value: "ID:rg1".into(),
+        };
+        assert_eq!(Aligner::from_cli_args(&args).err().unwrap(), invalid);
+        assert_eq!(read_group(&args).err().unwrap(), invalid);
+    }
+
+    /// `opt_str` from minimap2 2.30's `main.c`
+    const MAIN_OPT_STR: &str =
+        "2aSDw:k:K:t:r:f:Vv:g:G:I:d:XT:s:x:Hcp:M:n:z:A:B:b:O:E:m:N:Qu:R:hF:LC:yYPo:e:U:J:j:";
+
+    /// `long_options` from minimap2 2.30's `main.c`
+    const MAIN_LONG_OPTIONS: &[(&str, Arg)] = &[
+        ("bucket-bits", Arg::Required),
+        ("mb-size", Arg::Required),
+        ("seed", Arg::Required),
+        ("no-kalloc", Arg::None),
+        ("print-qname", Arg::None),
+        ("no-self", Arg::None),
+        ("print-seeds", Arg::None),
+        ("max-chain-skip", Arg::Required),
+        ("min-dp-len", Arg::Required),
+        ("print-aln-seq", Arg::None),
+        ("splice", Arg::None),
+        ("cost-non-gt-ag", Arg::Required),
+        ("no-long-join", Arg::None),
+        ("sr", Arg::Optional),
+        ("frag", Arg::Required),
+        ("secondary", Arg::Required),
+        ("cs", Arg::Optional),
+        ("end-bonus", Arg::Required),
+        ("no-pairing", Arg::None),
+        ("splice-flank", Arg::Required),
+        ("idx-no-seq", Arg::None),
+        ("end-seed-pen", Arg::Required),
+        ("for-only", Arg::None),
+        ("rev-only", Arg::None),
+        ("heap-sort", Arg::Required),
+        ("all-chain", Arg::None),
+        ("dual", Arg::Required),
+        ("max-clip-ratio", Arg::Required),
+        ("min-occ-floor", Arg::Required),
+        ("MD", Arg::None),
+        ("lj-min-ratio", Arg::Required),
+        ("score-N", Arg::Required),
+        ("eqx", Arg::None),
+        ("paf-no-hit", Arg::None),
+        ("split-prefix", Arg::Required),
+        ("no-end-flt", Arg::None),
+        ("hard-mask-level", Arg::None),
+        ("cap-sw-mem", Arg::Required),
+        ("max-qlen", Arg::Required),
+        ("max-chain-iter", Arg::Required),
+        ("junc-bed", Arg::Required),
+        ("junc-bonus", Arg::Required),
+        ("sam-hit-only", Arg::None),
+        ("chain-gap-scale", Arg::Required),
+        ("alt", Arg::Required),
+        ("alt-drop", Arg::Required),
+        ("mask-len", Arg::Required),
+        ("rmq", Arg::Optional),
+        ("qstrand", Arg::None),
+        ("cap-kalloc", Arg::Required),
+        ("q-occ-frac", Arg::Required),
+        ("chain-skip-scale", Arg::Required),
+        ("print-chains", Arg::None),
+        ("no-hash-name", Arg::None),
+        ("secondary-seq", Arg::None),
+        ("ds", Arg::None),
+        ("rmq-inner", Arg::Required),
+        ("spsc", Arg::Required),
+        ("junc-pen", Arg::Required),
+        ("pairing", Arg::Required),
+        ("jump-min-match", Arg::Required),
+        ("write-junc", Arg::None),
+        ("pass1", Arg::Required),
+        ("dbg-seed-occ", Arg::None),
+        ("help", Arg::None),
+        ("max-intron-len", Arg::Required),
+        ("version", Arg::None),
+        ("min-count", Arg::Required),
+        ("min-chain-score", Arg::Required),
+        ("mask-level", Arg::Required),
+        ("min-dp-score", Arg::Required),
+        ("sam", Arg::None),
+    ];
+
+    #[test]
+    fn options_match_main_c() {
+        let mut n_short = 0;
+        let mut chars = MAIN_OPT_STR.chars().peekable();
+        while let Some(c) = chars.next() {
+            let with_value = chars.next_if_eq(&':').is_some();
+            assert_eq!(SHORT_WITH_VALUE.contains(c), with_value, "-{c}");
+            assert_eq!(SHORT_FLAGS.contains(c), !with_value, "-{c}");
+            n_short += 1;
+
+            // Every option is either applied or rejected with a reason
+            let arg = format!("-{c}");
+            let args: &[&str] = if with_value { &[&arg, "1"] } else { &[&arg] };
+            if let Err(CliError::UnknownOption(e)) = Aligner::from_cli_args(args) {
+                panic!("{e} is not handled");
+            }
+        }
+        assert_eq!(SHORT_WITH_VALUE.len() + SHORT_FLAGS.len(), n_short);
+
+        assert_eq!(LONG_OPTIONS.len(), MAIN_LONG_OPTIONS.len());
+        for &(name, kind) in MAIN_LONG_OPTIONS {
+            assert!(LONG_OPTIONS.contains(&(name, kind)), "--{name}");
+
+            let arg = format!("--{name}");
+            let args: &[&str] = match kind {
+                Arg::Required => &[&arg, "1"],
+                _ => &[&arg],
+            };
+            if let Err(CliError::UnknownOption(e)) = Aligner::from_cli_args(args) {
+                panic!("{e} is not handled");
+            }
+        }
+    }
+
+    #[test]
+    fn options_set_main_c_flags() {
+        let cases: &[(&[&str], u64)] = &[
+            (&["--qstrand"], MM_F_QSTRAND | MM_F_NO_INV),
+            (
+                &["-S"],
+                (MM_F_OUT_CS | MM_F_CIGAR | MM_F_OUT_CS_LONG) as u64,
+            ),
+            (&["--sr"], MM_F_SR as u64),
+            (&["--sr=dna"], MM_F_SR as u64),
+            (&["--sr=rna"], MM_F_SR_RNA),
+            (&["--pairing", "no"], MM_F_INDEPEND_SEG as u64),
+            (&["--pairing", "weak"], MM_F_WEAK_PAIRING),
+            (&["--no-self"], MM_F_NO_DIAG as u64),
+            (&["--sam"], (MM_F_OUT_SAM | MM_F_CIGAR) as u64),
+            (&["--write-junc"], MM_F_OUT_JUNC | MM_F_CIGAR as u64),
+            (&["-N", "0"], MM_F_NO_PRINT_2ND as u64),
+        ];
+        for (args, bits) in cases {
+            let aligner = Aligner::from_cli_args(args).unwrap();
+            assert!(has(&aligner, *bits), "{args:?}");
+        }
+
+        let aligner = Aligner::from_cli_args(&["-x", "sr", "--sr=no"]).unwrap();
+        assert!(!has(&aligner, MM_F_SR as u64));
+        let aligner = Aligner::from_cli_args(&["--pairing=weak", "--pairing", "strong"]).unwrap();
+        assert!(!has(&aligner, MM_F_WEAK_PAIRING));
+        assert!(!has(&aligner, MM_F_INDEPEND_SEG as u64));
+
+        let aligner = Aligner::from_cli_args(&[
+            "-e",
+            "1k",
+            "--rmq-inner",
+            "2k",
+            "--min-count",
+            "4",
+            "--min-chain-score",
+            "50",
+            "--mask-level",
+            "0.6",
+            "--min-dp-score",
+            "60",
+            "--lj-min-ratio",
+            "0.5",
+        ])
+        .unwrap();
+        assert_eq!(aligner.mapopt.occ_dist, 1000);
+        assert_eq!(aligner.mapopt.rmq_inner_dist, 2000);
+        assert_eq!(aligner.mapopt.min_cnt, 4);
+        assert_eq!(aligner.mapopt.min_chain_score, 50);
+        assert_eq!(aligner.mapopt.mask_level, 0.6);
+        assert_eq!(aligner.mapopt.min_dp_max, 60);
+
+        let aligner =
+            Aligner::from_cli_args(&["-x", "splice", "--max-intron-len", "100k"]).unwrap();
+        assert_eq!(aligner.mapopt.max_gap_ref, 100_000);
+
+        // -N 0 keeps the previous -N
+        let aligner = Aligner::from_cli_args(&["-N", "7", "-N", "0"]).unwrap();
+        assert_eq!(aligner.mapopt.best_n, 7);
+        assert!(has(&aligner, MM_F_NO_PRINT_2ND as u64));
+    }
+}
@@ -0,0 +1,678 @@
+//! Serializable aligner configuration, for recording how a mapping run was set up
+//!
+//! [`AlignerConfig`] captures the effective options of an [`Aligner`]: the preset chain,
+//! every `mm_idxopt_t` / `mm_mapopt_t` field, the flags decoded into names and the
+//! minimap2 version. It serializes with serde (with the `serde` feature), converts back
+//! into an aligner with [`AlignerConfig::to_aligner`], and renders an `@PG` header line
+//! whose `CL` reproduces the options with [`Aligner::from_cli_args`].
+//!
+//! ```
+//! use minimap2::Aligner;
+//! use minimap2::config::AlignerConfig;
+//!
+//! let aligner = Aligner::from_cli_args(&["-x", "map-ont", "-k", "17", "--secondary=no"]).unwrap();
+//! let config = aligner.config();
+//! assert_eq!(config.presets, ["map-ont"]);
+//! assert!(config.map.flags.contains(&"NO_PRINT_2ND".to_string()));
+//! assert_eq!(config.cli_args().unwrap(), ["-x", "map-ont", "-k", "17", "--secondary=no"]);
+//!
+//! let restored = config.to_aligner().unwrap();
+//! assert_eq!(restored.config(), config);
+//! ```
+
+use std::str::FromStr;
+
+use bitflags::Flags;
+use minimap2_sys::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::flags::{IdxFlags, MapFlags};
+use crate::options::OptionError;
+use crate::{Aligner, BuilderState, IdxOpt, MapOpt, PresetSet};
+
+/// Command line switches turning a mapping flag on and off, where minimap2 has them.
+/// `CIGAR`, `OUT_CS`/`OUT_CS_LONG`, `SPLICE_FOR`/`SPLICE_REV`, `SR`/`SR_RNA`,
+/// `INDEPEND_SEG`/`WEAK_PAIRING` and `QSTRAND`/`NO_INV` are rendered separately.
+const MAP_FLAG_ARGS: &[(MapFlags, Option<&str>, Option<&str>)] = &[
+    (MapFlags::NO_DIAG, Some("-D"), None),
+    (MapFlags::NO_DUAL, Some("--dual=no"), Some("--dual=yes")),
+    (MapFlags::OUT_SAM, Some("-a"), None),
+    (MapFlags::NO_QUAL, Some("-Q"), None),
+    (MapFlags::OUT_CG, Some("-c"), None),
+    (MapFlags::SPLICE, Some("--splice"), None),
+    (MapFlags::NO_LJOIN, Some("--no-long-join"), None),
+    (MapFlags::FRAG_MODE, Some("--frag=yes"), Some("--frag=no")),
+    (
+        MapFlags::NO_PRINT_2ND,
+        Some("--secondary=no"),
+        Some("--secondary=yes"),
+    ),
+    (MapFlags::TWO_IO_THREADS, Some("-2"), None),
+    (MapFlags::LONG_CIGAR, Some("-L"), None),
+    (
+        MapFlags::SPLICE_FLANK,
+        Some("--splice-flank=yes"),
+        Some("--splice-flank=no"),
+    ),
+    (MapFlags::SOFTCLIP, Some("-Y"), None),
+    (MapFlags::FOR_ONLY, Some("--for-only"), None),
+    (MapFlags::REV_ONLY, Some("--rev-only"), None),
+    (
+        MapFlags::HEAP_SORT,
+        Some("--heap-sort=yes"),
+        Some("--heap-sort=no"),
+    ),
+    (MapFlags::ALL_CHAINS, Some("-P"), None),
+    (MapFlags::OUT_MD, Some("--MD"), None),
+    (MapFlags::COPY_COMMENT, Some("-y"), None),
+    (MapFlags::EQX, Some("--eqx"), None),
+    (MapFlags::PAF_NO_HIT, Some("--paf-no-hit"), None),
+    (MapFlags::NO_END_FLT, Some("--no-end-flt"), None),
+    (MapFlags::HARD_MLEVEL, Some("--hard-mask-level"), None),
+    (MapFlags::SAM_HIT_ONLY, Some("--sam-hit-only"), None),
+    (MapFlags::RMQ, Some("--rmq=yes"), Some("--rmq=no")),
+    (MapFlags::NO_HASH_NAME, Some("--no-hash-name"), None),
+    (MapFlags::SPLICE_OLD, Some("-J0"), Some("-J1")),
+    (MapFlags::SECONDARY_SEQ, Some("--secondary-seq"), None),
+    (MapFlags::OUT_DS, Some("--ds"), None),
+    (MapFlags::OUT_JUNC, Some("--write-junc"), None),
+];
+
+/// Flag names as written by their `Display`; bits without a name are kept as hex
+fn flag_names<F: Flags + std::fmt::Display>(flags: F) -> Vec<String> {
+    flags
+        .to_string()
+        .split(" | ")
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_flags<F: Flags + FromStr>(flags: &[String]) -> Result<F, ConfigError> {
+    flags.iter().try_fold(F::empty(), |acc, name| {
+        let flag = name
+            .parse::<F>()
+            .map_err(|_| ConfigError::UnknownFlag(name.clone()))?;
+        Ok(acc.union(flag))
+    })
+}
+
+/// Errors converting an [`AlignerConfig`] back into an aligner
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// A preset minimap2 does not know
+    UnknownPreset(String),
+    /// A flag name that is neither known nor a hex value
+    UnknownFlag(String),
+    /// The options are invalid
+    Invalid(OptionError),
+    /// Flags minimap2's command line cannot set (or clear) on top of the presets
+    Unrepresentable(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::UnknownPreset(preset) => write!(f, "Unknown preset '{preset}'"),
+            ConfigError::UnknownFlag(flag) => write!(f, "Unknown flag '{flag}'"),
+            ConfigError::Invalid(e) => e.fmt(f),
+            ConfigError::Unrepresentable(flags) => {
+                write!(f, "No minimap2 command line option gives the flags {flags}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Invalid(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<OptionError> for ConfigError {
+    fn from(e: OptionError) -> Self {
+        ConfigError::Invalid(e)
+    }
+}
+
+// Mirror an option struct field by field, with `flag` decoded into names
+macro_rules! option_config {
+    ($(#[$meta:meta])* $name:ident, $opt:ty, $flags:ty, { $($field:ident: $ty:ty),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        pub struct $name {
+            /// `flag`, by name
+            pub flags: Vec<String>,
+            $(pub $field: $ty,)*
+        }
+
+        impl $name {
+            fn new(opt: &$opt) -> Self {
+                Self {
+                    flags: flag_names(<$flags>::of(opt)),
+                    $($field: opt.$field,)*
+                }
+            }
+
+            fn write(&self, opt: &mut $opt) -> Result<(), ConfigError> {
+                opt.flag = parse_flags::<$flags>(&self.flags)?.bits() as _;
+                $(opt.$field = self.$field;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+option_config!(
+    /// Every `mm_idxopt_t` field
+    IndexConfig, IdxOpt, IdxFlags, {
+        k: i16,
+        w: i16,
+        bucket_bits: i16,
+        mini_batch_size: i64,
+        batch_size: u64,
+    }
+);
+
+option_config!(
+    /// Every `mm_mapopt_t` field except `split_prefix`
+    MapConfig, MapOpt, MapFlags, {
+        seed: i32,
+        sdust_thres: i32,
+        max_qlen: i32,
+        bw: i32,
+        bw_long: i32,
+        max_gap: i32,
+        max_gap_ref: i32,
+        max_frag_len: i32,
+        max_chain_skip: i32,
+        max_chain_iter: i32,
+        min_cnt: i32,
+        min_chain_score: i32,
+        chain_gap_scale: f32,
+        chain_skip_scale: f32,
+        rmq_size_cap: i32,
+        rmq_inner_dist: i32,
+        rmq_rescue_size: i32,
+        rmq_rescue_ratio: f32,
+        mask_level: f32,
+        mask_len: i32,
+        pri_ratio: f32,
+        best_n: i32,
+        alt_drop: f32,
+        a: i32,
+        b: i32,
+        q: i32,
+        e: i32,
+        q2: i32,
+        e2: i32,
+        transition: i32,
+        sc_ambi: i32,
+        noncan: i32,
+        junc_bonus: i32,
+        junc_pen: i32,
+        zdrop: i32,
+        zdrop_inv: i32,
+        end_bonus: i32,
+        min_dp_max: i32,
+        min_ksw_len: i32,
+        anchor_ext_len: i32,
+        anchor_ext_shift: i32,
+        max_clip_ratio: f32,
+        rank_min_len: i32,
+        rank_frac: f32,
+        pe_ori: i32,
+        pe_bonus: i32,
+        jump_min_match: i32,
+        mid_occ_frac: f32,
+        q_occ_frac: f32,
+        min_mid_occ: i32,
+        max_mid_occ: i32,
+        mid_occ: i32,
+        max_occ: i32,
+        max_max_occ: i32,
+        occ_dist: i32,
+        mini_batch_size: i64,
+        max_sw_mat: i64,
+        cap_kalloc: i64,
+    }
+);
+
+/// The effective configuration of an aligner, with provenance
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AlignerConfig {
+    /// minimap2 version the options were produced with
+    pub minimap2_version: String,
+    /// Version of this crate
+    pub crate_version: String,
+    /// Presets, in the order they were applied
+    pub presets: Vec<String>,
+    /// Threads used for indexing
+    pub threads: usize,
+    /// Soft clipping in CIGAR strings, see [`Aligner::with_cigar_clipping`]
+    pub cigar_clipping: bool,
+    /// Indexing options
+    pub index: IndexConfig,
+    /// Mapping options
+    pub map: MapConfig,
+}
+
+impl AlignerConfig {
+    /// Capture the configuration of an aligner
+    pub fn new<S: BuilderState>(aligner: &Aligner<S>) -> Self {
+        AlignerConfig {
+            minimap2_version: MM_VERSION.to_string_lossy().into_owned(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            presets: aligner.presets().to_vec(),
+            threads: aligner.threads,
+            cigar_clipping: aligner.cigar_clipping,
+            index: IndexConfig::new(&aligner.idxopt),
+            map: MapConfig::new(&aligner.mapopt),
+        }
+    }
+
+    /// Rebuild an aligner with exactly these options; the index still has to be added
+    pub fn to_aligner(&self) -> Result<Aligner<PresetSet>, ConfigError> {
+        let mut aligner =
+            Aligner::from_presets(&self.presets).map_err(ConfigError::UnknownPreset)?;
+        self.index.write(&mut aligner.idxopt)?;
+        self.map.write(&mut aligner.mapopt)?;
+        aligner.threads = self.threads;
+        aligner.cigar_clipping = self.cigar_clipping;
+        aligner.validate_opts()?;
+        Ok(aligner)
+    }
+
+    /// Command line options for [`Aligner::from_cli_args`] (and minimap2) reproducing this
+    /// configuration: the presets, then every option that differs from them.
+    ///
+    /// Fields minimap2 has no command line option for (`rmq_size_cap`, `rank_*`, `pe_*`, ...)
+    /// and [`cigar_clipping`](Self::cigar_clipping) are only recorded in the config itself.
+    /// Flags that no combination of options reproduces (e.g. `NO_INV` without `QSTRAND`, or
+    /// clearing a flag a preset sets and minimap2 has no switch for) return
+    /// [`ConfigError::Unrepresentable`].
+    pub fn cli_args(&self) -> Result<Vec<String>, ConfigError> {
+        let base = Aligner::from_presets(&self.presets).map_err(ConfigError::UnknownPreset)?;
+        let (bi, bm) = (IndexConfig::new(&base.idxopt), MapConfig::new(&base.mapopt));
+        let (i, m) = (&self.index, &self.map);
+
+        let mut args: Vec<String> = Vec::new();
+        for preset in &self.presets {
+            args.extend(["-x".to_string(), preset.clone()]);
+        }
+        let mut push = |flag: &str, value: String| args.extend([flag.to_string(), value]);
+
+        macro_rules! changed {
+            ($cfg:ident, $base:ident; $($field:ident => $flag:literal),* $(,)?) => {
+                $(if $cfg.$field != $base.$field {
+                    push($flag, $cfg.$field.to_string());
+                })*
+            };
+        }
+
+        changed!(
+            i, bi;
+            k => "-k",
+            w => "-w",
+            bucket_bits => "--bucket-bits",
+            batch_size => "-I",
+        );
+        if self.threads != base.threads {
+            push("-t", self.threads.to_string());
+        }
+
+        let flag: MapFlags = parse_flags(&m.flags)?;
+        let base_flag = base.map_flags();
+        let unrepresentable = |flags: MapFlags| ConfigError::Unrepresentable(flags.to_string());
+
+        // -G only applies to spliced mapping and also sets -r, so it goes first
+        if flag.contains(MapFlags::SPLICE) && m.max_gap_ref != bm.max_gap_ref {
+            push("-G", m.max_gap_ref.to_string());
+        }
+        if (m.bw, m.bw_long) != (bm.bw, bm.bw_long) {
+            push("-r", format!("{},{}", m.bw, m.bw_long));
+        }
+        if (m.q, m.q2) != (bm.q, bm.q2) {
+            push("-O", format!("{},{}", m.q, m.q2));
+        }
+        if (m.e, m.e2) != (bm.e, bm.e2) {
+            push("-E", format!("{},{}", m.e, m.e2));
+        }
+        if (m.zdrop, m.zdrop_inv) != (bm.zdrop, bm.zdrop_inv) {
+            push("-z", format!("{},{}", m.zdrop, m.zdrop_inv));
+        }
+        if (m.min_mid_occ, m.max_mid_occ) != (bm.min_mid_occ, bm.max_mid_occ) {
+            push("-U", format!("{},{}", m.min_mid_occ, m.max_mid_occ));
+        }
+        if (m.mid_occ, m.mid_occ_frac, m.max_occ) != (bm.mid_occ, bm.mid_occ_frac, bm.max_occ) {
+            // -f takes either a count or a fraction (which resets the count)
+            if m.mid_occ_frac != bm.mid_occ_frac || m.mid_occ == 0 {
+                push("-f", m.mid_occ_frac.to_string());
+            }
+            if m.mid_occ > 0 || m.max_occ != bm.max_occ {
+                let occ = if m.mid_occ > 0 {
+                    m.mid_occ.to_string()
+                } else {
+                    m.mid_occ_frac.to_string()
+                };
+                push("-f", format!("{occ},{}", m.max_occ));
+            }
+        }
+
+        changed!(
+            m, bm;
+            seed => "--seed",
+            sdust_thres => "-T",
+            max_qlen => "--max-qlen",
+            max_gap => "-g",
+            max_frag_len => "-F",
+            max_chain_skip => "--max-chain-skip",
+            max_chain_iter => "--max-chain-iter",
+            min_cnt => "-n",
+            min_chain_score => "-m",
+            chain_gap_scale => "--chain-gap-scale",
+            chain_skip_scale => "--chain-skip-scale",
+            rmq_inner_dist => "--rmq-inner",
+            mask_level => "-M",
+            mask_len => "--mask-len",
+            pri_ratio => "-p",
+            best_n => "-N",
+            alt_drop => "--alt-drop",
+            a => "-A",
+            b => "-B",
+            transition => "-b",
+            sc_ambi => "--score-N",
+            noncan => "-C",
+            junc_bonus => "--junc-bonus",
+            junc_pen => "--junc-pen",
+            end_bonus => "--end-bonus",
+            min_dp_max => "-s",
+            min_ksw_len => "--min-dp-len",
+            anchor_ext_shift => "--end-seed-pen",
+            max_clip_ratio => "--max-clip-ratio",
+            jump_min_match => "--jump-min-match",
+            q_occ_frac => "--q-occ-frac",
+            occ_dist => "-e",
+            mini_batch_size => "-K",
+            max_sw_mat => "--cap-sw-mem",
+            cap_kalloc => "--cap-kalloc",
+        );
+
+        let mut switches: Vec<&str> = Vec::new();
+
+        let idx_flag: IdxFlags = parse_flags(&i.flags)?;
+        let added_idx = idx_flag.difference(base.idx_flags());
+        if added_idx.contains(IdxFlags::HPC) {
+            switches.push("-H");
+        }
+        if added_idx.contains(IdxFlags::NO_SEQ) {
+            switches.push("--idx-no-seq");
+        }
+
+        for (bit, on, off) in MAP_FLAG_ARGS {
+            match (flag.contains(*bit), base_flag.contains(*bit)) {
+                (true, false) => switches.extend(*on),
+                (false, true) => switches.push(off.ok_or_else(|| unrepresentable(*bit))?),
+                _ => {}
+            }
+        }
+
+        let strand = MapFlags::SPLICE_FOR | MapFlags::SPLICE_REV;
+        if flag & strand != base_flag & strand {
+            switches.push(match flag & strand {
+                s if s == strand => "-ub",
+                MapFlags::SPLICE_FOR => "-uf",
+                MapFlags::SPLICE_REV => "-ur",
+                _ => "-un",
+            });
+        }
+
+        // --sr=no clears both short-read modes, --sr and --sr=rna each set one
+        let sr = MapFlags::SR | MapFlags::SR_RNA;
+        let mut sr_base = base_flag & sr;
+        if !sr_base.difference(flag).is_empty() {
+            switches.push("--sr=no");
+            sr_base = MapFlags::empty();
+        }
+        let sr_added = (flag & sr).difference(sr_base);
+        if sr_added.contains(MapFlags::SR) {
+            switches.push("--sr");
+        }
+        if sr_added.contains(MapFlags::SR_RNA) {
+            switches.push("--sr=rna");
+        }
+
+        // --pairing=weak and =strong reset INDEPEND_SEG, --pairing=no only sets it
+        let pairing = MapFlags::INDEPEND_SEG | MapFlags::WEAK_PAIRING;
+        if flag & pairing != base_flag & pairing {
+            let weak = flag.contains(MapFlags::WEAK_PAIRING);
+            let independ = flag.contains(MapFlags::INDEPEND_SEG);
+            if weak != base_flag.contains(MapFlags::WEAK_PAIRING) || !independ {
+                switches.push(if weak {
+                    "--pairing=weak"
+                } else {
+                    "--pairing=strong"
+                });
+            }
+            if independ {
+                switches.push("--pairing=no");
+            }
+        }
+
+        // --qstrand sets both and neither can be cleared
+        let qstrand = MapFlags::QSTRAND | MapFlags::NO_INV;
+        if flag & qstrand != base_flag & qstrand {
+            if flag & qstrand != qstrand {
+                return Err(unrepresentable((flag ^ base_flag) & qstrand));
+            }
+            switches.push("--qstrand");
+        }
+
+        // --cs=none only clears OUT_CS, so OUT_CS_LONG is set first if it changes
+        let cs = MapFlags::OUT_CS | MapFlags::OUT_CS_LONG;
+        if flag & cs != base_flag & cs {
+            let long = flag.contains(MapFlags::OUT_CS_LONG);
+            if flag.contains(MapFlags::OUT_CS) || long != base_flag.contains(MapFlags::OUT_CS_LONG)
+            {
+                switches.push(if long { "--cs=long" } else { "--cs" });
+            }
+            if !flag.contains(MapFlags::OUT_CS) {
+                switches.push("--cs=none");
+            }
+        }
+
+        // -a, -c, --cs and --write-junc all turn on CIGAR, and --cs=none nothing else
+        let sets_cigar = switches
+            .iter()
+            .any(|s| matches!(*s, "-a" | "-c" | "--write-junc") || s.starts_with("--cs"));
+        match (
+            flag.contains(MapFlags::CIGAR),
+            base_flag.contains(MapFlags::CIGAR),
+        ) {
+            (true, false) if !sets_cigar => switches.push(match flag & cs {
+                f if f == cs => "--cs=long",
+                MapFlags::OUT_CS => "--cs",
+                _ => "--cs=none",
+            }),
+            (false, true) => return Err(unrepresentable(MapFlags::CIGAR)),
+            (false, false) if sets_cigar => return Err(unrepresentable(MapFlags::CIGAR)),
+            _ => {}
+        }
+
+        args.extend(switches.into_iter().map(String::from));
+        Ok(args)
+    }
+
+    /// The command line, as it would be passed to minimap2 (without file arguments),
+    /// see [`cli_args`](Self::cli_args)
+    pub fn command_line(&self) -> Result<String, ConfigError> {
+        let args = self.cli_args()?;
+        Ok(std::iter::once("minimap2".to_string())
+            .chain(args)
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    /// An `@PG` SAM header line describing this configuration
+    ///
+    /// `CL` is left out if the options have no [command line](Self::command_line).
+    pub fn pg_header(&self) -> String {
+        let mut line = format!(
+            "@PG\tID:minimap2\tPN:minimap2\tVN:{}",
+            self.minimap2_version
+        );
+        if let Ok(command_line) = self.command_line() {
+            line.push_str("\tCL:");
+            line.push_str(&command_line);
+        }
+        line
+    }
+}
+
+impl<S: BuilderState> Aligner<S> {
+    /// The effective configuration of this aligner, see [`AlignerConfig`]
+    pub fn config(&self) -> AlignerConfig {
+        AlignerConfig::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn json_round_trip() {
+        let aligner = Aligner::builder()
+            .splice()
+            .with_cigar()
+            .with_index_threads(2);
+        let config = aligner.config();
+        assert_eq!(config.presets, ["splice"]);
+        assert!(config.map.flags.contains(&"SPLICE".to_string()));
+        assert!(config.map.flags.contains(&"CIGAR".to_string()));
+
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: AlignerConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, config);
+
+        let rebuilt = restored.to_aligner().unwrap();
+        assert_eq!(rebuilt.mapopt.flag, aligner.mapopt.flag);
+        assert_eq!(rebuilt.idxopt.k, aligner.idxopt.k);
+        assert_eq!(rebuilt.threads, 2);
+        assert_eq!(rebuilt.config(), config);
+    }
+
+    #[test]
+    fn cli_round_trip() {
+        let args = [
+            "-x",
+            "sr",
+            "-k",
+            "19",
+            "-r",
+            "200,400",
+            "-O",
+            "6,20",
+            "-N",
+            "3",
+            "--secondary=no",
+            "--MD",
+            "-a",
+            "--cs=long",
+            "-f",
+            "0.001",
+        ];
+        let config = Aligner::from_cli_args(&args).unwrap().config();
+        let rendered = config.cli_args().unwrap();
+        let rendered: Vec<&str> = rendered.iter().map(String::as_str).collect();
+        let reparsed = Aligner::from_cli_args(&rendered).unwrap().config();
+        assert_eq!(reparsed, config);
+
+        let pg = config.pg_header();
+        assert!(pg.starts_with("@PG\tID:minimap2\tPN:minimap2\tVN:"));
+        assert!(pg.contains("\tCL:minimap2 -x sr "));
+    }
+
+    #[test]
+    fn cli_round_trip_every_flag() {
+        let round_trip = |aligner: &Aligner<PresetSet>| -> Result<(), ConfigError> {
+            let rendered = aligner.config().cli_args()?;
+            let rendered: Vec<&str> = rendered.iter().map(String::as_str).collect();
+            let reparsed = Aligner::from_cli_args(&rendered).unwrap();
+            assert_eq!(
+                reparsed.map_flags(),
+                aligner.map_flags(),
+                "{}",
+                rendered.join(" ")
+            );
+            Ok(())
+        };
+
+        let mut unrepresentable = Vec::new();
+        for preset in ["map-ont", "sr", "splice", "ava-ont"] {
+            let base = Aligner::from_presets([preset]).unwrap().with_cigar();
+            for (name, bit) in MapFlags::all().iter_names() {
+                let mut aligner = base.clone();
+                aligner.mapopt.flag ^= bit.bits() as i64;
+                if aligner.validate_opts().is_err() {
+                    continue;
+                }
+                let on = aligner.map_flags().contains(bit);
+                if let Err(e) = round_trip(&aligner) {
+                    assert_eq!(e, ConfigError::Unrepresentable(name.to_string()));
+                    unrepresentable.push((preset, name, on));
+                }
+            }
+        }
+
+        // minimap2 has no switch clearing these, and sets NO_INV only with QSTRAND
+        // (invalid with the sr and splice presets)
+        let expected = [
+            ("map-ont", "CIGAR", false),
+            ("map-ont", "QSTRAND", true),
+            ("map-ont", "NO_INV", true),
+            ("sr", "CIGAR", false),
+            ("sr", "TWO_IO_THREADS", false),
+            ("sr", "NO_INV", true),
+            ("splice", "CIGAR", false),
+            ("splice", "SPLICE", false),
+            ("splice", "NO_INV", true),
+            ("ava-ont", "NO_DIAG", false),
+            ("ava-ont", "CIGAR", false),
+            ("ava-ont", "NO_LJOIN", false),
+            ("ava-ont", "ALL_CHAINS", false),
+            ("ava-ont", "QSTRAND", true),
+            ("ava-ont", "NO_INV", true),
+        ];
+        assert_eq!(unrepresentable, expected);
+    }
+
+    #[test]
+    fn flag_names_and_errors() {
+        let flags = MapFlags::CIGAR | MapFlags::OUT_CS | MapFlags::from_bits_retain(1 << 62);
+        let names = flag_names(flags);
+        assert_eq!(names, ["CIGAR", "OUT_CS", "0x4000000000000000"]);
+        assert_eq!(parse_flags::<MapFlags>(&names).unwrap(), flags);
+
+        let mut config = Aligner::builder().map_ont().config();
+        config.map.flags.push("NOT_A_FLAG".to_string());
+        assert_eq!(
+            config.to_aligner().err().unwrap(),
+            ConfigError::UnknownFlag("NOT_A_FLAG".to_string())
+        );
+
+        let mut config = Aligner::builder().map_ont().config();
+        config.presets = vec!["map-foo".to_string()];
+        assert!(matches!(
+            config.to_aligner().err().unwrap(),
+            ConfigError::UnknownPreset(_)
+        ));
+    }
+}
@@ -0,0 +1,412 @@
+//! Per-contig coverage depth from mapping results
+//!
+//! [`Coverage`] turns [`Mapping`]s into per-base depth over the contigs of the index, for
+//! quick viral and plasmid QC without writing a BAM file and running mosdepth on it. Depth
+//! is taken from the CIGAR when the mapping has one (matches only: deletions, introns and
+//! clipping do not count), otherwise from the whole target span. It is written as bedGraph,
+//! per base or in bins, and summarised per contig as mean, median and breadth.
+//!
+//! Each contig that gets a mapping keeps an `i32` per base, so this is meant for small
+//! genomes rather than whole human references.
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//! use minimap2::coverage::Coverage;
+//!
+//! let aligner = Aligner::builder()
+//!     .map_ont()
+//!     .with_cigar()
+//!     .with_index("virus.fa", None)
+//!     .expect("Unable to build index");
+//!
+//! let mut coverage = Coverage::new(&aligner).with_min_mapq(20);
+//! for mapping in aligner.map_file("reads.fastq.gz", false, false).unwrap() {
+//!     coverage.add(&mapping);
+//! }
+//! coverage.write_bedgraph(std::io::stdout()).unwrap();
+//! coverage.write_summary(std::io::stderr(), 10).unwrap();
+//! ```
+
+use std::ffi::CStr;
+use std::io::{self, Write};
+
+use crate::{Aligner, Built, Mapping};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ContigDepth {
+    name: String,
+    length: usize,
+    // Depth changes at each position (length + 1), empty until the first mapping
+    diff: Vec<i32>,
+}
+
+impl ContigDepth {
+    fn add(&mut self, start: usize, end: usize) {
+        let end = end.min(self.length);
+        if start >= end {
+            return;
+        }
+        if self.diff.is_empty() {
+            self.diff = vec![0; self.length + 1];
+        }
+        self.diff[start] += 1;
+        self.diff[end] -= 1;
+    }
+
+    fn depth(&self) -> Vec<u32> {
+        if self.diff.is_empty() {
+            return vec![0; self.length];
+        }
+        let mut depth = 0;
+        self.diff[..self.length]
+            .iter()
+            .map(|change| {
+                depth += change;
+                depth as u32
+            })
+            .collect()
+    }
+}
+
+/// Coverage depth summary of one contig
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContigCoverage {
+    pub name: String,
+    pub length: usize,
+    pub mean: f64,
+    pub median: u32,
+    /// Fraction of bases with at least the requested depth
+    pub breadth: f64,
+}
+
+/// Per-base coverage depth accumulator over the contigs of an index
+///
+/// Secondary mappings are skipped unless [`with_secondary`](Self::with_secondary) is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    contigs: Vec<ContigDepth>,
+    min_mapq: u32,
+    secondary: bool,
+}
+
+impl Coverage {
+    /// Empty coverage over the contigs of the aligner's index
+    pub fn new(aligner: &Aligner<Built>) -> Self {
+        let contigs = (0..aligner.n_seq() as usize)
+            .map(|i| {
+                let seq = aligner.get_seq(i).unwrap();
+                let name = unsafe { CStr::from_ptr(seq.name) };
+                (name.to_string_lossy().into_owned(), seq.len as usize)
+            })
+            .collect();
+        Self::from_contigs(contigs)
+    }
+
+    /// Empty coverage over `(name, length)` contigs, in index order (`Mapping::target_id`)
+    pub fn from_contigs(contigs: Vec<(String, usize)>) -> Self {
+        Self {
+            contigs: contigs
+                .into_iter()
+                .map(|(name, length)| ContigDepth {
+                    name,
+                    length,
+                    diff: Vec::new(),
+                })
+                .collect(),
+            min_mapq: 0,
+            secondary: false,
+        }
+    }
+
+    /// Skip mappings with a MAPQ below `min_mapq`
+    pub fn with_min_mapq(mut self, min_mapq: u32) -> Self {
+        self.min_mapq = min_mapq;
+        self
+    }
+
+    /// Count secondary mappings too
+    pub fn with_secondary(mut self) -> Self {
+        self.secondary = true;
+        self
+    }
+
+    /// Add the depth of a mapping; mappings to targets not in the index are ignored
+    pub fn add(&mut self, mapping: &Mapping) {
+        let secondary = !mapping.is_primary && !mapping.is_supplementary;
+        if (secondary && !self.secondary) || mapping.mapq < self.min_mapq {
+            return;
+        }
+        let Some(contig) = usize::try_from(mapping.target_id)
+            .ok()
+            .and_then(|id| self.contigs.get_mut(id))
+        else {
+            return;
+        };
+
+        let start = mapping.target_start as usize;
+        let cigar = mapping
+            .alignment
+            .as_ref()
+            .and_then(|aln| aln.cigar.as_ref());
+        let Some(cigar) = cigar else {
+            contig.add(start, mapping.target_end as usize);
+            return;
+        };
+
+        let mut pos = start;
+        for &(len, op) in cigar {
+            let len = len as usize;
+            match op {
+                // M, =, X
+                0 | 7 | 8 => {
+                    contig.add(pos, pos + len);
+                    pos += len;
+                }
+                // D, N
+                2 | 3 => pos += len,
+                _ => {}
+            }
+        }
+    }
+
+    /// Add the depth of another accumulator over the same contigs (e.g. one per thread)
+    ///
+    /// # Panics
+    /// If the contigs differ.
+    pub fn merge(&mut self, other: &Coverage) {
+        assert_eq!(
+            self.contigs.len(),
+            other.contigs.len(),
+            "Merging coverage over different contigs"
+        );
+        for (contig, other) in self.contigs.iter_mut().zip(&other.contigs) {
+            assert_eq!(
+                contig.name, other.name,
+                "Merging coverage over different contigs"
+            );
+            if other.diff.is_empty() {
+                continue;
+            }
+            if contig.diff.is_empty() {
+                contig.diff = other.diff.clone();
+                continue;
+            }
+            for (a, b) in contig.diff.iter_mut().zip(&other.diff) {
+                *a += b;
+            }
+        }
+    }
+
+    /// Contig names and lengths, in index order
+    pub fn contigs(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.contigs.iter().map(|c| (c.name.as_str(), c.length))
+    }
+
+    /// Per-base depth of contig `name`
+    pub fn depth(&self, name: &str) -> Option<Vec<u32>> {
+        self.contigs
+            .iter()
+            .find(|c| c.name == name)
+            .map(ContigDepth::depth)
+    }
+
+    /// Depth summary per contig; breadth counts bases with a depth of at least `min_depth`
+    pub fn summary(&self, min_depth: u32) -> Vec<ContigCoverage> {
+        self.contigs
+            .iter()
+            .map(|contig| {
+                let mut depth = contig.depth();
+                let covered = depth.iter().filter(|&&d| d >= min_depth).count();
+                let total: u64 = depth.iter().map(|&d| d as u64).sum();
+                let (mean, breadth) = if contig.length == 0 {
+                    (0.0, 0.0)
+                } else {
+                    let length = contig.length as f64;
+                    (total as f64 / length, covered as f64 / length)
+                };
+                let median = if depth.is_empty() {
+                    0
+                } else {
+                    let mid = depth.len() / 2;
+                    *depth.select_nth_unstable(mid).1
+                };
+                ContigCoverage {
+                    name: contig.name.clone(),
+                    length: contig.length,
+                    mean,
+                    median,
+                    breadth,
+                }
+            })
+            .collect()
+    }
+
+    /// Write per-base depth as bedGraph, one line per run of equal depth (zero included)
+    pub fn write_bedgraph<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for contig in &self.contigs {
+            let depth = contig.depth();
+            let mut start = 0;
+            for end in 1..=depth.len() {
+                if end == depth.len() || depth[end] != depth[start] {
+                    writeln!(w, "{}\t{start}\t{end}\t{}", contig.name, depth[start])?;
+                    start = end;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the mean depth of `bin_size` bins as bedGraph; the last bin of a contig may be
+    /// shorter
+    pub fn write_bedgraph_binned<W: Write>(&self, mut w: W, bin_size: usize) -> io::Result<()> {
+        assert!(bin_size > 0, "Bin size must be positive");
+        for contig in &self.contigs {
+            let depth = contig.depth();
+            for (i, bin) in depth.chunks(bin_size).enumerate() {
+                let start = i * bin_size;
+                let mean = bin.iter().map(|&d| d as u64).sum::<u64>() as f64 / bin.len() as f64;
+                let end = start + bin.len();
+                writeln!(w, "{}\t{start}\t{end}\t{mean:.2}", contig.name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write [`summary`](Self::summary) as TSV with a header line
+    pub fn write_summary<W: Write>(&self, mut w: W, min_depth: u32) -> io::Result<()> {
+        writeln!(w, "contig\tlength\tmean\tmedian\tbreadth_{min_depth}x")?;
+        for c in self.summary(min_depth) {
+            writeln!(
+                w,
+                "{}\t{}\t{:.2}\t{}\t{:.4}",
+                c.name, c.length, c.mean, c.median, c.breadth
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Alignment;
+
+    fn mapping(target_id: i32, start: i32, end: i32, cigar: Option<Vec<(u32, u8)>>) -> Mapping {
+        Mapping {
+            target_id,
+            target_start: start,
+            target_end: end,
+            mapq: 60,
+            is_primary: true,
+            alignment: cigar.map(|cigar| Alignment {
+                nm: 0,
+                cigar: Some(cigar),
+                cigar_str: None,
+                md: None,
+                cs: None,
+                alignment_score: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn contigs() -> Coverage {
+        Coverage::from_contigs(vec![("a".to_string(), 10), ("b".to_string(), 5)])
+    }
+
+    #[test]
+    fn depth() {
+        let mut coverage = contigs();
+        // 2S3M1I2D2M: matches on 2..5 and 7..9
+        coverage.add(&mapping(
+            0,
+            2,
+            9,
+            Some(vec![(2, 4), (3, 0), (1, 1), (2, 2), (2, 0)]),
+        ));
+        coverage.add(&mapping(0, 0, 4, None));
+        // Secondary, low MAPQ and unknown targets are skipped
+        let mut secondary = mapping(0, 0, 10, None);
+        secondary.is_primary = false;
+        coverage.add(&secondary);
+        let mut low_mapq = mapping(0, 0, 10, None);
+        low_mapq.mapq = 3;
+        let mut coverage = coverage.with_min_mapq(10);
+        coverage.add(&low_mapq);
+        coverage.add(&mapping(5, 0, 10, None));
+
+        assert_eq!(coverage.depth("a").unwrap(), [1, 1, 2, 2, 1, 0, 0, 1, 1, 0]);
+        assert_eq!(coverage.depth("b").unwrap(), [0; 5]);
+        assert_eq!(coverage.depth("c"), None);
+
+        let mut with_secondary = contigs().with_secondary();
+        with_secondary.add(&secondary);
+        assert_eq!(with_secondary.depth("a").unwrap(), [1; 10]);
+    }
+
+    #[test]
+    fn bedgraph() {
+        let mut coverage = contigs();
+        coverage.add(&mapping(0, 2, 6, None));
+        coverage.add(&mapping(0, 4, 10, None));
+
+        let mut out = Vec::new();
+        coverage.write_bedgraph(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a\t0\t2\t0\na\t2\t4\t1\na\t4\t6\t2\na\t6\t10\t1\nb\t0\t5\t0\n"
+        );
+
+        let mut out = Vec::new();
+        coverage.write_bedgraph_binned(&mut out, 4).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a\t0\t4\t0.50\na\t4\t8\t1.50\na\t8\t10\t1.00\nb\t0\t4\t0.00\nb\t4\t5\t0.00\n"
+        );
+    }
+
+    #[test]
+    fn summary_and_merge() {
+        let mut a = contigs();
+        a.add(&mapping(0, 0, 6, None));
+        let mut b = contigs();
+        b.add(&mapping(0, 4, 8, None));
+        a.merge(&b);
+        assert_eq!(a.depth("a").unwrap(), [1, 1, 1, 1, 2, 2, 1, 1, 0, 0]);
+
+        let summary = a.summary(1);
+        assert_eq!(summary[0].name, "a");
+        assert_eq!((summary[0].mean, summary[0].median), (1.0, 1));
+        assert_eq!(summary[0].breadth, 0.8);
+        assert_eq!((summary[1].mean, summary[1].breadth), (0.0, 0.0));
+        assert_eq!(a.summary(2)[0].breadth, 0.2);
+
+        let mut out = Vec::new();
+        a.write_summary(&mut out, 1).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with("contig\tlength\tmean\tmedian\tbreadth_1x\na\t10\t1.00\t1\t0.8000\n")
+        );
+    }
+
+    #[test]
+    fn from_index() {
+        let aligner = Aligner::builder()
+            .map_ont()
+            .with_cigar()
+            .with_index("test_data/MT-human.fa", None)
+            .unwrap();
+        let mut coverage = Coverage::new(&aligner);
+        assert_eq!(
+            coverage.contigs().collect::<Vec<_>>(),
+            [("MT_human", 16569)]
+        );
+
+        let query = b"GTTTATGTAGCTTATTCTATCCAAAGCAATGCACTGAAAATGTCTCGACGGGCCCACACGCCCCATAAACAAATAGGTTTGGTCCTAGCCTTTCTATTAGCTCTTAGTGAGGTTACACATGCAAGCATCCCCGCCCCAGTGAGTCGCCCTCCAAGTCACTCTGACTAAGAGGAGCAAGCATCAAGCACGCAACAGCGCAG";
+        for mapping in aligner.map(query, false, false, None, None, None).unwrap() {
+            coverage.add(&mapping);
+        }
+        let summary = coverage.summary(1);
+        assert!(summary[0].breadth > 0.0 && summary[0].breadth < 0.1);
+    }
+}
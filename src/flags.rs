@@ -0,0 +1,201 @@
+//! Typed `mm_mapopt_t` and `mm_idxopt_t` flags
+//!
+//! [`MapFlags`] and [`IdxFlags`] name every `MM_F_*` / `MM_I_*` bit, so the flags of an
+//! aligner can be inspected, printed and parsed instead of masked by hand.
+//!
+//! ```
+//! use minimap2::Aligner;
+//! use minimap2::flags::MapFlags;
+//!
+//! let aligner = Aligner::builder().splice().with_cigar();
+//! let flags = aligner.map_flags();
+//! assert!(flags.contains(MapFlags::CIGAR | MapFlags::SPLICE));
+//!
+//! let parsed: MapFlags = "CIGAR | OUT_CS".parse().unwrap();
+//! assert_eq!(parsed.to_string(), "CIGAR | OUT_CS");
+//! assert_eq!(parsed.iter_names().map(|(name, _)| name).collect::<Vec<_>>(), ["CIGAR", "OUT_CS"]);
+//! ```
+
+use bitflags::bitflags;
+use minimap2_sys::*;
+
+bitflags! {
+    /// Mapping flags (`mm_mapopt_t.flag`, `MM_F_*`)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct MapFlags: u64 {
+        const NO_DIAG = MM_F_NO_DIAG as u64;
+        const NO_DUAL = MM_F_NO_DUAL as u64;
+        const CIGAR = MM_F_CIGAR as u64;
+        const OUT_SAM = MM_F_OUT_SAM as u64;
+        const NO_QUAL = MM_F_NO_QUAL as u64;
+        const OUT_CG = MM_F_OUT_CG as u64;
+        const OUT_CS = MM_F_OUT_CS as u64;
+        const SPLICE = MM_F_SPLICE as u64;
+        const SPLICE_FOR = MM_F_SPLICE_FOR as u64;
+        const SPLICE_REV = MM_F_SPLICE_REV as u64;
+        const NO_LJOIN = MM_F_NO_LJOIN as u64;
+        const OUT_CS_LONG = MM_F_OUT_CS_LONG as u64;
+        const SR = MM_F_SR as u64;
+        const FRAG_MODE = MM_F_FRAG_MODE as u64;
+        const NO_PRINT_2ND = MM_F_NO_PRINT_2ND as u64;
+        /// `MM_F_2_IO_THREADS`
+        const TWO_IO_THREADS = MM_F_2_IO_THREADS as u64;
+        const LONG_CIGAR = MM_F_LONG_CIGAR as u64;
+        const INDEPEND_SEG = MM_F_INDEPEND_SEG as u64;
+        const SPLICE_FLANK = MM_F_SPLICE_FLANK as u64;
+        const SOFTCLIP = MM_F_SOFTCLIP as u64;
+        const FOR_ONLY = MM_F_FOR_ONLY as u64;
+        const REV_ONLY = MM_F_REV_ONLY as u64;
+        const HEAP_SORT = MM_F_HEAP_SORT as u64;
+        const ALL_CHAINS = MM_F_ALL_CHAINS as u64;
+        const OUT_MD = MM_F_OUT_MD as u64;
+        const COPY_COMMENT = MM_F_COPY_COMMENT as u64;
+        const EQX = MM_F_EQX as u64;
+        const PAF_NO_HIT = MM_F_PAF_NO_HIT as u64;
+        const NO_END_FLT = MM_F_NO_END_FLT as u64;
+        const HARD_MLEVEL = MM_F_HARD_MLEVEL as u64;
+        const SAM_HIT_ONLY = MM_F_SAM_HIT_ONLY as u64;
+        const RMQ = MM_F_RMQ as u64;
+        const QSTRAND = MM_F_QSTRAND;
+        const NO_INV = MM_F_NO_INV;
+        const NO_HASH_NAME = MM_F_NO_HASH_NAME;
+        const SPLICE_OLD = MM_F_SPLICE_OLD;
+        const SECONDARY_SEQ = MM_F_SECONDARY_SEQ;
+        const OUT_DS = MM_F_OUT_DS;
+        const WEAK_PAIRING = MM_F_WEAK_PAIRING;
+        const SR_RNA = MM_F_SR_RNA;
+        const OUT_JUNC = MM_F_OUT_JUNC;
+    }
+}
+
+bitflags! {
+    /// Indexing flags (`mm_idxopt_t.flag`, `MM_I_*`)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct IdxFlags: u16 {
+        const HPC = MM_I_HPC as u16;
+        const NO_SEQ = MM_I_NO_SEQ as u16;
+        const NO_NAME = MM_I_NO_NAME as u16;
+    }
+}
+
+macro_rules! impl_flags_text {
+    ($($flags:ty),*) => {$(
+        /// Flag names joined with `|`, e.g. `CIGAR | OUT_CS`; unnamed bits are shown in hex
+        impl std::fmt::Display for $flags {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                bitflags::parser::to_writer(self, f)
+            }
+        }
+
+        /// Parse flag names joined with `|` (as written by `Display`); hex values are accepted
+        impl std::str::FromStr for $flags {
+            type Err = bitflags::parser::ParseError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                bitflags::parser::from_str(s)
+            }
+        }
+    )*};
+}
+
+impl_flags_text!(MapFlags, IdxFlags);
+
+impl MapFlags {
+    /// The flags of a `mm_mapopt_t`
+    pub fn of(mapopt: &crate::MapOpt) -> Self {
+        MapFlags::from_bits_retain(mapopt.flag as u64)
+    }
+}
+
+impl IdxFlags {
+    /// The flags of a `mm_idxopt_t`
+    pub fn of(idxopt: &crate::IdxOpt) -> Self {
+        IdxFlags::from_bits_retain(idxopt.flag as u16)
+    }
+}
+
+/// A builder method would set a flag that is already set, e.g.
+/// [`Aligner::try_with_cigar`](crate::Aligner::try_with_cigar) called twice
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlagError {
+    /// The builder method, e.g. `with_cigar`
+    pub method: &'static str,
+    /// The flag it sets
+    pub flag: MapFlags,
+    /// All flags set at the time
+    pub flags: MapFlags,
+}
+
+impl std::fmt::Display for FlagError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}(): {} is already set (flags: {})",
+            self.method, self.flag, self.flags
+        )
+    }
+}
+
+impl std::error::Error for FlagError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Aligner;
+
+    #[test]
+    fn display_and_parse() {
+        let flags = MapFlags::CIGAR | MapFlags::OUT_CS | MapFlags::SPLICE;
+        assert_eq!(flags.to_string(), "CIGAR | OUT_CS | SPLICE");
+        assert_eq!(format!("{flags:?}"), "MapFlags(CIGAR | OUT_CS | SPLICE)");
+        assert_eq!(
+            "CIGAR | OUT_CS | SPLICE".parse::<MapFlags>().unwrap(),
+            flags
+        );
+        assert_eq!("SPLICE|CIGAR|OUT_CS".parse::<MapFlags>().unwrap(), flags);
+        assert!("CIGAR | NOT_A_FLAG".parse::<MapFlags>().is_err());
+
+        let unnamed = MapFlags::CIGAR | MapFlags::from_bits_retain(1 << 62);
+        assert_eq!(unnamed.to_string(), "CIGAR | 0x4000000000000000");
+        assert_eq!(unnamed.to_string().parse::<MapFlags>().unwrap(), unnamed);
+
+        assert_eq!(IdxFlags::HPC.to_string(), "HPC");
+        assert_eq!(MapFlags::empty().to_string(), "");
+    }
+
+    #[test]
+    fn aligner_flags() {
+        let aligner = Aligner::builder().sr().with_cigar();
+        let flags = aligner.map_flags();
+        assert!(flags.contains(MapFlags::SR | MapFlags::CIGAR | MapFlags::OUT_CS));
+        assert_eq!(flags.bits(), aligner.mapopt.flag as u64);
+
+        let aligner = Aligner::builder()
+            .map_hifi()
+            .with_index_options(&crate::options::IndexOptions::new().hpc(true));
+        assert!(aligner.unwrap().idx_flags().contains(IdxFlags::HPC));
+    }
+
+    #[test]
+    fn double_set() {
+        let aligner = Aligner::builder().map_ont().with_cigar();
+        let err = aligner.clone().try_with_cigar().err().unwrap();
+        assert_eq!(err.method, "with_cigar");
+        assert_eq!(err.flag, MapFlags::CIGAR);
+        assert_eq!(err.flags, aligner.map_flags());
+        assert!(
+            err.to_string()
+                .starts_with("with_cigar(): CIGAR is already set")
+        );
+
+        let aligner = aligner.try_with_sam_out().unwrap();
+        assert_eq!(
+            aligner.try_with_sam_out().err().unwrap().flag,
+            MapFlags::OUT_SAM
+        );
+        let aligner = Aligner::builder().try_with_copy_comment().unwrap();
+        assert!(aligner.try_with_copy_comment().is_err());
+        let aligner = Aligner::builder().try_with_sam_hit_only().unwrap();
+        assert!(aligner.try_with_sam_hit_only().is_err());
+    }
+}
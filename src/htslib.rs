@@ -43,20 +43,25 @@
 //! ```
 
 use super::ffi as mm_ffi;
-use crate::{Aligner, BUF, Built, Mapping, Strand};
+use crate::base_mods::clip_base_mods;
+use crate::flags::MapFlags;
+use crate::sam::{Clipping, TagValue, comment_tags, masked_seq, record_comment};
+pub use crate::sam::{ReadGroup, ReadGroupError};
+use crate::{Aligner, BUF, Built, Mapping, Strand, reverse_complement};
 use minimap2_sys::{km_destroy, km_init};
 use rust_htslib::bam::header::HeaderRecord;
-use rust_htslib::bam::record::{Cigar, CigarString};
+use rust_htslib::bam::record::{Aux, Cigar, CigarString};
 use rust_htslib::bam::{Header, HeaderView, Record};
 use std::ffi::{CStr, CString};
-use std::mem::MaybeUninit;
 use std::ptr;
 use std::sync::Arc;
 
 /// A wrapper around mm_bseq1_t
+///
+/// The name, sequence, quality and comment strings are owned by the query and freed on drop.
 #[derive(Debug)]
 pub struct Query {
-    pub inner: mm_ffi::mm_bseq1_t,
+    inner: mm_ffi::mm_bseq1_t,
 }
 
 impl Query {
@@ -80,35 +85,64 @@ impl Query {
 
         let inner = mm_ffi::mm_bseq1_t {
             l_seq: l_seq as i32,
-            rid: 0, // TODO: pass a unique read id,
+            rid: 0,
             name: name.into_raw(),
             seq,
             qual,
-            comment: ptr::null_mut(), // TODO: pass SAM flags in comment
+            comment: ptr::null_mut(),
         };
         Query { inner }
     }
 
-    pub fn as_unmapped_record(&self) -> Record {
-        let mut rec = Record::new();
+    /// The underlying mm_bseq1_t, whose strings stay owned by the query
+    pub fn inner(&self) -> &mm_ffi::mm_bseq1_t {
+        &self.inner
+    }
 
-        let qname = unsafe { CStr::from_ptr(self.inner.name).to_bytes() };
-        let seq = unsafe { CStr::from_ptr(self.inner.seq).to_bytes() };
-        let qual = if self.inner.qual.is_null() {
-            rec.set(qname, None, seq, &vec![255u8; seq.len()]);
-        } else {
-            rec.set(qname, None, seq, unsafe {
-                CStr::from_ptr(self.inner.qual).to_bytes()
-            });
-        };
-        rec.set_unmapped();
-        rec.set_tid(-1);
-        rec.set_pos(-1);
-        rec.set_mapq(0);
-        rec.set_mpos(-1);
-        rec.set_mtid(-1);
-        rec.set_insert_size(0);
-        rec
+    /// Set the read id, the index of the read in its input (0 by default)
+    pub fn with_rid(mut self, rid: i32) -> Self {
+        self.inner.rid = rid;
+        self
+    }
+
+    /// Set the FASTA/FASTQ comment, copied to the SAM record when `MM_F_COPY_COMMENT` is
+    /// set (`minimap2 -y`); it must consist of valid SAM tags, e.g. `BC:Z:ACGT`
+    pub fn with_comment(mut self, comment: &[u8]) -> Result<Self, &'static str> {
+        let comment = CString::new(comment).map_err(|_| "Comment contains a NUL byte")?;
+        if !self.inner.comment.is_null() {
+            drop(unsafe { CString::from_raw(self.inner.comment) });
+        }
+        self.inner.comment = comment.into_raw();
+        Ok(self)
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        let inner = &self.inner;
+        for s in [inner.name, inner.seq, inner.qual, inner.comment] {
+            if !s.is_null() {
+                drop(unsafe { CString::from_raw(s) });
+            }
+        }
+    }
+}
+
+impl ReadGroup {
+    /// Set the `RG:Z` tag of a record, replacing one copied from a comment
+    pub fn tag_record(&self, record: &mut Record) -> Result<(), rust_htslib::errors::Error> {
+        if record.aux(b"RG").is_ok() {
+            record.remove_aux(b"RG")?;
+        }
+        record.push_aux(b"RG", Aux::String(&self.id))
+    }
+
+    fn push_to(&self, header: &mut Header) {
+        let mut record = HeaderRecord::new(b"RG");
+        for (tag, value) in self.fields() {
+            record.push_tag(tag.as_bytes(), value);
+        }
+        header.push_record(&record);
     }
 }
 
@@ -124,6 +158,41 @@ impl Aligner<Built> {
         }
     }
 
+    /// A complete SAM header, as minimap2 writes it: `@HD`, `@SQ` for every sequence in the
+    /// index, `@RG` for each read group, an `@PG` line with the minimap2 version and the
+    /// effective options (see [`AlignerConfig::pg_header`](crate::config::AlignerConfig::pg_header))
+    /// and an `@CO` line for each comment
+    ///
+    /// Pass the read group of the reads to [`map_query_to_sam`](Self::map_query_to_sam) to
+    /// tag their records.
+    pub fn sam_header(&self, read_groups: &[ReadGroup], comments: &[&str]) -> Header {
+        let mut header = Header::new();
+        header.push_record(
+            HeaderRecord::new(b"HD")
+                .push_tag(b"VN", "1.6")
+                .push_tag(b"SO", "unsorted")
+                .push_tag(b"GO", "query"),
+        );
+        self.populate_header(&mut header);
+        for read_group in read_groups {
+            read_group.push_to(&mut header);
+        }
+
+        let config = self.config();
+        let mut pg = HeaderRecord::new(b"PG");
+        pg.push_tag(b"ID", "minimap2")
+            .push_tag(b"PN", "minimap2")
+            .push_tag(b"VN", &config.minimap2_version);
+        if let Ok(command_line) = config.command_line() {
+            pg.push_tag(b"CL", command_line);
+        }
+        header.push_record(&pg);
+        for comment in comments {
+            header.push_comment(comment.as_bytes());
+        }
+        header
+    }
+
     pub fn map_to_sam(
         &self,
         seq: &[u8],
@@ -133,30 +202,40 @@ impl Aligner<Built> {
         max_frag_len: Option<usize>,
         extra_flags: Option<Vec<u64>>,
     ) -> Result<Vec<Record>, &'static str> {
-        // Make sure index is set
-        if !self.has_index() {
-            return Err("No index");
-        }
-
         // Make sure sequence is not empty
         if seq.is_empty() {
             return Err("Sequence is empty");
         }
 
         let query = Query::new(seq, qual, name);
+        self.map_query_to_sam(&query, header, max_frag_len, extra_flags.as_deref(), None)
+    }
+
+    /// Like [`map_to_sam`](Self::map_to_sam), for a [`Query`] with a read id or comment
+    ///
+    /// All records, including the unmapped record of a read without hits, are written by
+    /// minimap2's `mm_write_sam2`. The comment is copied when `MM_F_COPY_COMMENT` is set.
+    /// With a `read_group`, every record is tagged with its `RG:Z` (`minimap2 -R`).
+    pub fn map_query_to_sam(
+        &self,
+        query: &Query,
+        header: &HeaderView,
+        max_frag_len: Option<usize>,
+        extra_flags: Option<&[u64]>,
+        read_group: Option<&ReadGroup>,
+    ) -> Result<Vec<Record>, &'static str> {
+        // Make sure index is set
+        if !self.has_index() {
+            return Err("No index");
+        }
+
         // Number of results
         let mut n_regs: i32 = 0;
-        let mut map_opt = self.mapopt.clone();
+        let mut map_opt = self.mapopt;
 
         // immutable raw pointer to the index
         let mi = &**self.idx.as_ref().unwrap().as_ref() as *const mm_ffi::mm_idx_t;
 
-        // TODO: other flags to consider:
-        // MM_F_NO_PRINT_2ND
-        // MM_F_SAM_HIT_ONLY (though this seems to be the default?)
-        //map_opt.flag |= mm_ffi::MM_F_OUT_SAM as i64;
-        //map_opt.flag |= mm_ffi::MM_F_CIGAR as i64;
-
         // if max_frag_len is not None: map_opt.max_frag_len = max_frag_len
         if let Some(max_frag_len) = max_frag_len {
             map_opt.max_frag_len = max_frag_len as i32;
@@ -165,117 +244,298 @@ impl Aligner<Built> {
         // if extra_flags is not None: map_opt.flag |= extra_flags
         if let Some(extra_flags) = extra_flags {
             for flag in extra_flags {
-                map_opt.flag |= flag as i64;
+                map_opt.flag |= *flag as i64;
             }
         }
 
-        let mappings = BUF.with(|buf| {
-            let mm_reg = MaybeUninit::new(unsafe {
-                mm_ffi::mm_map(
+        BUF.with(|buf| unsafe {
+            let regs = mm_ffi::mm_map(
+                mi,
+                query.inner.l_seq,
+                query.inner.seq as *const libc::c_char,
+                &mut n_regs,
+                buf.borrow_mut().buf,
+                &map_opt,
+                query.inner.name,
+            );
+
+            // Without hits, reg_idx -1 writes the unmapped record
+            let reg_idxs = if n_regs > 0 {
+                0..n_regs
+            } else if map_opt.flag & mm_ffi::MM_F_SAM_HIT_ONLY as i64 == 0 {
+                -1..0
+            } else {
+                0..0
+            };
+
+            let const_regs = regs as *const mm_ffi::mm_reg1_t;
+            let km = km_init();
+            let mut records = Vec::with_capacity(reg_idxs.len());
+            let mut result = Ok(());
+            for i in reg_idxs {
+                let mut sam: mm_ffi::kstring_t = std::mem::zeroed();
+                mm_ffi::mm_write_sam2(
+                    &mut sam,
                     mi,
-                    query.inner.l_seq,
-                    query.inner.seq as *const libc::c_char,
-                    &mut n_regs,
-                    buf.borrow_mut().buf,
-                    &map_opt,
-                    query.inner.name,
-                )
-            });
-            // FIXFIX: mm_map should return unmapped SAM records but it
-            //  currently doesn't seem to work. To work around this we create the
-            // record manually
-            if (n_regs == 0) & ((map_opt.flag & mm_ffi::MM_F_SAM_HIT_ONLY as i64) == 0) {
-                return vec![query.as_unmapped_record()];
+                    &query.inner,
+                    0,
+                    i,
+                    1,
+                    &n_regs,
+                    &const_regs,
+                    km,
+                    map_opt.flag,
+                );
+                let record = Record::from_sam(header, CStr::from_ptr(sam.s).to_bytes());
+                libc::free(sam.s as *mut libc::c_void);
+                match record {
+                    Ok(mut record) if i >= 0 => {
+                        let reg = &*const_regs.offset(i as isize);
+                        let seq = CStr::from_ptr(query.inner.seq).to_bytes();
+                        clip_record_base_mods(&mut record, seq, reg.qs as usize, reg.qe as usize);
+                        records.push(record);
+                    }
+                    Ok(record) => records.push(record),
+                    Err(_) => {
+                        result = Err("Invalid SAM record (is the comment made of SAM tags?)");
+                        break;
+                    }
+                }
             }
+            km_destroy(km);
 
-            let mut mappings = Vec::with_capacity(n_regs as usize);
-
-            for i in 0..n_regs {
-                let sam_str = unsafe {
-                    let mut result: MaybeUninit<mm_ffi::kstring_t> = MaybeUninit::zeroed();
-                    let const_ptr = *mm_reg.as_ptr() as *const mm_ffi::mm_reg1_t;
-                    let km = km_init();
-                    mm_ffi::mm_write_sam2(
-                        result.as_mut_ptr(),
-                        mi,
-                        &query.inner as *const mm_ffi::mm_bseq1_t,
-                        0,
-                        i,
-                        1,
-                        &n_regs,
-                        &const_ptr,
-                        km,
-                        map_opt.flag,
-                    );
-                    km_destroy(km);
-                    CStr::from_ptr((*result.as_ptr()).s)
-                };
-                let record = Record::from_sam(header, sam_str.to_bytes()).unwrap();
-                mappings.push(record);
+            for i in 0..n_regs as isize {
+                libc::free((*regs.offset(i)).p as *mut libc::c_void);
+            }
+            libc::free(regs as *mut libc::c_void);
+
+            result?;
+            if let Some(read_group) = read_group {
+                for record in &mut records {
+                    read_group
+                        .tag_record(record)
+                        .map_err(|_| "Cannot tag the record with its read group")?;
+                }
             }
-            mappings
-        });
-        Ok(mappings)
+            Ok(records)
+        })
     }
 }
 
+/// Convert a [`Mapping`] of `seq` to a record, as `mm_write_sam2` would write it
+///
+/// `None` gives an unmapped record. `qual` is phred+33 encoded (as for
+/// [`Aligner::map_to_sam`]) and defaults to [`Mapping::query_qual`]; without either the
+/// qualities are missing (`*`). `flags` are the mapping flags of the aligner (see
+/// [`Aligner::map_flags`]): they pick hard or soft clipping and whether secondary records
+/// keep their sequence.
+///
+/// The `NM`, `AS`, `ts`, `tp`, `MD` and `cs` tags are written when available, `MD` and
+/// `cs` both if the mapping has both (minimap2 only writes `MD` then). Tags that
+/// [`Mapping`] does not carry (`ms`, `nn`, `cm`, `s1`, `s2`, `de`, `rl`, `SA`) are not.
+/// `AS` is [`Alignment::alignment_score`](crate::Alignment::alignment_score), the DP score
+/// minimap2 writes there (its `ms` is the unadjusted max score). SEQ is
+/// [`Mapping::masked_seq`] when set, the read the tags describe. With
+/// `MapFlags::COPY_COMMENT`, the SAM tags of [`Mapping::comment`] follow, with `MM`/`ML`
+/// clipped to the kept part of hard-clipped records (see [`crate::base_mods`]).
 pub fn mapping_to_record(
     mapping: Option<&Mapping>,
     seq: &[u8],
-    header: Header,
+    header: &HeaderView,
     qual: Option<&[u8]>,
     query_name: Option<&[u8]>,
+    flags: MapFlags,
 ) -> Record {
     let mut rec = Record::new();
     let qname = query_name.unwrap_or(b"query");
-    // FIXFIX: there's probably a better way of setting a default value
-    // for the quality string
-    let qual = qual.map_or_else(|| vec![255u8; seq.len()], Vec::from);
-
-    let cigar: Option<CigarString> = mapping.and_then(|m| {
-        m.alignment
-            .as_ref()
-            .and_then(|aln| aln.cigar.as_ref())
-            .map(cigar_to_cigarstr)
+    let qual = qual.or_else(|| {
+        mapping
+            .and_then(|m| m.query_qual.as_deref())
+            .map(String::as_bytes)
     });
+    let qual = qual.map_or_else(
+        || vec![255u8; seq.len()],
+        |qual| qual.iter().map(|q| q.saturating_sub(33)).collect(),
+    );
 
-    rec.set(qname, cigar.as_ref(), seq, &qual[..]);
-    match mapping {
-        Some(m) => {
-            if m.strand == Strand::Reverse {
-                rec.set_reverse();
-            }
-            if !m.is_primary {
-                rec.set_secondary();
-            }
-            if m.is_supplementary {
-                rec.set_supplementary();
-            }
-            // TODO: set secondary/supplementary flags
-            rec.set_pos(m.target_start as i64);
-            rec.set_mapq(m.mapq as u8);
-            rec.set_mpos(-1);
-            // TODO: set tid from sequences listed in header
-            rec.set_mtid(-1);
-            rec.set_insert_size(0);
+    let Some(m) = mapping else {
+        rec.set(qname, None, seq, &qual);
+        rec.set_unmapped();
+        rec.set_tid(-1);
+        rec.set_pos(-1);
+        rec.set_mapq(0);
+        rec.set_mpos(-1);
+        rec.set_mtid(-1);
+        rec.set_insert_size(0);
+        return rec;
+    };
+
+    let reverse = m.strand == Strand::Reverse;
+    let secondary = !m.is_primary && !m.is_supplementary;
+    let read = seq;
+    let seq = masked_seq(m, seq);
+
+    // SEQ and QUAL are on the forward strand of the target
+    let (mut seq, mut qual) = if reverse {
+        (reverse_complement(seq), qual.into_iter().rev().collect())
+    } else {
+        (seq.to_vec(), qual)
+    };
+
+    let alignment = m.alignment.as_ref();
+    let clipping = Clipping::new(m, seq.len(), flags);
+    let cigar = alignment.and_then(|aln| aln.cigar.as_ref()).map(|cigar| {
+        if clipping.hard {
+            let kept = clipping.kept(seq.len());
+            seq = seq[kept.clone()].to_vec();
+            qual = qual[kept].to_vec();
         }
-        None => {
-            rec.set_unmapped();
-            rec.set_tid(-1);
-            rec.set_pos(-1);
-            rec.set_mapq(255);
-            rec.set_mpos(-1);
-            rec.set_mtid(-1);
-            rec.set_insert_size(-1);
+        cigar_to_cigarstr(&clipping.cigar(cigar))
+    });
+
+    if secondary && !flags.contains(MapFlags::SECONDARY_SEQ) {
+        seq.clear();
+        qual.clear();
+    }
+    rec.set(qname, cigar.as_ref(), &seq, &qual);
+
+    // `Record::new()` starts out unmapped
+    rec.unset_unmapped();
+    if reverse {
+        rec.set_reverse();
+    }
+    if secondary {
+        rec.set_secondary();
+    }
+    if m.is_supplementary {
+        rec.set_supplementary();
+    }
+    let tid = m
+        .target_name
+        .as_ref()
+        .and_then(|name| header.tid(name.as_bytes()))
+        .map_or(m.target_id, |tid| tid as i32);
+    rec.set_tid(tid);
+    rec.set_pos(m.target_start as i64);
+    rec.set_mapq(m.mapq as u8);
+    rec.set_mpos(-1);
+    rec.set_mtid(-1);
+    rec.set_insert_size(0);
+
+    // Tags in the order of write_tags() in minimap2's format.c
+    if let Some(aln) = alignment {
+        rec.push_aux(b"NM", int_aux(aln.nm as i64)).unwrap();
+        if let Some(score) = aln.alignment_score {
+            rec.push_aux(b"AS", int_aux(score as i64)).unwrap();
         }
+    }
+    if let Some(trans_strand) = m.trans_strand {
+        let strand = match trans_strand {
+            Strand::Forward => b'+',
+            Strand::Reverse => b'-',
+        };
+        rec.push_aux(b"ts", Aux::Char(strand)).unwrap();
+    }
+    let tp = match (secondary, m.is_inversion) {
+        (false, false) => b'P',
+        (false, true) => b'I',
+        (true, false) => b'S',
+        (true, true) => b'i',
     };
-    // TODO: set AUX flags for cs/md if available
+    rec.push_aux(b"tp", Aux::Char(tp)).unwrap();
+    if let Some(aln) = alignment {
+        if let Some(md) = &aln.md {
+            rec.push_aux(b"MD", Aux::String(md)).unwrap();
+        }
+        if let Some(cs) = &aln.cs {
+            rec.push_aux(b"cs", Aux::String(cs)).unwrap();
+        }
+    }
+    if let Some(comment) = m
+        .comment
+        .as_ref()
+        .filter(|_| flags.contains(MapFlags::COPY_COMMENT))
+    {
+        let hard_clipped = cigar.is_some() && clipping.hard;
+        push_comment(&mut rec, &record_comment(m, comment, read, hard_clipped));
+    }
     rec
 }
 
-fn cigar_to_cigarstr(cigar: &Vec<(u32, u8)>) -> CigarString {
+/// Add the SAM tags of a copied comment, skipping fields that are not valid tags
+fn push_comment(rec: &mut Record, comment: &str) {
+    for (tag, value) in comment_tags(comment) {
+        let aux = match &value {
+            TagValue::Char(c) => Aux::Char(*c),
+            TagValue::Int(i) => int_aux(*i),
+            TagValue::Float(f) => Aux::Float(*f),
+            TagValue::String(s) => Aux::String(s),
+            TagValue::Hex(h) => Aux::HexByteArray(h),
+            TagValue::Int8Array(v) => Aux::ArrayI8(v.into()),
+            TagValue::UInt8Array(v) => Aux::ArrayU8(v.into()),
+            TagValue::Int16Array(v) => Aux::ArrayI16(v.into()),
+            TagValue::UInt16Array(v) => Aux::ArrayU16(v.into()),
+            TagValue::Int32Array(v) => Aux::ArrayI32(v.into()),
+            TagValue::UInt32Array(v) => Aux::ArrayU32(v.into()),
+            TagValue::FloatArray(v) => Aux::ArrayFloat(v.into()),
+        };
+        // Duplicated tags are kept once, as with the first occurrence
+        rec.push_aux(&tag, aux).ok();
+    }
+}
+
+/// Clip the `MM`/`ML` tags of a hard-clipped record to `read[start..end]`, the part of the
+/// read (as sequenced) it keeps, and set `MN` to the kept length
+///
+/// Records without hard clipping or `MM`, or with invalid tags, are left unchanged.
+fn clip_record_base_mods(rec: &mut Record, read: &[u8], start: usize, end: usize) {
+    if !rec
+        .cigar()
+        .iter()
+        .any(|op| matches!(op, Cigar::HardClip(_)))
+    {
+        return;
+    }
+    let Ok(Aux::String(mm)) = rec.aux(b"MM") else {
+        return;
+    };
+    let mm = mm.to_string();
+    let ml: Vec<u8> = match rec.aux(b"ML") {
+        Ok(Aux::ArrayU8(ml)) => ml.iter().collect(),
+        _ => Vec::new(),
+    };
+    let Ok((mm, ml)) = clip_base_mods(&mm, &ml, read, start, end) else {
+        return;
+    };
+    for tag in [b"MM", b"ML", b"MN"] {
+        rec.remove_aux(tag).ok();
+    }
+    rec.push_aux(b"MM", Aux::String(&mm)).unwrap();
+    rec.push_aux(b"ML", Aux::ArrayU8((&ml).into())).unwrap();
+    rec.push_aux(b"MN", int_aux((end - start) as i64)).unwrap();
+}
+
+/// The smallest integer type for `value`, as htslib picks when parsing SAM
+fn int_aux(value: i64) -> Aux<'static> {
+    if value >= 0 {
+        if value <= u8::MAX as i64 {
+            Aux::U8(value as u8)
+        } else if value <= u16::MAX as i64 {
+            Aux::U16(value as u16)
+        } else {
+            Aux::U32(value as u32)
+        }
+    } else if value >= i8::MIN as i64 {
+        Aux::I8(value as i8)
+    } else if value >= i16::MIN as i64 {
+        Aux::I16(value as i16)
+    } else {
+        Aux::I32(value as i32)
+    }
+}
+
+fn cigar_to_cigarstr(cigar: &[(u32, u8)]) -> CigarString {
     let op_vec: Vec<Cigar> = cigar
-        .to_owned()
         .iter()
         .map(|(len, op)| match op {
             0 => Cigar::Match(*len),
@@ -351,6 +611,7 @@ impl From<&Aligner<Built>> for MMIndex {
 mod tests {
     use super::*;
     use crate::Aligner;
+    use crate::quality::QualityOptions;
     use rust_htslib::bam::ext::BamRecordExtensions;
     use rust_htslib::bam::{Read, Reader, Record, header::Header, record::Aux};
 
@@ -629,6 +890,288 @@ mod tests {
         assert_eq!(o.inner().core.isize_, e.inner().core.isize_);
     }
 
+    #[test]
+    fn test_mapping_to_record() {
+        let cases = [
+            ("perfect_read.fwd", false),
+            ("perfect_read.rev", false),
+            ("imperfect_read.fwd", false),
+            ("perfect_inv_duplicate", false),
+            ("split_read", false),
+            ("unmappable_read", false),
+            ("cdna.fwd", true),
+            ("cdna.rev", true),
+        ];
+        let tags: [&[u8]; 5] = [b"NM", b"AS", b"ts", b"tp", b"MD"];
+        for (query_name, spliced) in cases {
+            let (aligner, _, header_view, _, seq, qual) = get_test_case(query_name, spliced);
+            // minimap2 writes MD instead of cs when both are asked for
+            let [expected, expected_cs] = [mm_ffi::MM_F_OUT_MD, mm_ffi::MM_F_OUT_CS].map(|flag| {
+                aligner
+                    .map_to_sam(
+                        &seq,
+                        Some(&qual),
+                        Some(query_name.as_bytes()),
+                        &header_view,
+                        None,
+                        Some(vec![flag as u64]),
+                    )
+                    .unwrap()
+            });
+            let mappings = aligner
+                .map(&seq, true, true, None, None, Some(query_name.as_bytes()))
+                .unwrap();
+            let observed: Vec<Record> = if mappings.is_empty() {
+                vec![None]
+            } else {
+                mappings.iter().map(Some).collect()
+            }
+            .into_iter()
+            .map(|m| {
+                mapping_to_record(
+                    m,
+                    &seq,
+                    &header_view,
+                    Some(&qual),
+                    Some(query_name.as_bytes()),
+                    aligner.map_flags(),
+                )
+            })
+            .collect();
+
+            assert_eq!(observed.len(), expected.len(), "{query_name}");
+            for ((o, e), e_cs) in observed.iter().zip(&expected).zip(&expected_cs) {
+                assert_eq!(o.qname(), e.qname(), "{query_name}");
+                assert_eq!(o.flags(), e.flags(), "{query_name}");
+                assert_eq!(o.tid(), e.tid(), "{query_name}");
+                assert_eq!(o.pos(), e.pos(), "{query_name}");
+                assert_eq!(o.mapq(), e.mapq(), "{query_name}");
+                assert_eq!(o.cigar(), e.cigar(), "{query_name}");
+                assert_eq!(o.seq().as_bytes(), e.seq().as_bytes(), "{query_name}");
+                assert_eq!(o.qual(), e.qual(), "{query_name}");
+                assert_eq!(o.mpos(), e.mpos(), "{query_name}");
+                assert_eq!(o.insert_size(), e.insert_size(), "{query_name}");
+                for tag in tags {
+                    assert_eq!(o.aux(tag).ok(), e.aux(tag).ok(), "{query_name}");
+                }
+                assert_eq!(o.aux(b"cs").ok(), e_cs.aux(b"cs").ok(), "{query_name}");
+            }
+        }
+
+        let (aligner, _, header_view, _, seq, _) = get_test_case("perfect_read.fwd", false);
+        let mut mapping = aligner.map(&seq, false, false, None, None, None).unwrap()[0].clone();
+        mapping.is_inversion = true;
+        for (is_primary, tp) in [(true, b'I'), (false, b'i')] {
+            mapping.is_primary = is_primary;
+            let rec = mapping_to_record(
+                Some(&mapping),
+                &seq,
+                &header_view,
+                None,
+                None,
+                aligner.map_flags(),
+            );
+            assert_eq!(rec.aux(b"tp").unwrap(), Aux::Char(tp));
+        }
+    }
+
+    #[test]
+    fn test_masked_seq() {
+        // SEQ is the masked read, whose Ns are the mismatches of NM and MD
+        let cases = [
+            ("perfect_read.fwd", 50, "50C0T0A0T0C45"),
+            ("perfect_read.rev", 45, "45C0T0G0G0A50"),
+        ];
+        for (query_name, start, md) in cases {
+            let (aligner, _, header_view, _, seq, mut qual) = get_test_case(query_name, false);
+            qual[50..55].fill(b'#');
+            let quality = QualityOptions::new().mask_below(10);
+            let mappings = aligner
+                .map_with_qual(&seq, Some(&qual), false, true, None, Some(&quality))
+                .unwrap();
+            let rec = mapping_to_record(
+                mappings.first(),
+                &seq,
+                &header_view,
+                None,
+                Some(query_name.as_bytes()),
+                aligner.map_flags(),
+            );
+            let rec_seq = rec.seq().as_bytes();
+            assert_eq!(&rec_seq[start..start + 5], b"NNNNN", "{query_name}");
+            assert_eq!(
+                rec_seq.iter().filter(|&&b| b == b'N').count(),
+                5,
+                "{query_name}"
+            );
+            assert_eq!(rec.aux(b"NM").unwrap(), Aux::U8(5), "{query_name}");
+            assert_eq!(rec.aux(b"MD").unwrap(), Aux::String(md), "{query_name}");
+        }
+    }
+
+    #[test]
+    fn test_query_comment() {
+        for query_name in ["perfect_read.fwd", "unmappable_read"] {
+            let (aligner, _, header_view, _, seq, qual) = get_test_case(query_name, false);
+            let query = Query::new(&seq, Some(&qual), Some(query_name.as_bytes()))
+                .with_rid(7)
+                .with_comment(b"BC:Z:ACGT\tRX:Z:GGCC")
+                .unwrap();
+            assert_eq!(query.inner().rid, 7);
+
+            let records = aligner
+                .map_query_to_sam(&query, &header_view, None, None, None)
+                .unwrap();
+            assert_eq!(records.len(), 1);
+            assert!(records[0].aux(b"BC").is_err());
+
+            let copy_comment = [mm_ffi::MM_F_COPY_COMMENT as u64];
+            let records = aligner
+                .map_query_to_sam(&query, &header_view, None, Some(&copy_comment), None)
+                .unwrap();
+            let rec = records.first().unwrap();
+            assert_eq!(rec.is_unmapped(), query_name == "unmappable_read");
+            assert_eq!(rec.aux(b"BC").unwrap(), Aux::String("ACGT"));
+            assert_eq!(rec.aux(b"RX").unwrap(), Aux::String("GGCC"));
+        }
+
+        // A comment that is not made of SAM tags cannot be parsed
+        let (aligner, _, header_view, _, seq, _) = get_test_case("perfect_read.fwd", false);
+        assert!(
+            Query::new(&seq, None, None)
+                .with_comment(b"BC:Z:\0")
+                .is_err()
+        );
+        let query = Query::new(&seq, None, None)
+            .with_comment(b"not a tag")
+            .unwrap();
+        let copy_comment = [mm_ffi::MM_F_COPY_COMMENT as u64];
+        assert!(
+            aligner
+                .map_query_to_sam(&query, &header_view, None, Some(&copy_comment), None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_base_mods() {
+        let (aligner, _, header_view, _, seq, qual) = get_test_case("split_read", false);
+        // A call on every C of the read
+        let n_c = seq.iter().filter(|&&b| b == b'C').count();
+        let skips: String = (0..n_c).map(|_| ",0").collect();
+        let ml: Vec<String> = (0..n_c).map(|i| (i % 256).to_string()).collect();
+        let comment = format!("MM:Z:C+m?{skips};\tML:B:C,{}\tRG:Z:rg1", ml.join(","));
+
+        let query = Query::new(&seq, Some(&qual), Some(b"split_read"))
+            .with_comment(comment.as_bytes())
+            .unwrap();
+        let copy_comment = [mm_ffi::MM_F_COPY_COMMENT as u64];
+        let expected = aligner
+            .map_query_to_sam(&query, &header_view, None, Some(&copy_comment), None)
+            .unwrap();
+        assert!(expected.iter().any(|rec| rec.is_supplementary()));
+
+        let mut mappings = aligner
+            .map(&seq, false, false, None, None, Some(b"split_read"))
+            .unwrap();
+        assert_eq!(mappings.len(), expected.len());
+        for (m, e) in mappings.iter_mut().zip(&expected) {
+            m.comment = Some(Arc::new(comment.clone()));
+            let o = mapping_to_record(
+                Some(m),
+                &seq,
+                &header_view,
+                Some(&qual),
+                Some(b"split_read"),
+                aligner.map_flags() | MapFlags::COPY_COMMENT,
+            );
+            for tag in [b"MM", b"ML", b"MN", b"RG"] {
+                assert_eq!(o.aux(tag).ok(), e.aux(tag).ok());
+            }
+
+            // One call per C of the read kept in SEQ
+            let Ok(Aux::String(mm)) = e.aux(b"MM") else {
+                panic!("No MM tag");
+            };
+            let kept_c = match e.is_reverse() {
+                false => e.seq().as_bytes().iter().filter(|&&b| b == b'C').count(),
+                true => e.seq().as_bytes().iter().filter(|&&b| b == b'G').count(),
+            };
+            assert_eq!(mm.matches(',').count(), kept_c);
+            match e.aux(b"MN") {
+                Ok(mn) => {
+                    assert!(e.is_supplementary());
+                    assert_eq!(mn, int_aux(e.seq_len() as i64));
+                }
+                Err(_) => assert_eq!(kept_c, n_c),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unmapped_hit_only() {
+        let (aligner, _, header_view, _, seq, qual) = get_test_case("unmappable_read", false);
+        let hit_only = vec![mm_ffi::MM_F_SAM_HIT_ONLY as u64];
+        let records = aligner
+            .map_to_sam(&seq, Some(&qual), None, &header_view, None, Some(hit_only))
+            .unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn test_read_groups() {
+        let rg = ReadGroup::parse("@RG\\tID:rg1\\tSM:sample1\\tPL:ONT\\tPU:flowcell1").unwrap();
+        let aligner = Aligner::builder()
+            .map_ont()
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+        let rg2 = ReadGroup::new("rg2")
+            .with_sample("sample2")
+            .with_library("lib2");
+        let header = aligner.sam_header(&[rg.clone(), rg2], &["run 42"]);
+        let text = String::from_utf8(header.to_bytes()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "@HD\tVN:1.6\tSO:unsorted\tGO:query");
+        assert_eq!(lines[1], "@SQ\tSN:chr1\tLN:1720");
+        assert_eq!(lines[3], "@RG\tID:rg1\tSM:sample1\tPL:ONT\tPU:flowcell1");
+        assert_eq!(lines[4], "@RG\tID:rg2\tSM:sample2\tLB:lib2");
+        assert!(lines[5].starts_with("@PG\tID:minimap2\tPN:minimap2\tVN:"));
+        // with_cigar() is written as --cs, which implies -c
+        assert!(lines[5].contains("\tCL:minimap2 -x map-ont "));
+        assert!(lines[5].ends_with(" --cs"));
+        assert_eq!(lines[6], "@CO\trun 42");
+
+        let header_view = HeaderView::from_header(&header);
+        let seq = b"TACGCCACACGGGCTACACTCTCGCCTTCTCGTCTCAACTACGAGATGGACTGTCGGCCTAGAGGATCTAACACGAGAAGTACTTGCCGGCAAGCCCTAA";
+        let records = aligner
+            .map_to_sam(seq, None, None, &header_view, None, None)
+            .unwrap();
+        assert!(records[0].aux(b"RG").is_err());
+        for (seq, name) in [(&seq[..], None), (b"ACGT", Some(&b"unmapped"[..]))] {
+            let query = Query::new(seq, None, name);
+            let records = aligner
+                .map_query_to_sam(&query, &header_view, None, None, Some(&rg))
+                .unwrap();
+            assert_eq!(records[0].aux(b"RG").unwrap(), Aux::String("rg1"));
+        }
+
+        // The given read group replaces one copied from the comment
+        let query = Query::new(seq, None, None)
+            .with_comment(b"RG:Z:other")
+            .unwrap();
+        let copy_comment = [mm_ffi::MM_F_COPY_COMMENT as u64];
+        let records = aligner
+            .map_query_to_sam(&query, &header_view, None, Some(&copy_comment), Some(&rg))
+            .unwrap();
+        assert_eq!(records[0].aux(b"RG").unwrap(), Aux::String("rg1"));
+        let n_rg = records[0]
+            .aux_iter()
+            .filter(|aux| matches!(aux, Ok((tag, _)) if tag == b"RG"))
+            .count();
+        assert_eq!(n_rg, 1);
+    }
+
     #[test]
     fn test_optional_fields() {
         let query_name = "perfect_read.fwd";
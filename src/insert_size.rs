@@ -0,0 +1,399 @@
+//! Insert-size (fragment length) estimation for paired-end runs
+//!
+//! minimap2 infers the fragment size distribution internally when pairing, but does not
+//! expose it. [`InsertSizeStats`] accumulates the same information from the results of
+//! [`Aligner::map_pair`](crate::Aligner::map_pair), so it can be reported for QC or fed back
+//! as `max_frag_len` on a second pass.
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//! use minimap2::insert_size::InsertSizeStats;
+//!
+//! let aligner = Aligner::builder()
+//!     .sr()
+//!     .with_cigar()
+//!     .with_index("reference.fa", None)
+//!     .expect("Unable to build index");
+//!
+//! let mut stats = InsertSizeStats::new();
+//! let (m1, m2) = aligner
+//!     .map_pair(b"ACGT", b"TGCA", false, false, None, None, Some(b"pair"))
+//!     .unwrap();
+//! stats.add_pair(&m1, &m2);
+//!
+//! // Second pass with a fragment length bootstrapped from the first
+//! let max_frag_len = stats.max_frag_len(4.0);
+//! let (m1, m2) = aligner
+//!     .map_pair(b"ACGT", b"TGCA", false, false, max_frag_len, None, Some(b"pair"))
+//!     .unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::{Mapping, Strand};
+
+/// Relative orientation of the two reads of a pair
+///
+/// Follows the usual short-read aligner convention: `FR` is forward-reverse (reads point
+/// towards each other), `RF` is reverse-forward (reads point away from each other) and `FF`
+/// is both reads on the same strand (`FF` and `RR` are the same class).
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+pub enum PairOrientation {
+    FR,
+    RF,
+    FF,
+}
+
+impl std::fmt::Display for PairOrientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PairOrientation::FR => write!(f, "FR"),
+            PairOrientation::RF => write!(f, "RF"),
+            PairOrientation::FF => write!(f, "FF"),
+        }
+    }
+}
+
+impl PairOrientation {
+    /// Orientation of two mappings on the same target
+    ///
+    /// For pairs on opposite strands, the 5' ends decide: if the forward read's 5' end lies
+    /// before the reverse read's 5' end the reads point inwards (`FR`), otherwise outwards (`RF`).
+    pub fn of(m1: &Mapping, m2: &Mapping) -> Self {
+        if m1.strand == m2.strand {
+            return PairOrientation::FF;
+        }
+
+        let (fwd, rev) = if m1.strand == Strand::Forward {
+            (m1, m2)
+        } else {
+            (m2, m1)
+        };
+
+        if fwd.target_start < rev.target_end {
+            PairOrientation::FR
+        } else {
+            PairOrientation::RF
+        }
+    }
+}
+
+/// Insert-size distribution accumulator for paired-end mappings
+///
+/// Only pairs where both reads have a primary mapping to the same target contribute to the
+/// distribution. The insert size is the span from the leftmost mapped base to the rightmost,
+/// the same as the absolute SAM `TLEN`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InsertSizeStats {
+    histogram: BTreeMap<u32, u64>,
+    fr: u64,
+    rf: u64,
+    ff: u64,
+    unpaired: u64,
+    max_insert: Option<u32>,
+}
+
+impl InsertSizeStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ignore pairs with an insert size above `max_insert`
+    ///
+    /// Useful to keep chimeric or structurally variant pairs from dragging the mean around.
+    /// Ignored pairs are counted in [`unpaired`](Self::unpaired).
+    pub fn with_max_insert(mut self, max_insert: u32) -> Self {
+        self.max_insert = Some(max_insert);
+        self
+    }
+
+    /// Add the result of a single [`map_pair`](crate::Aligner::map_pair) call
+    ///
+    /// Returns the insert size and orientation of the pair if it contributed to the
+    /// distribution.
+    pub fn add_pair(
+        &mut self,
+        mappings1: &[Mapping],
+        mappings2: &[Mapping],
+    ) -> Option<(u32, PairOrientation)> {
+        let m1 = mappings1.iter().find(|m| m.is_primary);
+        let m2 = mappings2.iter().find(|m| m.is_primary);
+
+        let (Some(m1), Some(m2)) = (m1, m2) else {
+            self.unpaired += 1;
+            return None;
+        };
+
+        if m1.target_id != m2.target_id {
+            self.unpaired += 1;
+            return None;
+        }
+
+        let start = m1.target_start.min(m2.target_start);
+        let end = m1.target_end.max(m2.target_end);
+        let insert = (end - start) as u32;
+
+        if self.max_insert.is_some_and(|max| insert > max) {
+            self.unpaired += 1;
+            return None;
+        }
+
+        let orientation = PairOrientation::of(m1, m2);
+        self.add(insert, orientation);
+        Some((insert, orientation))
+    }
+
+    /// Add a single observation directly
+    pub fn add(&mut self, insert: u32, orientation: PairOrientation) {
+        *self.histogram.entry(insert).or_insert(0) += 1;
+        match orientation {
+            PairOrientation::FR => self.fr += 1,
+            PairOrientation::RF => self.rf += 1,
+            PairOrientation::FF => self.ff += 1,
+        }
+    }
+
+    /// Combine the observations of another accumulator into this one (e.g. one per thread)
+    pub fn merge(&mut self, other: &InsertSizeStats) {
+        for (insert, count) in &other.histogram {
+            *self.histogram.entry(*insert).or_insert(0) += count;
+        }
+        self.fr += other.fr;
+        self.rf += other.rf;
+        self.ff += other.ff;
+        self.unpaired += other.unpaired;
+    }
+
+    /// Number of pairs contributing to the distribution
+    pub fn count(&self) -> u64 {
+        self.histogram.values().sum()
+    }
+
+    /// Number of pairs that did not contribute (unmapped, different targets or over `max_insert`)
+    pub fn unpaired(&self) -> u64 {
+        self.unpaired
+    }
+
+    /// Insert size histogram as `insert size -> number of pairs`
+    pub fn histogram(&self) -> &BTreeMap<u32, u64> {
+        &self.histogram
+    }
+
+    /// Number of pairs observed in the given orientation
+    pub fn orientation_count(&self, orientation: PairOrientation) -> u64 {
+        match orientation {
+            PairOrientation::FR => self.fr,
+            PairOrientation::RF => self.rf,
+            PairOrientation::FF => self.ff,
+        }
+    }
+
+    /// The most frequently observed orientation, if any pairs have been seen
+    pub fn dominant_orientation(&self) -> Option<PairOrientation> {
+        [
+            PairOrientation::FR,
+            PairOrientation::RF,
+            PairOrientation::FF,
+        ]
+        .into_iter()
+        .filter(|o| self.orientation_count(*o) > 0)
+        .max_by_key(|o| self.orientation_count(*o))
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        let n = self.count();
+        if n == 0 {
+            return None;
+        }
+        let sum: f64 = self
+            .histogram
+            .iter()
+            .map(|(insert, count)| *insert as f64 * *count as f64)
+            .sum();
+        Some(sum / n as f64)
+    }
+
+    pub fn median(&self) -> Option<f64> {
+        median(&self.histogram)
+    }
+
+    /// Median absolute deviation from the median (unscaled)
+    pub fn mad(&self) -> Option<f64> {
+        let center = self.median()?;
+        // Deviations are kept doubled so half-integer medians stay exact
+        let mut deviations: BTreeMap<u32, u64> = BTreeMap::new();
+        for (insert, count) in &self.histogram {
+            let deviation = (2.0 * *insert as f64 - 2.0 * center).abs() as u32;
+            *deviations.entry(deviation).or_insert(0) += count;
+        }
+        median(&deviations).map(|d| d / 2.0)
+    }
+
+    /// A `max_frag_len` suitable for a second pass of [`map_pair`](crate::Aligner::map_pair):
+    /// the median plus `n_mads` scaled median absolute deviations.
+    ///
+    /// The MAD is scaled by 1.4826 so `n_mads` behaves like a number of standard deviations
+    /// for normally distributed insert sizes. Returns `None` if no pairs have been observed.
+    pub fn max_frag_len(&self, n_mads: f64) -> Option<usize> {
+        let center = self.median()?;
+        let mad = self.mad()?;
+        Some((center + n_mads * 1.4826 * mad).ceil() as usize)
+    }
+}
+
+/// Median of a weighted histogram, averaging the two middle values for even counts
+fn median(histogram: &BTreeMap<u32, u64>) -> Option<f64> {
+    let n: u64 = histogram.values().sum();
+    if n == 0 {
+        return None;
+    }
+    let lower = nth(histogram, (n - 1) / 2)?;
+    let upper = nth(histogram, n / 2)?;
+    Some((lower as f64 + upper as f64) / 2.0)
+}
+
+/// The `k`-th (0-based) smallest value in a weighted histogram
+fn nth(histogram: &BTreeMap<u32, u64>, k: u64) -> Option<u32> {
+    let mut seen = 0;
+    for (value, count) in histogram {
+        seen += count;
+        if seen > k {
+            return Some(*value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Aligner;
+
+    fn mapping(target_id: i32, start: i32, end: i32, strand: Strand) -> Mapping {
+        Mapping {
+            target_id,
+            target_start: start,
+            target_end: end,
+            strand,
+            is_primary: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_orientation() {
+        let fwd = mapping(0, 100, 150, Strand::Forward);
+        let rev = mapping(0, 300, 350, Strand::Reverse);
+        assert_eq!(PairOrientation::of(&fwd, &rev), PairOrientation::FR);
+        assert_eq!(PairOrientation::of(&rev, &fwd), PairOrientation::FR);
+
+        let fwd = mapping(0, 300, 350, Strand::Forward);
+        let rev = mapping(0, 100, 150, Strand::Reverse);
+        assert_eq!(PairOrientation::of(&fwd, &rev), PairOrientation::RF);
+
+        let rev2 = mapping(0, 300, 350, Strand::Reverse);
+        assert_eq!(PairOrientation::of(&rev, &rev2), PairOrientation::FF);
+    }
+
+    #[test]
+    fn test_summary_statistics() {
+        let mut stats = InsertSizeStats::new();
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.max_frag_len(3.0), None);
+
+        for insert in [200, 210, 220, 230, 1000] {
+            stats.add(insert, PairOrientation::FR);
+        }
+        stats.add(220, PairOrientation::RF);
+
+        assert_eq!(stats.count(), 6);
+        assert_eq!(stats.median(), Some(220.0));
+        // |x - 220| = 20, 10, 0, 0, 10, 780
+        assert_eq!(stats.mad(), Some(10.0));
+        assert!((stats.mean().unwrap() - 2080.0 / 6.0).abs() < 1e-9);
+        assert_eq!(stats.orientation_count(PairOrientation::FR), 5);
+        assert_eq!(stats.orientation_count(PairOrientation::RF), 1);
+        assert_eq!(stats.dominant_orientation(), Some(PairOrientation::FR));
+        assert_eq!(stats.max_frag_len(3.0), Some(265));
+
+        let mut other = InsertSizeStats::new();
+        other.add(240, PairOrientation::FF);
+        stats.merge(&other);
+        assert_eq!(stats.count(), 7);
+        assert_eq!(stats.orientation_count(PairOrientation::FF), 1);
+        assert_eq!(stats.histogram().get(&220), Some(&2));
+    }
+
+    #[test]
+    fn test_add_pair_filters() {
+        let mut stats = InsertSizeStats::new().with_max_insert(500);
+
+        let fwd = mapping(0, 100, 150, Strand::Forward);
+        let rev = mapping(0, 250, 300, Strand::Reverse);
+        assert_eq!(
+            stats.add_pair(std::slice::from_ref(&fwd), &[rev]),
+            Some((200, PairOrientation::FR))
+        );
+
+        // Different targets
+        let other_contig = mapping(1, 250, 300, Strand::Reverse);
+        assert_eq!(
+            stats.add_pair(std::slice::from_ref(&fwd), &[other_contig]),
+            None
+        );
+
+        // One read unmapped
+        assert_eq!(stats.add_pair(std::slice::from_ref(&fwd), &[]), None);
+
+        // Over max_insert
+        let far = mapping(0, 900, 950, Strand::Reverse);
+        assert_eq!(stats.add_pair(&[fwd], &[far]), None);
+
+        assert_eq!(stats.count(), 1);
+        assert_eq!(stats.unpaired(), 3);
+    }
+
+    #[test]
+    fn test_insert_size_from_map_pair() {
+        let aligner = Aligner::builder()
+            .sr()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+
+        let mut stats = InsertSizeStats::new();
+
+        let (m1, m2) = aligner
+            .map_pair(
+                b"ATTGCTGCCAAGTATTCGATGCATCTGTTACCCAGAGGTGCTCCTCACTA",
+                b"GATCCTCTCGGCCGATAGTCCAGCTCGTAGTTGCGACGAGAAGGCGAGAG",
+                false,
+                false,
+                Some(500),
+                None,
+                Some(b"chr1_chr1_pair"),
+            )
+            .unwrap();
+        // TLEN is 226 in test_data/pair_vs_genome.sam
+        assert_eq!(stats.add_pair(&m1, &m2), Some((226, PairOrientation::FR)));
+
+        let (m1, m2) = aligner
+            .map_pair(
+                b"TGCCGCACAACTTCCATATTGCTGGTTATTACTACGGTACTGACCGCGCG",
+                b"GATCCTCTCGGCCGATAGTCCAGCTCGTAGTTGCGACGAGAAGGCGAGAG",
+                false,
+                false,
+                Some(500),
+                None,
+                Some(b"chr2_chr1_unpair"),
+            )
+            .unwrap();
+        assert_eq!(stats.add_pair(&m1, &m2), None);
+
+        assert_eq!(stats.count(), 1);
+        assert_eq!(stats.unpaired(), 1);
+        assert_eq!(stats.median(), Some(226.0));
+        assert_eq!(stats.max_frag_len(3.0), Some(226));
+    }
+}
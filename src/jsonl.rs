@@ -0,0 +1,192 @@
+//! JSON Lines output of mappings
+//!
+//! With the `serde` feature, [`Mapping`](crate::Mapping), [`Alignment`](crate::Alignment),
+//! [`Strand`](crate::Strand), [`Junction`](crate::Junction) and
+//! [`AlignmentType`](crate::AlignmentType) implement `Serialize` and `Deserialize`.
+//! [`JsonlWriter`] writes one JSON object per line, [`JsonlReader`] reads them back.
+//!
+//! # Schema
+//!
+//! Field names are the struct field names, and only ever get added to: records written by
+//! an older version still deserialize, with missing `Mapping` fields left at their default.
+//!
+//! - Strands are `"+"` / `"-"`, alignment types `"primary"`, `"secondary"`, `"inversion"`
+//! - Missing values (`query_name`, `query_len`, `trans_strand`, `alignment`, ...) are `null`
+//! - `alignment.cigar` is a list of `[length, op]` pairs, `op` being the BAM op code (0-8)
+//!
+//! ```json
+//! {"query_name":"read1","query_len":200,"query_start":0,"query_end":200,"strand":"+",
+//!  "target_name":"MT_human","target_len":16569,"target_start":60,"target_end":260,
+//!  "target_id":0,"match_len":200,"block_len":200,"mapq":60,"is_primary":true,
+//!  "is_supplementary":false,"is_spliced":false,"trans_strand":null,"is_alt":false,
+//!  "is_inversion":false,"alignment":{"nm":0,"cigar":[[200,0]],"cigar_str":"200M",
+//!  "md":null,"cs":null,"alignment_score":400},"segment_id":0,"comment":null,
+//!  "query_qual":null}
+//! ```
+//!
+//! (wrapped here; each record is written on a single line)
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//! use minimap2::jsonl::JsonlWriter;
+//!
+//! let aligner = Aligner::builder().map_ont().with_cigar().with_index("ref.fa", None).unwrap();
+//! let mut writer = JsonlWriter::new(std::io::stdout().lock());
+//! let mappings = aligner.map(b"ACGGTAGAGAGGAAGCAAT", false, false, None, None, None).unwrap();
+//! writer.write_all(&mappings).unwrap();
+//! ```
+
+use std::io::{self, BufRead, Write};
+use std::marker::PhantomData;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Writes records as JSON Lines
+#[derive(Debug)]
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    /// Write to `writer`; wrap it in a `BufWriter` if it is unbuffered
+    pub fn new(writer: W) -> Self {
+        JsonlWriter { writer }
+    }
+
+    /// Write one record (a [`Mapping`](crate::Mapping), [`Junction`](crate::Junction), ...)
+    pub fn write<T: Serialize + ?Sized>(&mut self, record: &T) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")
+    }
+
+    /// Write every record of `records`
+    pub fn write_all<'a, T: Serialize + 'a>(
+        &mut self,
+        records: impl IntoIterator<Item = &'a T>,
+    ) -> io::Result<()> {
+        records
+            .into_iter()
+            .try_for_each(|record| self.write(record))
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flush and return the underlying writer
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads records written by [`JsonlWriter`], skipping blank lines
+#[derive(Debug)]
+pub struct JsonlReader<R: BufRead, T> {
+    reader: R,
+    line: String,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<R: BufRead, T: DeserializeOwned> JsonlReader<R, T> {
+    pub fn new(reader: R) -> Self {
+        JsonlReader {
+            reader,
+            line: String::new(),
+            _record: PhantomData,
+        }
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for JsonlReader<R, T> {
+    type Item = io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) => return None,
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) => return Some(serde_json::from_str(&self.line).map_err(io::Error::from)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Aligner, AlignmentType, Junction, Mapping, Strand};
+    use std::sync::Arc;
+
+    const QUERY: &[u8] = b"GTTTATGTAGCTTATTCTATCCAAAGCAATGCACTGAAAATGTCTCGACGGGCCCACACGCCCCATAAACAAATAGGTTTGGTCCTAGCCTTTCTATTAGCTCTTAGTGAGGTTACACATGCAAGCATCCCCGCCCCAGTGAGTCGCCCTCCAAGTCACTCTGACTAAGAGGAGCAAGCATCAAGCACGCAACAGCGCAG";
+
+    #[test]
+    fn round_trip() {
+        let aligner = Aligner::builder()
+            .map_ont()
+            .with_cigar()
+            .with_index("test_data/MT-human.fa", None)
+            .unwrap();
+        let mut mappings = aligner
+            .map(QUERY, true, true, None, None, Some(b"read1".as_slice()))
+            .unwrap();
+        assert!(!mappings.is_empty());
+        mappings.push(Mapping {
+            strand: Strand::Reverse,
+            trans_strand: Some(Strand::Forward),
+            ..Default::default()
+        });
+
+        let mut writer = JsonlWriter::new(Vec::new());
+        writer.write_all(&mappings).unwrap();
+        let out = writer.into_inner().unwrap();
+        assert_eq!(out.iter().filter(|&&b| b == b'\n').count(), mappings.len());
+
+        let read: Vec<Mapping> = JsonlReader::new(out.as_slice())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, mappings);
+    }
+
+    #[test]
+    fn schema() {
+        let json = serde_json::to_value(Mapping {
+            query_name: Some(Arc::new("q".to_string())),
+            strand: Strand::Reverse,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(json["query_name"], "q");
+        assert_eq!(json["strand"], "-");
+        assert!(json["query_len"].is_null());
+        assert!(json["alignment"].is_null());
+
+        let junction = Junction::new(None, 10, 20, None, 3, Strand::Forward);
+        let line = serde_json::to_string(&junction).unwrap();
+        assert_eq!(
+            line,
+            r#"{"target_name":null,"start":10,"end":20,"query_name":null,"score":3,"strand":"+"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&AlignmentType::Inversion).unwrap(),
+            r#""inversion""#
+        );
+
+        // Missing fields take their defaults, blank lines are skipped
+        let input = "\n{\"target_start\": 5, \"strand\": \"-\"}\n";
+        let read: Vec<Mapping> = JsonlReader::new(input.as_bytes())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].target_start, 5);
+        assert_eq!(read[0].strand, Strand::Reverse);
+
+        let bad: Vec<io::Result<Mapping>> =
+            JsonlReader::new("{\"strand\": \"?\"}".as_bytes()).collect();
+        assert!(bad[0].is_err());
+    }
+}
@@ -0,0 +1,1085 @@
+//! Splice junction aggregation across many reads
+//!
+//! [`Aligner::score_junctions`](crate::Aligner::score_junctions) reports the introns of a
+//! single spliced mapping. [`JunctionCollector`] pools those per-read junctions into one
+//! record per distinct site `(contig, start, end, strand)`, counting the number of reads
+//! supporting it, and writes the result back out in a form minimap2 can load again:
+//!
+//! - [`write_junc`](JunctionCollector::write_junc) writes the 6-column format of
+//!   `minimap2 --write-junc`, readable by [`read_junction`](crate::Aligner::read_junction)
+//!   and [`read_pass1`](crate::Aligner::read_pass1).
+//! - [`write_bed12`](JunctionCollector::write_bed12) writes one two-block BED12 line per
+//!   junction, readable by [`read_junction_lr`](crate::Aligner::read_junction_lr).
+//!
+//! Annotation held in memory rather than in files can be loaded with
+//! [`load_junctions`](crate::Aligner::load_junctions),
+//! [`load_pass1`](crate::Aligner::load_pass1) and
+//! [`load_transcripts`](crate::Aligner::load_transcripts), which check every
+//! [`JunctionRecord`] or [`TranscriptRecord`] against the index first.
+//!
+//! [`TwoPassSplicer`] builds on the collector to run minimap2's two-pass spliced alignment
+//! without leaving the process.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use minimap2::Aligner;
+//! use minimap2::junctions::JunctionCollector;
+//!
+//! let aligner = Aligner::builder()
+//!     .splice()
+//!     .with_cigar()
+//!     .with_index("reference.fa", None)
+//!     .expect("Unable to build index");
+//!
+//! let mut collector = JunctionCollector::new();
+//! for mapping in aligner.map_file("reads.fq", false, false).unwrap() {
+//!     collector.add_mapping(&aligner, &mapping);
+//! }
+//!
+//! collector
+//!     .write_junc(File::create("junctions.bed").unwrap())
+//!     .unwrap();
+//! aligner.read_pass1("junctions.bed").unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use minimap2_sys::{MM_F_CIGAR, MM_JUNC_ANNO, MM_JUNC_MISC};
+
+use crate::{Aligner, Built, Junction, Mapping, Strand};
+
+/// A distinct splice junction: an intron on a contig, in 0-based half-open coordinates
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JunctionSite {
+    pub target_name: Arc<String>,
+    pub start: u32,
+    pub end: u32,
+    pub strand: Strand,
+}
+
+/// Evidence collected for a [`JunctionSite`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct JunctionSupport {
+    /// Number of reads spanning the junction
+    pub reads: u32,
+    /// Best splice motif score seen for the junction (0-6, as reported by minimap2)
+    pub score: u32,
+}
+
+impl JunctionSupport {
+    fn merge(&mut self, other: &JunctionSupport) {
+        self.reads += other.reads;
+        self.score = self.score.max(other.score);
+    }
+}
+
+/// Accumulates splice junctions from many reads, keyed by [`JunctionSite`]
+///
+/// Sites are kept sorted by contig name, start, end and strand, which is also the order
+/// they are written in.
+#[derive(Debug, Clone, Default)]
+pub struct JunctionCollector {
+    sites: BTreeMap<JunctionSite, JunctionSupport>,
+}
+
+impl JunctionCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one read supporting `junction`
+    ///
+    /// Junctions without a target name cannot be written out and are ignored.
+    pub fn add(&mut self, junction: &Junction) {
+        let Some(target_name) = junction.target_name.as_ref() else {
+            return;
+        };
+
+        let site = JunctionSite {
+            target_name: Arc::clone(target_name),
+            start: junction.start,
+            end: junction.end,
+            strand: junction.strand,
+        };
+
+        self.sites.entry(site).or_default().merge(&JunctionSupport {
+            reads: 1,
+            score: junction.score,
+        });
+    }
+
+    /// Score the junctions of `mapping` with `aligner` and record them
+    ///
+    /// Secondary mappings are skipped so that each read is counted once per site. The
+    /// aligner must have been built with CIGAR output, see [`Aligner::score_junctions`].
+    pub fn add_mapping(&mut self, aligner: &Aligner<Built>, mapping: &Mapping) {
+        if !mapping.is_primary && !mapping.is_supplementary {
+            return;
+        }
+
+        let mut junctions = Vec::new();
+        aligner.score_junctions(mapping, &mut junctions);
+        self.extend(junctions.iter());
+    }
+
+    /// Combine the counts from another collector, e.g. one filled by a different thread
+    pub fn merge(&mut self, other: &JunctionCollector) {
+        for (site, support) in other.sites.iter() {
+            self.sites.entry(site.clone()).or_default().merge(support);
+        }
+    }
+
+    /// Number of distinct junction sites
+    pub fn len(&self) -> usize {
+        self.sites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+
+    pub fn get(&self, site: &JunctionSite) -> Option<&JunctionSupport> {
+        self.sites.get(site)
+    }
+
+    /// Keep only the sites for which `f` returns true
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&JunctionSite, &JunctionSupport) -> bool,
+    {
+        self.sites.retain(|site, support| f(site, support));
+    }
+
+    /// Iterate over the sites in sorted order
+    pub fn iter(&self) -> btree_map::Iter<'_, JunctionSite, JunctionSupport> {
+        self.sites.iter()
+    }
+
+    /// The sites as records for [`Aligner::load_junctions`] or [`Aligner::load_pass1`],
+    /// scored by motif score
+    pub fn to_records(&self) -> Vec<JunctionRecord> {
+        self.sites
+            .iter()
+            .map(|(site, support)| JunctionRecord {
+                contig: site.target_name.to_string(),
+                start: site.start,
+                end: site.end,
+                strand: Some(site.strand),
+                score: support.score as i32,
+            })
+            .collect()
+    }
+
+    /// Write the junctions in the format of `minimap2 --write-junc`
+    ///
+    /// Columns are contig, start, end, supporting read count, motif score and strand.
+    /// [`Aligner::read_pass1`] only keeps junctions with a motif score of at least 5.
+    pub fn write_junc<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (site, support) in self.sites.iter() {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                site.target_name, site.start, site.end, support.reads, support.score, site.strand
+            )?;
+        }
+        writer.flush()
+    }
+
+    /// Write the junctions as BED12, one two-block feature per junction
+    ///
+    /// Each block is the single base flanking the intron, so the intron between the blocks
+    /// is exactly the junction. The BED score is the supporting read count capped at 1000
+    /// and the name is `JUNC<n>` numbered in output order.
+    pub fn write_bed12<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (i, (site, support)) in self.sites.iter().enumerate() {
+            // A junction at the very start of a contig has no base before it
+            let Some(chrom_start) = site.start.checked_sub(1) else {
+                continue;
+            };
+            let chrom_end = site.end + 1;
+            writeln!(
+                writer,
+                "{}\t{}\t{}\tJUNC{}\t{}\t{}\t{}\t{}\t0\t2\t1,1\t0,{}",
+                site.target_name,
+                chrom_start,
+                chrom_end,
+                i + 1,
+                support.reads.min(1000),
+                site.strand,
+                chrom_start,
+                chrom_end,
+                chrom_end - chrom_start - 1
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+impl<'a> Extend<&'a Junction> for JunctionCollector {
+    fn extend<I: IntoIterator<Item = &'a Junction>>(&mut self, iter: I) {
+        for junction in iter {
+            self.add(junction);
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a JunctionCollector {
+    type Item = (&'a JunctionSite, &'a JunctionSupport);
+    type IntoIter = btree_map::Iter<'a, JunctionSite, JunctionSupport>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.sites.iter()
+    }
+}
+
+/// Errors from loading junction annotation into the index
+#[derive(Debug)]
+pub enum JunctionError {
+    /// The aligner has no index to load into
+    NoIndex,
+    /// The path contains a NUL byte and cannot be passed to minimap2
+    InvalidPath(String),
+    /// A record names a contig that is not in the index
+    UnknownContig(String),
+    /// A record's interval is empty or reversed
+    InvalidInterval {
+        contig: String,
+        start: u32,
+        end: u32,
+    },
+    /// A record extends past the end of its contig
+    OutOfBounds {
+        contig: String,
+        end: u32,
+        contig_len: u32,
+    },
+    /// Writing the records for minimap2 failed
+    Io(io::Error),
+    /// minimap2 failed to load the file at `path`, with its return code (-1 if the file
+    /// cannot be opened)
+    Load { path: String, code: i32 },
+}
+
+impl std::fmt::Display for JunctionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JunctionError::NoIndex => write!(f, "No index"),
+            JunctionError::InvalidPath(path) => write!(f, "Invalid path: {path:?}"),
+            JunctionError::UnknownContig(contig) => {
+                write!(f, "Contig {contig} is not in the index")
+            }
+            JunctionError::InvalidInterval { contig, start, end } => {
+                write!(f, "Invalid interval {contig}:{start}-{end}")
+            }
+            JunctionError::OutOfBounds {
+                contig,
+                end,
+                contig_len,
+            } => write!(
+                f,
+                "Interval end {end} is past the end of {contig} (length {contig_len})"
+            ),
+            JunctionError::Io(e) => write!(f, "Unable to write junction records: {e}"),
+            JunctionError::Load { path, code } => {
+                write!(f, "minimap2 failed to load {path} (code {code})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for JunctionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JunctionError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for JunctionError {
+    fn from(e: io::Error) -> Self {
+        JunctionError::Io(e)
+    }
+}
+
+/// A single intron, as read by [`Aligner::read_junction`] and [`Aligner::read_pass1`]
+///
+/// Coordinates are 0-based half-open. A `strand` of `None` is written as `.` (unknown).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JunctionRecord {
+    pub contig: String,
+    pub start: u32,
+    pub end: u32,
+    pub strand: Option<Strand>,
+    /// Only used by [`Aligner::load_pass1`], which drops records scoring below 5
+    pub score: i32,
+}
+
+/// A transcript's exon structure, as read by [`Aligner::read_junction_lr`]
+///
+/// Exons are 0-based half-open intervals. The introns between consecutive exons are what
+/// minimap2 uses; single-exon transcripts are accepted but contribute nothing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranscriptRecord {
+    pub name: Option<String>,
+    pub contig: String,
+    pub strand: Option<Strand>,
+    pub exons: Vec<(u32, u32)>,
+}
+
+impl JunctionRecord {
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t.\t{}\t{}",
+            self.contig,
+            self.start,
+            self.end,
+            self.score,
+            strand_char(self.strand)
+        )
+    }
+}
+
+impl TranscriptRecord {
+    /// Exons sorted by position, as BED12 requires
+    fn sorted_exons(&self) -> Vec<(u32, u32)> {
+        let mut exons = self.exons.clone();
+        exons.sort_unstable();
+        exons
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let exons = self.sorted_exons();
+        let (Some(first), Some(last)) = (exons.first(), exons.last()) else {
+            return Ok(());
+        };
+        let (start, end) = (first.0, last.1);
+
+        let sizes: Vec<String> = exons.iter().map(|(s, e)| (e - s).to_string()).collect();
+        let starts: Vec<String> = exons.iter().map(|(s, _)| (s - start).to_string()).collect();
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t0\t{}\t{}\t{}\t0\t{}\t{}\t{}",
+            self.contig,
+            start,
+            end,
+            self.name.as_deref().unwrap_or("."),
+            strand_char(self.strand),
+            start,
+            end,
+            exons.len(),
+            sizes.join(","),
+            starts.join(",")
+        )
+    }
+}
+
+fn strand_char(strand: Option<Strand>) -> char {
+    match strand {
+        Some(Strand::Forward) => '+',
+        Some(Strand::Reverse) => '-',
+        None => '.',
+    }
+}
+
+/// Convert a path for minimap2's C loaders
+pub(crate) fn c_path(path: &str) -> Result<CString, JunctionError> {
+    CString::new(path).map_err(|_| JunctionError::InvalidPath(path.to_string()))
+}
+
+/// Convert the path of a BED file for minimap2's BED readers, which do not report files
+/// they cannot open (`mm_idx_jjump_read` crashes on them)
+pub(crate) fn c_bed_path(path: &str) -> Result<CString, JunctionError> {
+    let c_path = c_path(path)?;
+    if path != "-" && File::open(path).is_err() {
+        return Err(JunctionError::Load {
+            path: path.to_string(),
+            code: -1,
+        });
+    }
+    Ok(c_path)
+}
+
+static RECORD_FILE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// minimap2 only loads annotation from files, so write the records to a temporary one,
+/// hand its path to `load` and remove it again
+fn via_temp_file<F, L>(write: F, load: L) -> Result<(), JunctionError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+    L: FnOnce(&str) -> Result<(), JunctionError>,
+{
+    let path = std::env::temp_dir().join(format!(
+        "minimap2-rs-junc-{}-{}.bed",
+        std::process::id(),
+        RECORD_FILE_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let ret = (|| {
+        let mut writer = BufWriter::new(File::create(&path)?);
+        write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| JunctionError::InvalidPath(path.display().to_string()))?;
+        load(path_str)
+    })();
+
+    let _ = std::fs::remove_file(&path);
+    ret
+}
+
+impl Aligner<Built> {
+    /// Load annotated junctions into the index from memory.
+    /// Equivalent to [`read_junction`](Self::read_junction) on a file with these records.
+    ///
+    /// All records are checked against the index before anything is loaded.
+    pub fn load_junctions(&self, records: &[JunctionRecord]) -> Result<(), JunctionError> {
+        self.load_jumps(records, MM_JUNC_ANNO, -1)
+    }
+
+    /// Load junctions from a previous run into the index from memory, keeping those with
+    /// a score of at least 5. Equivalent to [`read_pass1`](Self::read_pass1).
+    pub fn load_pass1(&self, records: &[JunctionRecord]) -> Result<(), JunctionError> {
+        self.load_jumps(records, MM_JUNC_MISC, 5)
+    }
+
+    /// Load transcript exon structures into the index from memory.
+    /// Equivalent to [`read_junction_lr`](Self::read_junction_lr) on a BED12 file.
+    ///
+    /// All records are checked against the index before anything is loaded.
+    pub fn load_transcripts(&self, records: &[TranscriptRecord]) -> Result<(), JunctionError> {
+        for record in records {
+            for &(start, end) in record.exons.iter() {
+                self.validate_interval(&record.contig, start, end)?;
+            }
+            // Overlapping exons would give negative intron lengths
+            for pair in record.sorted_exons().windows(2) {
+                if pair[1].0 < pair[0].1 {
+                    return Err(JunctionError::InvalidInterval {
+                        contig: record.contig.clone(),
+                        start: pair[1].0,
+                        end: pair[0].1,
+                    });
+                }
+            }
+        }
+
+        via_temp_file(
+            |writer| records.iter().try_for_each(|record| record.write(writer)),
+            |path| self.read_junction_lr(path),
+        )
+    }
+
+    pub(crate) fn load_jumps(
+        &self,
+        records: &[JunctionRecord],
+        flag: u32,
+        min_sc: i32,
+    ) -> Result<(), JunctionError> {
+        for record in records {
+            self.validate_interval(&record.contig, record.start, record.end)?;
+        }
+
+        via_temp_file(
+            |writer| records.iter().try_for_each(|record| record.write(writer)),
+            |path| self.read_jjump(path, flag, min_sc),
+        )
+    }
+
+    /// Check that `contig` is in the index and `start..end` is a non-empty interval on it
+    pub(crate) fn validate_interval(
+        &self,
+        contig: &str,
+        start: u32,
+        end: u32,
+    ) -> Result<(), JunctionError> {
+        if !self.has_index() {
+            return Err(JunctionError::NoIndex);
+        }
+
+        let Some((idx, id)) = self.find_contig(contig) else {
+            return Err(JunctionError::UnknownContig(contig.to_string()));
+        };
+
+        if start >= end {
+            return Err(JunctionError::InvalidInterval {
+                contig: contig.to_string(),
+                start,
+                end,
+            });
+        }
+
+        let contig_len = unsafe { (*(*idx).seq.offset(id as isize)).len };
+        if end > contig_len {
+            return Err(JunctionError::OutOfBounds {
+                contig: contig.to_string(),
+                end,
+                contig_len,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs minimap2's two-pass spliced alignment within one process
+///
+/// The first pass maps reads as usual and collects their junctions. The junctions passing
+/// the support and motif score thresholds are then loaded into the index as pass-1 jumps
+/// (as `minimap2 --pass1` does) and the reads are mapped again.
+///
+/// Junctions are loaded into the index itself, so every aligner sharing that index sees
+/// them after [`load_junctions`](Self::load_junctions).
+///
+/// ```no_run
+/// use minimap2::Aligner;
+/// use minimap2::junctions::TwoPassSplicer;
+///
+/// let aligner = Aligner::builder()
+///     .splice()
+///     .with_cigar()
+///     .with_index("reference.fa", None)
+///     .expect("Unable to build index");
+///
+/// let mut splicer = TwoPassSplicer::new(&aligner).unwrap().with_min_reads(2);
+/// let mappings = splicer.map_file("reads.fq", false, false).unwrap();
+/// ```
+pub struct TwoPassSplicer<'a> {
+    aligner: &'a Aligner<Built>,
+    collector: JunctionCollector,
+    min_reads: u32,
+    min_score: u32,
+    loaded: bool,
+}
+
+impl<'a> TwoPassSplicer<'a> {
+    /// Wrap a built aligner. It must have an index and CIGAR output enabled.
+    ///
+    /// Loading the junctions changes the index for good: `aligner`, its clones and any
+    /// other aligner sharing the index map with the pass-1 junctions from then on.
+    ///
+    /// Defaults match `minimap2 --pass1`: every junction seen at least once with a motif
+    /// score of at least 5 is kept.
+    pub fn new(aligner: &'a Aligner<Built>) -> Result<Self, &'static str> {
+        if !aligner.has_index() {
+            return Err("No index");
+        }
+
+        if (aligner.mapopt.flag & MM_F_CIGAR as i64) == 0 {
+            return Err("CIGAR must be set to score junctions");
+        }
+
+        Ok(Self {
+            aligner,
+            collector: JunctionCollector::new(),
+            min_reads: 1,
+            min_score: 5,
+            loaded: false,
+        })
+    }
+
+    /// Minimum number of supporting reads for a junction to be kept
+    pub fn with_min_reads(mut self, min_reads: u32) -> Self {
+        self.min_reads = min_reads;
+        self
+    }
+
+    /// Minimum splice motif score (0-6) for a junction to be kept
+    pub fn with_min_score(mut self, min_score: u32) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Map `seq` and collect its junctions. Returns the first-pass mappings.
+    pub fn first_pass(
+        &mut self,
+        seq: &[u8],
+        cs: bool,
+        md: bool,
+        query_name: Option<&[u8]>,
+    ) -> Result<Vec<Mapping>, &'static str> {
+        if self.loaded {
+            return Err("Junctions already loaded, first pass is over");
+        }
+
+        let mappings = self.aligner.map(seq, cs, md, None, None, query_name)?;
+        for mapping in mappings.iter() {
+            self.collector.add_mapping(self.aligner, mapping);
+        }
+        Ok(mappings)
+    }
+
+    /// All junctions collected so far, before filtering
+    pub fn collector(&self) -> &JunctionCollector {
+        &self.collector
+    }
+
+    /// The junctions passing the support and score thresholds
+    pub fn filtered(&self) -> JunctionCollector {
+        let mut filtered = self.collector.clone();
+        filtered.retain(|_, support| {
+            support.reads >= self.min_reads && support.score >= self.min_score
+        });
+        filtered
+    }
+
+    /// Load the filtered junctions into the index as pass-1 jumps.
+    /// Returns the number of junctions loaded.
+    pub fn load_junctions(&mut self) -> Result<usize, &'static str> {
+        if self.loaded {
+            return Err("Junctions already loaded");
+        }
+
+        let filtered = self.filtered();
+        if filtered.is_empty() {
+            // Nothing to load, the second pass is the same as the first
+            self.loaded = true;
+            return Ok(0);
+        }
+
+        self.aligner
+            .load_jumps(&filtered.to_records(), MM_JUNC_MISC, -1)
+            .map_err(|_| "Unable to load pass-1 junctions")?;
+
+        self.loaded = true;
+        Ok(filtered.len())
+    }
+
+    /// Map `seq` against the index with the pass-1 junctions loaded
+    pub fn second_pass(
+        &self,
+        seq: &[u8],
+        cs: bool,
+        md: bool,
+        query_name: Option<&[u8]>,
+    ) -> Result<Vec<Mapping>, &'static str> {
+        if !self.loaded {
+            return Err("Junctions not loaded, call load_junctions first");
+        }
+
+        self.aligner.map(seq, cs, md, None, None, query_name)
+    }
+
+    /// Run both passes over a FASTA/FASTQ file, which is read twice.
+    /// Returns the second-pass mappings.
+    #[cfg(feature = "map-file")]
+    pub fn map_file(
+        &mut self,
+        file: &str,
+        cs: bool,
+        md: bool,
+    ) -> Result<Vec<Mapping>, &'static str> {
+        if self.loaded {
+            return Err("Junctions already loaded, first pass is over");
+        }
+
+        for mapping in self.aligner.map_file(file, cs, md)?.iter() {
+            self.collector.add_mapping(self.aligner, mapping);
+        }
+        self.load_junctions()?;
+        self.aligner.map_file(file, cs, md)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn junction(target: &str, start: u32, end: u32, score: u32, strand: Strand) -> Junction {
+        Junction::new(
+            Some(Arc::new(target.to_string())),
+            start,
+            end,
+            Some(Arc::new("read".to_string())),
+            score,
+            strand,
+        )
+    }
+
+    fn site(target: &str, start: u32, end: u32, strand: Strand) -> JunctionSite {
+        JunctionSite {
+            target_name: Arc::new(target.to_string()),
+            start,
+            end,
+            strand,
+        }
+    }
+
+    #[test]
+    fn test_collector_counts_sites() {
+        let mut collector = JunctionCollector::new();
+        collector.extend(&[
+            junction("chr1", 100, 200, 6, Strand::Forward),
+            junction("chr1", 100, 200, 4, Strand::Forward),
+            junction("chr1", 100, 200, 6, Strand::Reverse),
+            junction("chr2", 50, 80, 3, Strand::Forward),
+        ]);
+        collector.add(&Junction::new(None, 1, 2, None, 6, Strand::Forward));
+
+        assert_eq!(collector.len(), 3);
+        assert_eq!(
+            collector.get(&site("chr1", 100, 200, Strand::Forward)),
+            Some(&JunctionSupport { reads: 2, score: 6 })
+        );
+        assert_eq!(
+            collector.get(&site("chr1", 100, 200, Strand::Reverse)),
+            Some(&JunctionSupport { reads: 1, score: 6 })
+        );
+
+        let mut other = JunctionCollector::new();
+        other.add(&junction("chr2", 50, 80, 5, Strand::Forward));
+        collector.merge(&other);
+        assert_eq!(
+            collector.get(&site("chr2", 50, 80, Strand::Forward)),
+            Some(&JunctionSupport { reads: 2, score: 5 })
+        );
+    }
+
+    #[test]
+    fn test_collector_writers() {
+        let mut collector = JunctionCollector::new();
+        collector.extend(&[
+            junction("chr2", 50, 80, 3, Strand::Forward),
+            junction("chr1", 100, 200, 6, Strand::Reverse),
+            junction("chr1", 100, 200, 6, Strand::Reverse),
+        ]);
+
+        let mut junc = Vec::new();
+        collector.write_junc(&mut junc).unwrap();
+        assert_eq!(
+            String::from_utf8(junc).unwrap(),
+            "chr1\t100\t200\t2\t6\t-\nchr2\t50\t80\t1\t3\t+\n"
+        );
+
+        let mut bed = Vec::new();
+        collector.write_bed12(&mut bed).unwrap();
+        assert_eq!(
+            String::from_utf8(bed).unwrap(),
+            "chr1\t99\t201\tJUNC1\t2\t-\t99\t201\t0\t2\t1,1\t0,101\n\
+             chr2\t49\t81\tJUNC2\t1\t+\t49\t81\t0\t2\t1,1\t0,31\n"
+        );
+    }
+
+    #[cfg(feature = "map-file")]
+    #[test]
+    fn test_collector_from_cdna_reads() {
+        let aligner = Aligner::builder()
+            .splice()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+
+        let mut collector = JunctionCollector::new();
+        for mapping in aligner
+            .map_file("test_data/cDNA_reads.fq", false, false)
+            .unwrap()
+        {
+            collector.add_mapping(&aligner, &mapping);
+        }
+
+        // cdna.fwd is 100M260N100M80N100M80N100M at chr1:541 in test_data/cDNA_vs_genome.sam
+        let introns: Vec<(u32, u32)> = collector
+            .iter()
+            .map(|(site, _)| (site.start, site.end))
+            .collect();
+        for intron in [(640, 900), (1000, 1080), (1180, 1260)] {
+            assert!(introns.contains(&intron), "missing intron {intron:?}");
+        }
+
+        let dir = std::env::temp_dir();
+        let junc_path = dir.join("minimap2_rs_collector.junc.bed");
+        let bed12_path = dir.join("minimap2_rs_collector.bed12");
+        collector
+            .write_junc(std::fs::File::create(&junc_path).unwrap())
+            .unwrap();
+        collector
+            .write_bed12(std::fs::File::create(&bed12_path).unwrap())
+            .unwrap();
+
+        assert!(aligner.read_junction(junc_path.to_str().unwrap()).is_ok());
+        assert!(aligner.read_pass1(junc_path.to_str().unwrap()).is_ok());
+        assert!(
+            aligner
+                .read_junction_lr(bed12_path.to_str().unwrap())
+                .is_ok()
+        );
+
+        std::fs::remove_file(junc_path).unwrap();
+        std::fs::remove_file(bed12_path).unwrap();
+    }
+
+    #[test]
+    fn test_two_pass_splicer() {
+        let aligner = Aligner::builder()
+            .splice()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+
+        let mut splicer = TwoPassSplicer::new(&aligner).unwrap().with_min_score(0);
+
+        let transcript = std::fs::read_to_string("test_data/cDNA.fasta").unwrap();
+        let transcript: String = transcript.lines().filter(|l| !l.starts_with('>')).collect();
+        let first = splicer
+            .first_pass(transcript.as_bytes(), false, false, Some(b"fwd"))
+            .unwrap();
+        assert!(!first.is_empty());
+        assert!(
+            splicer
+                .second_pass(transcript.as_bytes(), false, false, None)
+                .is_err()
+        );
+
+        let loaded = splicer.load_junctions().unwrap();
+        assert_eq!(loaded, splicer.filtered().len());
+        assert!(loaded >= 3);
+        assert!(splicer.load_junctions().is_err());
+        assert!(
+            splicer
+                .first_pass(transcript.as_bytes(), false, false, None)
+                .is_err()
+        );
+
+        let second = splicer
+            .second_pass(transcript.as_bytes(), false, false, Some(b"fwd"))
+            .unwrap();
+        assert_eq!(second[0].target_start, first[0].target_start);
+        assert_eq!(second[0].target_end, first[0].target_end);
+    }
+
+    #[cfg(feature = "map-file")]
+    #[test]
+    fn test_two_pass_splicer_map_file() {
+        let aligner = Aligner::builder()
+            .splice()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+
+        // The two reads share no intron, so requiring two supporting reads loads nothing
+        let mut splicer = TwoPassSplicer::new(&aligner)
+            .unwrap()
+            .with_min_reads(2)
+            .with_min_score(0);
+        let mappings = splicer
+            .map_file("test_data/cDNA_reads.fq", false, false)
+            .unwrap();
+
+        // Same placement as test_data/cDNA_vs_genome.sam
+        let fwd = mappings
+            .iter()
+            .find(|m| m.query_name.as_deref().map(String::as_str) == Some("cdna.fwd"))
+            .unwrap();
+        assert_eq!(fwd.target_start, 540);
+        assert_eq!(
+            fwd.alignment.as_ref().unwrap().cigar_str.as_deref(),
+            Some("100M260N100M80N100M80N100M")
+        );
+
+        assert!(splicer.filtered().is_empty());
+        assert!(
+            splicer
+                .map_file("test_data/cDNA_reads.fq", false, false)
+                .is_err()
+        );
+
+        // With the defaults the junctions are loaded and the second pass uses them
+        let aligner = Aligner::builder()
+            .splice()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+        let mut splicer = TwoPassSplicer::new(&aligner).unwrap().with_min_score(0);
+        let mappings = splicer
+            .map_file("test_data/cDNA_reads.fq", false, false)
+            .unwrap();
+        assert!(splicer.filtered().len() >= 3);
+        // Still the placement of test_data/cDNA_vs_genome.sam
+        for (query_name, cigar) in [
+            ("cdna.fwd", "100M260N100M80N100M80N100M"),
+            ("cdna.rev", "100M80N100M620N100M80N100M"),
+        ] {
+            let mapping = mappings
+                .iter()
+                .find(|m| m.query_name.as_deref().map(String::as_str) == Some(query_name))
+                .unwrap();
+            assert_eq!(mapping.target_start, 540);
+            assert_eq!(
+                mapping.alignment.as_ref().unwrap().cigar_str.as_deref(),
+                Some(cigar)
+            );
+        }
+
+        let no_cigar = Aligner::builder()
+            .splice()
+            .with_index_threads(1)
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+        assert!(TwoPassSplicer::new(&no_cigar).is_err());
+    }
+
+    #[test]
+    fn test_record_formats() {
+        let junction = JunctionRecord {
+            contig: "chr1".to_string(),
+            start: 640,
+            end: 900,
+            strand: None,
+            score: 6,
+        };
+        let mut out = Vec::new();
+        junction.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "chr1\t640\t900\t.\t6\t.\n");
+
+        // Exons out of order are sorted for BED12
+        let transcript = TranscriptRecord {
+            name: Some("tx1".to_string()),
+            contig: "chr1".to_string(),
+            strand: Some(Strand::Forward),
+            exons: vec![(900, 1000), (540, 640), (1080, 1180)],
+        };
+        let mut out = Vec::new();
+        transcript.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "chr1\t540\t1180\ttx1\t0\t+\t540\t1180\t0\t3\t100,100,100\t0,360,540\n"
+        );
+    }
+
+    #[test]
+    fn test_load_records() {
+        let aligner = Aligner::builder()
+            .splice()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+
+        let record = |contig: &str, start: u32, end: u32| JunctionRecord {
+            contig: contig.to_string(),
+            start,
+            end,
+            strand: Some(Strand::Forward),
+            score: 6,
+        };
+
+        aligner
+            .load_junctions(&[record("chr1", 640, 900), record("chr1", 1000, 1080)])
+            .unwrap();
+        aligner.load_pass1(&[record("chr2", 100, 200)]).unwrap();
+        aligner
+            .load_transcripts(&[TranscriptRecord {
+                name: None,
+                contig: "chr1".to_string(),
+                strand: Some(Strand::Forward),
+                exons: vec![(540, 640), (900, 1000), (1080, 1180), (1260, 1360)],
+            }])
+            .unwrap();
+
+        assert!(matches!(
+            aligner.load_junctions(&[record("chr3", 10, 20)]),
+            Err(JunctionError::UnknownContig(contig)) if contig == "chr3"
+        ));
+        assert!(matches!(
+            aligner.load_junctions(&[record("chr2", 100, 500)]),
+            Err(JunctionError::OutOfBounds {
+                contig_len: 460,
+                ..
+            })
+        ));
+        assert!(matches!(
+            aligner.load_pass1(&[record("chr1", 900, 640)]),
+            Err(JunctionError::InvalidInterval { .. })
+        ));
+        assert!(matches!(
+            aligner.load_transcripts(&[TranscriptRecord {
+                name: None,
+                contig: "chr1".to_string(),
+                strand: None,
+                exons: vec![(540, 640), (600, 700)],
+            }]),
+            Err(JunctionError::InvalidInterval {
+                start: 600,
+                end: 640,
+                ..
+            })
+        ));
+        assert!(matches!(
+            aligner.read_junction("does/not/exist.bed"),
+            Err(JunctionError::Load { .. })
+        ));
+        assert_eq!(
+            aligner
+                .load_junctions(&[record("chr3", 10, 20)])
+                .unwrap_err()
+                .to_string(),
+            "Contig chr3 is not in the index"
+        );
+    }
+
+    #[test]
+    fn test_validate_interval_multi_part() {
+        // chr1 (1720bp) fills the first part, chr2 (460bp) goes to the second
+        let mut builder = Aligner::builder().splice().with_index_threads(1);
+        builder.idxopt.batch_size = 1000;
+        let aligner = builder.with_index("test_data/genome.fa", None).unwrap();
+        assert_eq!(aligner.idx_parts.len(), 2);
+
+        aligner.validate_interval("chr1", 640, 1720).unwrap();
+        aligner.validate_interval("chr2", 100, 460).unwrap();
+        assert!(matches!(
+            aligner.validate_interval("chr2", 100, 500),
+            Err(JunctionError::OutOfBounds {
+                contig_len: 460,
+                ..
+            })
+        ));
+        assert!(matches!(
+            aligner.validate_interval("chr3", 10, 20),
+            Err(JunctionError::UnknownContig(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_records_multi_part() {
+        let mut builder = Aligner::builder().splice().with_index_threads(1);
+        builder.idxopt.batch_size = 1000;
+        let aligner = builder.with_index("test_data/genome.fa", None).unwrap();
+        assert_eq!(aligner.idx_parts.len(), 2);
+
+        aligner
+            .load_junctions(&[JunctionRecord {
+                contig: "chr2".to_string(),
+                start: 100,
+                end: 200,
+                strand: Some(Strand::Forward),
+                score: 6,
+            }])
+            .unwrap();
+
+        // chr2 is the only contig of the second part
+        let jumps = |part: usize| {
+            let mut n = 0;
+            unsafe {
+                minimap2_sys::mm_idx_jump_get(aligner.idx_parts[part].idx, 0, 0, -1, &mut n);
+            }
+            n
+        };
+        assert_eq!(jumps(0), 0);
+        assert!(jumps(1) > 0);
+    }
+}
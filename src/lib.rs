@@ -56,6 +56,7 @@
 //! assert_eq!(hits.unwrap().len(), 1);
 //! ```
 
+use std::borrow::Cow;
 use std::cell::RefCell;
 
 use std::ffi::{CStr, CString};
@@ -69,6 +70,13 @@ use std::os::unix::ffi::OsStrExt;
 use libc::c_void;
 use minimap2_sys::*;
 
+use flags::{FlagError, IdxFlags, MapFlags};
+use junctions::{JunctionError, c_bed_path, c_path};
+use options::{IndexOptions, MapOptions, OptionError};
+#[cfg(feature = "map-file")]
+use preprocess::{PreprocessStats, Preprocessor};
+use quality::QualityOptions;
+
 pub use minimap2_sys as ffi;
 
 #[cfg(feature = "map-file")]
@@ -77,6 +85,33 @@ use needletail::parse_fastx_file;
 #[cfg(feature = "htslib")]
 pub mod htslib;
 
+#[cfg(feature = "serde")]
+pub mod jsonl;
+
+#[cfg(feature = "tokio")]
+pub mod async_aligner;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
+pub mod annotation;
+pub mod base_mods;
+pub mod cli;
+pub mod config;
+pub mod coverage;
+pub mod flags;
+pub mod insert_size;
+pub mod junctions;
+#[cfg(feature = "noodles")]
+pub mod noodles;
+pub mod options;
+pub mod preprocess;
+pub mod quality;
+pub mod sam;
+#[cfg(feature = "htslib")]
+pub mod sorted_bam;
+pub mod stats;
+
 /// Alias for mm_mapop_t
 pub type MapOpt = mm_mapopt_t;
 
@@ -107,10 +142,13 @@ static MAP10K: &CStr = c"map10k";
 static CDNA: &CStr = c"cdna";
 
 /// Strand enum
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Strand {
     #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "+"))]
     Forward,
+    #[cfg_attr(feature = "serde", serde(rename = "-"))]
     Reverse,
 }
 
@@ -174,6 +212,7 @@ impl From<Preset> for *const libc::c_char {
 
 /// Represents a splice junction and its associated score
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Junction {
     pub target_name: Option<Arc<String>>,
     pub start: u32,
@@ -204,6 +243,11 @@ impl Junction {
 
 /// Alignment type
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum AlignmentType {
     Primary,
     Secondary,
@@ -212,6 +256,7 @@ pub enum AlignmentType {
 
 /// Alignment struct when alignment flag is set
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Alignment {
     /// The edit distance as calculated in cmappy.h: `h->NM = r->blen - r->mlen + r->p->n_ambi;`
     pub nm: i32,
@@ -224,6 +269,11 @@ pub struct Alignment {
 
 /// Mapping result
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Mapping {
     // The query sequence name.
     pub query_name: Option<Arc<String>>,
@@ -243,9 +293,22 @@ pub struct Mapping {
     pub is_supplementary: bool,
     pub is_spliced: bool,
     pub trans_strand: Option<Strand>,
+    /// The target is an ALT contig, see [`Aligner::read_alt_contigs`]
+    pub is_alt: bool,
+    /// The mapping is an inversion found between two aligned hits (`tp:A:I` or `tp:A:i`)
+    pub is_inversion: bool,
     pub alignment: Option<Alignment>,
     // Segment ID for paired-end reads (0 = read1, 1 = read2). Always 0 for single-end.
     pub segment_id: u8,
+    /// FASTA/FASTQ comment of the query, set by [`Aligner::map_file`] with
+    /// [`Aligner::with_copy_comment`] and copied into SAM/BAM records
+    pub comment: Option<Arc<String>>,
+    /// Phred+33 quality string of the query, set by [`Aligner::map_with_qual`]
+    pub query_qual: Option<Arc<String>>,
+    /// The query with the bases masked by [`QualityOptions::mask_below`] replaced by `N`,
+    /// set by [`Aligner::map_with_qual`] when masking changed the read. `NM`, `MD` and `cs`
+    /// describe this sequence, so the SAM/BAM converters write it as SEQ
+    pub masked_seq: Option<Arc<String>>,
 }
 
 // Thread local buffer (memory management) for minimap2
@@ -356,6 +419,9 @@ pub struct Aligner<S: BuilderState> {
     /// Whether to add soft clipping to CIGAR result
     pub cigar_clipping: bool,
 
+    // Presets applied, in order, for provenance
+    presets: Vec<String>,
+
     // State of the builder
     _state: S,
 }
@@ -371,6 +437,7 @@ impl Default for Aligner<Unset> {
             idx_parts: Vec::new(),
             idx_reader: None,
             cigar_clipping: false,
+            presets: Vec::new(),
             _state: Unset,
         }
     }
@@ -395,6 +462,38 @@ impl Aligner<()> {
 
         aligner
     }
+
+    /// Default options with minimap2 presets applied in order, as repeated `-x` do.
+    /// Returns the first unknown preset name as the error.
+    pub(crate) fn from_presets<I, P>(presets: I) -> Result<Aligner<PresetSet>, String>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        let mut aligner = Aligner::builder();
+        for preset in presets {
+            let preset = preset.as_ref();
+            let c_preset = CString::new(preset).map_err(|_| preset.to_string())?;
+            let ret =
+                unsafe { mm_set_opt(c_preset.as_ptr(), &mut aligner.idxopt, &mut aligner.mapopt) };
+            if ret < 0 {
+                return Err(preset.to_string());
+            }
+            aligner.presets.push(preset.to_string());
+        }
+
+        Ok(Aligner {
+            idxopt: aligner.idxopt,
+            mapopt: aligner.mapopt,
+            threads: aligner.threads,
+            idx: aligner.idx,
+            idx_parts: aligner.idx_parts,
+            idx_reader: aligner.idx_reader,
+            cigar_clipping: aligner.cigar_clipping,
+            presets: aligner.presets,
+            _state: PresetSet,
+        })
+    }
 }
 
 impl Aligner<Unset> {
@@ -602,12 +701,15 @@ impl Aligner<Unset> {
     /// Presets should be called before any other options are set, as they change multiple
     /// options at once.
     pub fn preset(mut self, preset: Preset) -> Aligner<PresetSet> {
+        let name: *const libc::c_char = preset.into();
         unsafe {
             let ret1 = mm_set_opt(std::ptr::null(), &mut self.idxopt, &mut self.mapopt);
             assert_eq!(ret1, 0);
-            let ret2 = mm_set_opt(preset.into(), &mut self.idxopt, &mut self.mapopt);
+            let ret2 = mm_set_opt(name, &mut self.idxopt, &mut self.mapopt);
             assert_eq!(ret2, 0);
         };
+        let name = unsafe { CStr::from_ptr(name) };
+        self.presets = vec![name.to_string_lossy().into_owned()];
 
         Aligner {
             idxopt: self.idxopt,
@@ -617,6 +719,7 @@ impl Aligner<Unset> {
             idx_parts: self.idx_parts,
             idx_reader: self.idx_reader,
             cigar_clipping: self.cigar_clipping,
+            presets: self.presets,
             _state: PresetSet,
         }
     }
@@ -625,6 +728,23 @@ impl Aligner<Unset> {
     // If you make a change copy it below!
 }
 
+impl<S: BuilderState> Aligner<S> {
+    /// The minimap2 presets this aligner was configured with, in the order they were applied
+    pub fn presets(&self) -> &[String] {
+        &self.presets
+    }
+
+    /// The mapping flags currently set (`mapopt.flag`)
+    pub fn map_flags(&self) -> MapFlags {
+        MapFlags::of(&self.mapopt)
+    }
+
+    /// The indexing flags currently set (`idxopt.flag`)
+    pub fn idx_flags(&self) -> IdxFlags {
+        IdxFlags::of(&self.idxopt)
+    }
+}
+
 impl<S> Aligner<S>
 where
     S: BuilderState + AcceptsParams,
@@ -635,12 +755,25 @@ where
     /// Aligner::builder().map_ont().with_cigar();
     /// ```
     ///
-    pub fn with_cigar(mut self) -> Self {
-        // Make sure MM_F_CIGAR flag isn't already set
-        assert!((self.mapopt.flag & MM_F_CIGAR as i64) == 0);
+    /// # Panics
+    /// If CIGAR generation is already enabled, e.g. by calling this twice; see
+    /// [`try_with_cigar`](Self::try_with_cigar).
+    pub fn with_cigar(self) -> Self {
+        self.try_with_cigar().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`with_cigar`](Self::with_cigar), but returns an error if CIGAR generation is
+    /// already enabled
+    /// ```
+    /// # use minimap2::*;
+    /// let aligner = Aligner::builder().map_ont().try_with_cigar().unwrap();
+    /// assert!(aligner.try_with_cigar().is_err());
+    /// ```
+    pub fn try_with_cigar(mut self) -> Result<Self, FlagError> {
+        self.check_flag_unset(MapFlags::CIGAR, "with_cigar")?;
 
         self.mapopt.flag |= MM_F_CIGAR as i64 | MM_F_OUT_CS as i64;
-        self
+        Ok(self)
     }
 
     pub fn with_cigar_clipping(mut self) -> Self {
@@ -648,20 +781,69 @@ where
         self
     }
 
-    pub fn with_sam_out(mut self) -> Self {
-        // Make sure MM_F_CIGAR flag isn't already set
-        assert!((self.mapopt.flag & MM_F_OUT_SAM as i64) == 0);
+    /// # Panics
+    /// If SAM output is already enabled; see [`try_with_sam_out`](Self::try_with_sam_out).
+    pub fn with_sam_out(self) -> Self {
+        self.try_with_sam_out().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`with_sam_out`](Self::with_sam_out), but returns an error if SAM output is
+    /// already enabled
+    pub fn try_with_sam_out(mut self) -> Result<Self, FlagError> {
+        self.check_flag_unset(MapFlags::OUT_SAM, "with_sam_out")?;
 
         self.mapopt.flag |= MM_F_OUT_SAM as i64;
-        self
+        Ok(self)
+    }
+
+    /// # Panics
+    /// If `SAM_HIT_ONLY` is already set; see
+    /// [`try_with_sam_hit_only`](Self::try_with_sam_hit_only).
+    pub fn with_sam_hit_only(self) -> Self {
+        self.try_with_sam_hit_only()
+            .unwrap_or_else(|e| panic!("{e}"))
     }
 
-    pub fn with_sam_hit_only(mut self) -> Self {
-        // Make sure MM_F_CIGAR flag isn't already set
-        assert!((self.mapopt.flag & MM_F_SAM_HIT_ONLY as i64) == 0);
+    /// Like [`with_sam_hit_only`](Self::with_sam_hit_only), but returns an error if
+    /// `SAM_HIT_ONLY` is already set
+    pub fn try_with_sam_hit_only(mut self) -> Result<Self, FlagError> {
+        self.check_flag_unset(MapFlags::SAM_HIT_ONLY, "with_sam_hit_only")?;
 
         self.mapopt.flag |= MM_F_SAM_HIT_ONLY as i64;
-        self
+        Ok(self)
+    }
+
+    /// Copy FASTA/FASTQ comments into the output (`minimap2 -y`), e.g. the `MM`/`ML`
+    /// base modification tags of ONT and PacBio reads, see [`base_mods`]
+    ///
+    /// # Panics
+    /// If comments are already copied; see
+    /// [`try_with_copy_comment`](Self::try_with_copy_comment).
+    pub fn with_copy_comment(self) -> Self {
+        self.try_with_copy_comment()
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`with_copy_comment`](Self::with_copy_comment), but returns an error if
+    /// comments are already copied
+    pub fn try_with_copy_comment(mut self) -> Result<Self, FlagError> {
+        self.check_flag_unset(MapFlags::COPY_COMMENT, "with_copy_comment")?;
+
+        self.mapopt.flag |= MM_F_COPY_COMMENT as i64;
+        Ok(self)
+    }
+
+    // Setting a flag twice usually means two parts of the caller disagree on the options
+    fn check_flag_unset(&self, flag: MapFlags, method: &'static str) -> Result<(), FlagError> {
+        let flags = self.map_flags();
+        if flags.contains(flag) {
+            return Err(FlagError {
+                method,
+                flag,
+                flags,
+            });
+        }
+        Ok(())
     }
 
     /// Sets the gap open penalty for minimap2.
@@ -680,6 +862,18 @@ where
         self
     }
 
+    /// Sets the fraction by which hits to ALT contigs are down-weighted when choosing the
+    /// primary mapping (minimap2 --alt-drop, default 0.15)
+    /// ```
+    /// # use minimap2::*;
+    /// Aligner::builder().map_hifi().with_alt_drop_fraction(0.2).unwrap();
+    /// ```
+    ///
+    /// Only has an effect once ALT contigs are loaded, see [`Aligner::read_alt_contigs`].
+    pub fn with_alt_drop_fraction(self, fraction: f32) -> Result<Self, OptionError> {
+        self.with_map_options(&MapOptions::new().alt_drop(fraction))
+    }
+
     /// Sets the number of threads minimap2 will use for building the index
     /// ```
     /// # use minimap2::*;
@@ -700,14 +894,48 @@ where
 
     // Check options
     /// Check if the options are valid - Maps to mm_check_opt in minimap2
+    ///
+    /// See [`validate_opts`](Self::validate_opts) for which option is invalid.
     pub fn check_opts(&self) -> Result<(), &'static str> {
-        let result = unsafe { mm_check_opt(&self.idxopt, &self.mapopt) };
+        self.validate_opts().map_err(|_| "Invalid options")
+    }
 
-        if result == 0 {
-            Ok(())
-        } else {
-            Err("Invalid options")
-        }
+    /// Check if the options are valid, naming the offending option if not.
+    /// A superset of mm_check_opt in minimap2, see [`options::validate`].
+    pub fn validate_opts(&self) -> Result<(), OptionError> {
+        options::validate(&self.idxopt, &self.mapopt)
+    }
+
+    /// Apply typed mapping options on top of the current ones (usually the preset's)
+    /// and validate the result
+    /// ```
+    /// # use minimap2::*;
+    /// # use minimap2::options::MapOptions;
+    /// Aligner::builder()
+    ///     .map_ont()
+    ///     .with_map_options(&MapOptions::new().best_n(1).min_chain_score(60))
+    ///     .unwrap();
+    /// ```
+    pub fn with_map_options(mut self, options: &MapOptions) -> Result<Self, OptionError> {
+        options.apply_to(&mut self.mapopt);
+        self.validate_opts()?;
+        Ok(self)
+    }
+
+    /// Apply typed indexing options on top of the current ones (usually the preset's)
+    /// and validate the result. Must be called before the index is built.
+    /// ```
+    /// # use minimap2::*;
+    /// # use minimap2::options::IndexOptions;
+    /// Aligner::builder()
+    ///     .map_ont()
+    ///     .with_index_options(&IndexOptions::new().kmer(19).window(15))
+    ///     .unwrap();
+    /// ```
+    pub fn with_index_options(mut self, options: &IndexOptions) -> Result<Self, OptionError> {
+        options.apply(&mut self.idxopt);
+        self.validate_opts()?;
+        Ok(self)
     }
 
     /// Set index parameters for minimap2 using builder pattern
@@ -808,10 +1036,13 @@ where
                     break; // No more parts to read
                 }
                 
+                // Build the name table now: minimap2 builds it lazily on the first name
+                // lookup, which would modify an index that may already be shared
+                mm_idx_index_name(idx_part);
+
                 if first_idx.is_none() {
                     // Use the first part for mapping option updates and API compatibility
                     mm_mapopt_update(&mut self.mapopt, idx_part);
-                    mm_idx_index_name(idx_part);
                     // Store the first part - don't duplicate it in both places
                     let first_part = Arc::new(idx_part.into());
                     first_idx = Some(Arc::clone(&first_part));
@@ -842,6 +1073,7 @@ where
             idx_parts: self.idx_parts,
             idx_reader: Some(Arc::new(unsafe { *idx_reader })),
             cigar_clipping: self.cigar_clipping,
+            presets: self.presets,
             _state: Built,
         };
         // make sure that the names of the references are all short enough to fit in
@@ -960,6 +1192,9 @@ where
             .iter()
             .map(|s| std::ffi::CString::new(s.clone()).expect("Invalid ID"))
             .collect();
+        // mm_idx_str takes arrays of C string pointers
+        let mut seq_ptrs: Vec<*const libc::c_char> = seqs.iter().map(|s| s.as_ptr()).collect();
+        let mut id_ptrs: Vec<*const libc::c_char> = ids.iter().map(|s| s.as_ptr()).collect();
 
         let idx = MaybeUninit::new(unsafe {
             mm_idx_str(
@@ -968,12 +1203,13 @@ where
                 (self.idxopt.flag & 1) as i32,
                 self.idxopt.bucket_bits as i32,
                 seqs.len() as i32,
-                seqs.as_ptr() as *mut *const libc::c_char,
-                ids.as_ptr() as *mut *const libc::c_char,
+                seq_ptrs.as_mut_ptr(),
+                id_ptrs.as_mut_ptr(),
             )
         });
 
         let mm_idx = unsafe { idx.assume_init() };
+        unsafe { rebuild_name_table(mm_idx) };
         let idx_arc = Arc::new(mm_idx.into());
         self.idx = Some(Arc::clone(&idx_arc));
         self.idx_parts = vec![idx_arc]; // Sequence-based indexes are always single-part
@@ -988,6 +1224,7 @@ where
             idx_parts: self.idx_parts,
             idx_reader: None,
             cigar_clipping: self.cigar_clipping,
+            presets: self.presets,
             _state: Built,
         };
 
@@ -1000,91 +1237,247 @@ where
     /// Presets should be called before any other options are set, as they change multiple
     /// options at once.
     pub fn additional_preset(mut self, preset: Preset) -> Self {
+        let name: *const libc::c_char = preset.into();
         unsafe {
-            let ret = mm_set_opt(preset.into(), &mut self.idxopt, &mut self.mapopt);
+            let ret = mm_set_opt(name, &mut self.idxopt, &mut self.mapopt);
             assert_eq!(ret, 0);
         };
+        let name = unsafe { CStr::from_ptr(name) };
+        self.presets.push(name.to_string_lossy().into_owned());
 
         self
     }
 }
 
 impl Aligner<Built> {
+    /// Create a variant of this aligner with different mapping options, sharing the index.
+    ///
+    /// `f` edits a copy of the mapping options, which are then updated from the index like
+    /// minimap2 does after loading it (`mm_mapopt_update`) and validated. The original aligner
+    /// is untouched, so variants can be used alongside it, e.g. for a strict and a
+    /// permissive pass over one index. If `f` changes `mid_occ_frac` but not `mid_occ`, the
+    /// occurrence cutoff is recomputed for the new fraction.
+    /// ```
+    /// # use minimap2::*;
+    /// let aligner = Aligner::builder().map_ont().with_index("test_data/MT-human.fa", None).unwrap();
+    /// let strict = aligner.with_mapopt(|opt| {
+    ///     opt.min_chain_score = 100;
+    ///     opt.best_n = 1;
+    /// }).unwrap();
+    /// assert_eq!(strict.mapopt.min_chain_score, 100);
+    /// assert!(aligner.with_mapopt(|opt| opt.pri_ratio = 2.0).is_err());
+    /// ```
+    pub fn with_mapopt<F>(&self, f: F) -> Result<Aligner<Built>, OptionError>
+    where
+        F: FnOnce(&mut MapOpt),
+    {
+        let mut aligner = self.clone();
+        f(&mut aligner.mapopt);
+
+        if aligner.mapopt.mid_occ_frac != self.mapopt.mid_occ_frac
+            && aligner.mapopt.mid_occ == self.mapopt.mid_occ
+        {
+            aligner.mapopt.mid_occ = 0;
+        }
+        if let Some(idx) = aligner.idx.as_ref() {
+            unsafe { mm_mapopt_update(&mut aligner.mapopt, idx.idx) };
+        }
+
+        options::validate(&aligner.idxopt, &aligner.mapopt)?;
+        Ok(aligner)
+    }
+
     /// Load splice/junc data from `bed_path` into the underlying `mm_idx_t`.
     /// Equivalent to --junc-bed <bed_path> in minimap2.
-    pub fn read_junction_lr(&self, bed_path: &str) -> Result<(), i32> {
-        let idx: *mut mm_idx_t = self.idx.as_ref().unwrap().idx as *mut _;
-
-        let c_bed = CString::new(bed_path).map_err(|_| -1)?;
-        // call into C
-        let ret = unsafe { mm_idx_bed_read(idx, c_bed.as_ptr(), 1 as libc::c_int) };
-        if ret == 0 {
-            Ok(())
-        } else {
-            println!("Failed to load the junction BED file");
-            Err(ret)
+    ///
+    /// See [`load_transcripts`](Self::load_transcripts) to load in-memory records instead.
+    pub fn read_junction_lr(&self, bed_path: &str) -> Result<(), JunctionError> {
+        let parts = self.idx_parts_mut()?;
+
+        let c_bed = c_bed_path(bed_path)?;
+        for idx in parts {
+            // call into C
+            let ret = unsafe { mm_idx_bed_read(idx, c_bed.as_ptr(), 1 as libc::c_int) };
+            if ret != 0 {
+                return Err(JunctionError::Load {
+                    path: bed_path.to_string(),
+                    code: ret,
+                });
+            }
         }
+        Ok(())
     }
 
     /// Load splice/junc data from `bed_path` into the underlying `mm_idx_t`.
     /// Equivalent to -j <bed_path> in minimap2.
-    pub fn read_junction(&self, bed_path: &str) -> Result<(), i32> {
-        let idx: *mut mm_idx_t = self.idx.as_ref().unwrap().idx as *mut _;
-
-        let c_bed = CString::new(bed_path).map_err(|_| -1)?;
-        // call into C
-        let ret = unsafe {
-            mm_idx_jjump_read(
-                idx,
-                c_bed.as_ptr(),
-                MM_JUNC_ANNO as libc::c_int,
-                -1 as libc::c_int,
-            )
-        };
-        if ret == 0 {
-            Ok(())
-        } else {
-            println!("Failed to load the jump BED file");
-            Err(ret)
+    ///
+    /// See [`load_junctions`](Self::load_junctions) to load in-memory records instead.
+    pub fn read_junction(&self, bed_path: &str) -> Result<(), JunctionError> {
+        self.read_jjump(bed_path, MM_JUNC_ANNO, -1)
+    }
+
+    /// Load junctions found by a previous run from `bed_path`, keeping those with a
+    /// score of at least 5. Equivalent to --pass1 <bed_path> in minimap2.
+    ///
+    /// See [`load_pass1`](Self::load_pass1) to load in-memory records instead.
+    pub fn read_pass1(&self, bed_path: &str) -> Result<(), JunctionError> {
+        self.read_jjump(bed_path, MM_JUNC_MISC, 5)
+    }
+
+    /// Load jumps from `bed_path` tagged with `flag` (`MM_JUNC_ANNO` or `MM_JUNC_MISC`),
+    /// keeping those with a score of at least `min_sc` (-1 keeps everything).
+    pub(crate) fn read_jjump(
+        &self,
+        bed_path: &str,
+        flag: u32,
+        min_sc: i32,
+    ) -> Result<(), JunctionError> {
+        let parts = self.idx_parts_mut()?;
+
+        let c_bed = c_bed_path(bed_path)?;
+        for idx in parts {
+            // call into C
+            let ret = unsafe {
+                mm_idx_jjump_read(
+                    idx,
+                    c_bed.as_ptr(),
+                    flag as libc::c_int,
+                    min_sc as libc::c_int,
+                )
+            };
+            if ret != 0 {
+                return Err(JunctionError::Load {
+                    path: bed_path.to_string(),
+                    code: ret,
+                });
+            }
         }
+        Ok(())
     }
 
+    /// Load splice site scores from `file_path`. Equivalent to --spsc <file_path> in minimap2.
+    pub fn read_splice_scores(&self, file_path: &str) -> Result<(), JunctionError> {
+        let parts = self.idx_parts_mut()?;
+
+        let c_filepath = c_path(file_path)?;
+        for idx in parts {
+            unsafe {
+                mm_idx_spsc_read(idx, c_filepath.as_ptr(), mm_max_spsc_bonus(&self.mapopt));
+            };
+
+            if unsafe { (*idx).spsc.is_null() } {
+                return Err(JunctionError::Load {
+                    path: file_path.to_string(),
+                    code: -1,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The index parts as mutable pointers, for loaders that annotate them in place
     ///
-    pub fn read_pass1(&self, bed_path: &str) -> Result<(), i32> {
-        let idx: *mut mm_idx_t = self.idx.as_ref().unwrap().idx as *mut _;
+    /// Annotation is loaded into every part, as minimap2 does for each part it reads.
+    fn idx_parts_mut(&self) -> Result<Vec<*mut mm_idx_t>, JunctionError> {
+        if !self.has_index() {
+            return Err(JunctionError::NoIndex);
+        }
+        Ok(self.idx_parts.iter().map(|part| part.idx).collect())
+    }
 
-        let c_bed = CString::new(bed_path).map_err(|_| -1)?;
-        // call into C
-        let ret = unsafe {
-            mm_idx_jjump_read(
-                idx,
-                c_bed.as_ptr(),
-                MM_JUNC_MISC as libc::c_int,
-                5 as libc::c_int,
-            )
-        };
-        if ret == 0 {
-            Ok(())
-        } else {
-            println!("Failed to load the pass-1 jump BED file");
-            Err(ret)
+    /// Find contig `name` in the index, returning the index part that holds it and its id
+    /// within that part
+    pub(crate) fn find_contig(&self, name: &str) -> Option<(*const mm_idx_t, i32)> {
+        self.idx_parts.iter().find_map(|part| {
+            let idx = part.idx as *const mm_idx_t;
+            contig_id(idx, name).map(|id| (idx, id))
+        })
+    }
+
+    /// Mark the contigs listed in `path` as ALT contigs. Equivalent to `--alt <path>` in minimap2.
+    ///
+    /// The file has one contig name per line (only the first word is used). Names not in
+    /// the index are ignored. Returns the number of contigs newly marked.
+    ///
+    /// Hits to ALT contigs have their score reduced by the
+    /// [ALT drop fraction](Aligner::with_alt_drop_fraction) when the primary mapping is
+    /// picked, so the primary assembly is preferred.
+    pub fn read_alt_contigs(&self, path: &str) -> Result<usize, &'static str> {
+        if !self.has_index() {
+            return Err("No index");
         }
+
+        let c_path = CString::new(path).map_err(|_| "Invalid path")?;
+        let mut n_alt = 0;
+        for part in self.idx_parts.iter() {
+            let before = count_alt(part.idx);
+            let ret = unsafe { mm_idx_alt_read(part.idx, c_path.as_ptr()) };
+            if ret < 0 {
+                return Err("Unable to read ALT contig list");
+            }
+            // minimap2 sets n_alt to the number of names it found in this file, which
+            // drops contigs marked before and counts names listed twice
+            let after = count_alt(part.idx);
+            unsafe { (*part.idx).n_alt = after as i32 };
+            n_alt += after - before;
+        }
+        Ok(n_alt)
     }
 
-    pub fn read_splice_scores(&self, file_path: &str) -> Result<(), i32> {
-        let idx: *mut mm_idx_t = self.idx.as_ref().unwrap().idx as *mut _;
+    /// Mark the contigs named in `names` as ALT contigs,
+    /// see [`read_alt_contigs`](Self::read_alt_contigs).
+    ///
+    /// Names not in the index are ignored. Returns the number of contigs newly marked.
+    pub fn load_alt_contigs<I, S>(&self, names: I) -> Result<usize, &'static str>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        if !self.has_index() {
+            return Err("No index");
+        }
 
-        let c_filepath = CString::new(file_path).map_err(|_| -1)?;
-        unsafe {
-            mm_idx_spsc_read(idx, c_filepath.as_ptr(), mm_max_spsc_bonus(&self.mapopt));
-        };
+        let names: Vec<S> = names.into_iter().collect();
+        let mut n_alt = 0;
+        for part in self.idx_parts.iter() {
+            let idx = part.idx;
+            for name in names.iter() {
+                let Some(id) = contig_id(idx, name.as_ref()) else {
+                    continue;
+                };
+                unsafe {
+                    let seq = (*idx).seq.offset(id as isize);
+                    if (*seq).is_alt == 0 {
+                        (*seq).is_alt = 1;
+                        (*idx).n_alt += 1;
+                        n_alt += 1;
+                    }
+                }
+            }
+        }
+        Ok(n_alt)
+    }
 
-        if unsafe { (*idx).spsc == std::ptr::null_mut() } {
-            println!("Failed to load the splice score file");
-            Err(-1)
-        } else {
-            Ok(())
+    /// Whether the contig with id `target_id` is marked as an ALT contig
+    ///
+    /// For an index in several parts, ids number the contigs of all parts in order: the
+    /// first contig of the second part follows the last contig of the first part.
+    /// [`Mapping::target_id`] is the id within its own part; use [`Mapping::is_alt`] there.
+    pub fn is_alt_contig(&self, target_id: i32) -> bool {
+        if target_id < 0 {
+            return false;
+        }
+        let mut id = target_id as u32;
+        for part in self.idx_parts.iter() {
+            let idx = part.idx;
+            unsafe {
+                if id < (*idx).n_seq {
+                    return (*(*idx).seq.offset(id as isize)).is_alt != 0;
+                }
+                id -= (*idx).n_seq;
+            }
         }
+        false
     }
 
     /// Returns the number of sequences in the index
@@ -1473,8 +1866,13 @@ impl Aligner<Built> {
                         is_supplementary,
                         is_spliced,
                         trans_strand,
+                        is_alt: reg.is_alt() != 0,
+                        is_inversion: reg.inv() != 0,
                         alignment,
                         segment_id: 0, // Single-end mapping
+                        comment: None,
+                        query_qual: None,
+                        masked_seq: None,
                     });
                     libc::free(reg.p as *mut c_void);
                 }
@@ -1501,7 +1899,8 @@ impl Aligner<Built> {
     ///
     /// This method uses minimap2's `mm_map_frag` function internally to perform
     /// paired-end alignment, which considers the expected fragment length and
-    /// orientation of read pairs.
+    /// orientation of read pairs. It is a thin wrapper over
+    /// [`map_fragment`](Self::map_fragment) with two segments.
     ///
     /// # Parameters
     /// * `seq1` - First read sequence (read 1)
@@ -1547,6 +1946,67 @@ impl Aligner<Built> {
         extra_flags: Option<&[u64]>,
         query_name: Option<&[u8]>,
     ) -> Result<(Vec<Mapping>, Vec<Mapping>), &'static str> {
+        // Make sure sequences are not empty
+        if seq1.is_empty() {
+            return Err("Sequence 1 is empty");
+        }
+        if seq2.is_empty() {
+            return Err("Sequence 2 is empty");
+        }
+
+        let mut mappings =
+            self.map_fragment(&[seq1, seq2], cs, md, max_frag_len, extra_flags, query_name)?;
+
+        let mappings2 = mappings.pop().unwrap();
+        let mappings1 = mappings.pop().unwrap();
+        Ok((mappings1, mappings2))
+    }
+
+    /// Aligns all segments of a multi-segment fragment to the index.
+    ///
+    /// This is the general form of [`map_pair`](Self::map_pair), for data such as linked reads
+    /// or Hi-C where a fragment has more than two segments. Segments are mapped jointly with
+    /// minimap2's `mm_map_frag`, up to `MM_MAX_SEG` (255) segments per fragment.
+    ///
+    /// As in minimap2, `pe_ori` is only applied to two-segment fragments.
+    ///
+    /// # Parameters
+    /// * `seqs` - The segment sequences, in order
+    /// * `cs` - Whether to output CS tag
+    /// * `md` - Whether to output MD tag
+    /// * `max_frag_len` - Maximum fragment length. If None, uses default.
+    /// * `extra_flags` - Extra flags to pass to minimap2
+    /// * `query_name` - Name of the fragment
+    ///
+    /// # Returns
+    /// One `Vec<Mapping>` per segment, in the same order as `seqs`. Each mapping's
+    /// `segment_id` is the index of its segment.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use minimap2::Aligner;
+    ///
+    /// let aligner = Aligner::builder()
+    ///     .sr()
+    ///     .with_cigar()
+    ///     .with_index("reference.fa", None)
+    ///     .expect("Unable to build index");
+    ///
+    /// let segments: [&[u8]; 3] = [b"ACGTACGTACGTACGT", b"TGCATGCATGCATGCA", b"GGGAAACCCTTTGGGA"];
+    /// let mappings = aligner
+    ///     .map_fragment(&segments, false, false, None, None, Some(b"fragment_001"))
+    ///     .expect("Unable to align");
+    /// assert_eq!(mappings.len(), 3);
+    /// ```
+    pub fn map_fragment(
+        &self,
+        seqs: &[&[u8]],
+        cs: bool,
+        md: bool,
+        max_frag_len: Option<usize>,
+        extra_flags: Option<&[u64]>,
+        query_name: Option<&[u8]>,
+    ) -> Result<Vec<Vec<Mapping>>, &'static str> {
         use std::os::raw::c_int;
 
         // Make sure index is set
@@ -1554,12 +2014,17 @@ impl Aligner<Built> {
             return Err("No index");
         }
 
-        // Make sure sequences are not empty
-        if seq1.is_empty() {
-            return Err("Sequence 1 is empty");
+        if seqs.is_empty() {
+            return Err("No segments");
         }
-        if seq2.is_empty() {
-            return Err("Sequence 2 is empty");
+
+        if seqs.len() > MM_MAX_SEG as usize {
+            return Err("Too many segments");
+        }
+
+        // Make sure sequences are not empty
+        if seqs.iter().any(|seq| seq.is_empty()) {
+            return Err("Sequence is empty");
         }
 
         let qname_cstring;
@@ -1601,48 +2066,47 @@ impl Aligner<Built> {
 
         // Determine which reads need to be reverse-complemented based on pe_ori
         // pe_ori encoding: bit-0 for reverse read2, bit-1 for reverse read1
-        let revcomp_read1 = map_opt.pe_ori >= 0 && ((map_opt.pe_ori >> 1) & 1) != 0;
-        let revcomp_read2 = map_opt.pe_ori >= 0 && (map_opt.pe_ori & 1) != 0;
+        // Like minimap2, this only applies to pairs
+        let n_segs = seqs.len();
+        let revcomp_flags: Vec<bool> = (0..n_segs)
+            .map(|seg_id| {
+                n_segs == 2 && map_opt.pe_ori >= 0 && ((map_opt.pe_ori >> (1 - seg_id)) & 1) != 0
+            })
+            .collect();
 
         // Reverse complement sequences if needed
-        let seq1_rc: Vec<u8>;
-        let seq2_rc: Vec<u8>;
-        let seq1_mapped: &[u8] = if revcomp_read1 {
-            seq1_rc = reverse_complement(seq1);
-            &seq1_rc
-        } else {
-            seq1
-        };
-        let seq2_mapped: &[u8] = if revcomp_read2 {
-            seq2_rc = reverse_complement(seq2);
-            &seq2_rc
-        } else {
-            seq2
-        };
+        let seqs_rc: Vec<Option<Vec<u8>>> = seqs
+            .iter()
+            .zip(revcomp_flags.iter())
+            .map(|(seq, revcomp)| revcomp.then(|| reverse_complement(seq)))
+            .collect();
+        let seqs_mapped: Vec<&[u8]> = seqs
+            .iter()
+            .zip(seqs_rc.iter())
+            .map(|(seq, rc)| rc.as_deref().unwrap_or(seq))
+            .collect();
 
         // Prepare arrays for mm_map_frag
-        let seqs: [&[u8]; 2] = [seq1_mapped, seq2_mapped];
-        let qlens: [c_int; 2] = [seq1.len() as c_int, seq2.len() as c_int];
-        let seq_ptrs: [*const ::std::os::raw::c_char; 2] = [
-            seq1_mapped.as_ptr() as *const ::std::os::raw::c_char,
-            seq2_mapped.as_ptr() as *const ::std::os::raw::c_char,
-        ];
+        let qlens: Vec<c_int> = seqs.iter().map(|seq| seq.len() as c_int).collect();
+        let seq_ptrs: Vec<*const ::std::os::raw::c_char> = seqs_mapped
+            .iter()
+            .map(|seq| seq.as_ptr() as *const ::std::os::raw::c_char)
+            .collect();
 
-        let (mappings1, mappings2) = BUF.with_borrow_mut(|buf| {
-            let mut all_mappings1 = Vec::new();
-            let mut all_mappings2 = Vec::new();
+        let mappings = BUF.with_borrow_mut(|buf| {
+            let mut all_mappings: Vec<Vec<Mapping>> = vec![Vec::new(); n_segs];
 
             // Map against all index parts
             for idx_part in &self.idx_parts {
-                let mut n_regs: [c_int; 2] = [0, 0];
-                let mut regs: [*mut mm_reg1_t; 2] = [std::ptr::null_mut(), std::ptr::null_mut()];
+                let mut n_regs: Vec<c_int> = vec![0; n_segs];
+                let mut regs: Vec<*mut mm_reg1_t> = vec![std::ptr::null_mut(); n_segs];
 
                 let km: *mut libc::c_void = unsafe { mm_tbuf_get_km(buf.get_buf()) };
 
                 unsafe {
                     mm_map_frag(
                         &**idx_part.as_ref() as *const mm_idx_t,
-                        2, // n_segs = 2 for paired-end
+                        n_segs as c_int,
                         qlens.as_ptr(),
                         seq_ptrs.as_ptr() as *mut *const ::std::os::raw::c_char,
                         n_regs.as_mut_ptr(),
@@ -1653,14 +2117,11 @@ impl Aligner<Built> {
                     );
                 }
 
-                // Determine which segments need coordinate flipping
-                let revcomp_flags: [bool; 2] = [revcomp_read1, revcomp_read2];
-
-                // Process results for both segments
-                for seg_id in 0..2usize {
+                // Process results for all segments
+                for seg_id in 0..n_segs {
                     let seg_n_regs = n_regs[seg_id];
                     let seg_regs = regs[seg_id];
-                    let seq = seqs[seg_id];
+                    let seq = seqs_mapped[seg_id];
 
                     let mut mappings = Vec::with_capacity(seg_n_regs as usize);
 
@@ -1874,19 +2335,19 @@ impl Aligner<Built> {
                                 is_supplementary,
                                 is_spliced,
                                 trans_strand,
+                                is_alt: reg.is_alt() != 0,
+                                is_inversion: reg.inv() != 0,
                                 alignment,
                                 segment_id: seg_id as u8,
+                                comment: None,
+                                query_qual: None,
+                                masked_seq: None,
                             });
                             libc::free(reg.p as *mut c_void);
                         }
                     }
 
-                    // Add mappings to the appropriate result vector
-                    if seg_id == 0 {
-                        all_mappings1.extend(mappings);
-                    } else {
-                        all_mappings2.extend(mappings);
-                    }
+                    all_mappings[seg_id].extend(mappings);
 
                     // Free the regs array for this segment
                     if !seg_regs.is_null() {
@@ -1897,10 +2358,10 @@ impl Aligner<Built> {
                 }
             }
 
-            (all_mappings1, all_mappings2)
+            all_mappings
         });
 
-        Ok((mappings1, mappings2))
+        Ok(mappings)
     }
 
     /// Map entire file
@@ -1912,6 +2373,50 @@ impl Aligner<Built> {
     ///
     #[cfg(feature = "map-file")]
     pub fn map_file(&self, file: &str, cs: bool, md: bool) -> Result<Vec<Mapping>, &'static str> {
+        self.map_file_with_quality(file, cs, md, None)
+    }
+
+    /// Map entire file like [`map_file`](Self::map_file), masking and trimming FASTQ reads
+    /// by quality as set by `quality` (see [`Aligner::map_with_qual`])
+    #[cfg(feature = "map-file")]
+    pub fn map_file_with_quality(
+        &self,
+        file: &str,
+        cs: bool,
+        md: bool,
+        quality: Option<&QualityOptions>,
+    ) -> Result<Vec<Mapping>, &'static str> {
+        self.map_fastx(file, cs, md, quality, None)
+    }
+
+    /// Map entire file like [`map_file`](Self::map_file), trimming and filtering each read
+    /// with `preprocessor` first
+    ///
+    /// Returns the mappings of the reads that pass and the preprocessing counts. Trimmed
+    /// bases show up as clipping: coordinates refer to the whole read.
+    #[cfg(feature = "map-file")]
+    pub fn map_file_preprocessed(
+        &self,
+        file: &str,
+        cs: bool,
+        md: bool,
+        preprocessor: &Preprocessor,
+    ) -> Result<(Vec<Mapping>, PreprocessStats), &'static str> {
+        let mut stats = PreprocessStats::new();
+        let quality = preprocessor.quality_options();
+        let mappings = self.map_fastx(file, cs, md, quality, Some((preprocessor, &mut stats)))?;
+        Ok((mappings, stats))
+    }
+
+    #[cfg(feature = "map-file")]
+    fn map_fastx(
+        &self,
+        file: &str,
+        cs: bool,
+        md: bool,
+        quality: Option<&QualityOptions>,
+        mut preprocess: Option<(&Preprocessor, &mut PreprocessStats)>,
+    ) -> Result<Vec<Mapping>, &'static str> {
         // Make sure index is set
         if self.idx.is_none() {
             return Err("No index");
@@ -1942,18 +2447,55 @@ impl Aligner<Built> {
                 }
             };
 
-            let query_name = record.id().to_vec();
+            // The header line is the name, then the comment after the first space or tab
+            let id = record.id();
+            let (query_name, comment) = match id.iter().position(|b| matches!(b, b' ' | b'\t')) {
+                Some(i) => (&id[..i], Some(&id[i + 1..])),
+                None => (id, None),
+            };
+            let comment = comment
+                .filter(|_| self.mapopt.flag & MM_F_COPY_COMMENT as i64 != 0)
+                .map(|comment| Arc::new(String::from_utf8_lossy(comment).into_owned()));
+            let seq = record.seq();
+            let qual = record.qual();
+            let kept = match preprocess.as_mut() {
+                Some((preprocessor, stats)) => match preprocessor.process(&seq, qual, stats) {
+                    Some(kept) => kept,
+                    None => continue,
+                },
+                None => 0..seq.len(),
+            };
             let mut seq_mappings = self
-                .map(&record.seq(), cs, md, None, None, Some(&query_name))
+                .map_with_qual(
+                    &seq[kept.clone()],
+                    qual.map(|qual| &qual[kept.clone()]),
+                    cs,
+                    md,
+                    Some(query_name),
+                    quality,
+                )
                 .unwrap();
 
+            let query_qual = qual.map(|qual| Arc::new(String::from_utf8_lossy(qual).into_owned()));
+            // The masked sequence covers the preprocessed part only
+            let masked_seq = seq_mappings
+                .first()
+                .and_then(|m| m.masked_seq.as_deref())
+                .map(|masked| {
+                    let mut full = seq.to_vec();
+                    full[kept.clone()].copy_from_slice(masked.as_bytes());
+                    Arc::new(String::from_utf8_lossy(&full).into_owned())
+                });
             for mapping in seq_mappings.iter_mut() {
-                let id = record.id();
-                if id.is_empty() {
+                if query_name.is_empty() {
                     mapping.query_name = Some(Arc::new(
-                        format!("Unnamed Seq with Length: {}", record.seq().len()).to_string(),
+                        format!("Unnamed Seq with Length: {}", seq.len()).to_string(),
                     ));
                 }
+                quality::untrim(mapping, &kept, seq.len(), self.cigar_clipping);
+                mapping.comment = comment.clone();
+                mapping.query_qual = query_qual.clone();
+                mapping.masked_seq = masked_seq.clone();
             }
 
             mappings.extend(seq_mappings);
@@ -1962,6 +2504,53 @@ impl Aligner<Built> {
         Ok(mappings)
     }
 
+    /// Aligns a sequence with its phred+33 quality string `qual`
+    ///
+    /// Like [`map`](Self::map), but the quality string is kept on the mappings
+    /// ([`Mapping::query_qual`]) for the SAM/BAM record converters. With `quality`, low
+    /// quality bases are masked and/or trimmed before mapping; coordinates and CIGAR clipping
+    /// still refer to the whole read, and a read trimmed away entirely has no mappings.
+    /// Masked bases are aligned as `N`, so `NM`, `MD` and `cs` count them as mismatches; the
+    /// masked read is kept on [`Mapping::masked_seq`] for the converters to write as SEQ.
+    pub fn map_with_qual(
+        &self,
+        seq: &[u8],
+        qual: Option<&[u8]>,
+        cs: bool,
+        md: bool,
+        query_name: Option<&[u8]>,
+        quality: Option<&QualityOptions>,
+    ) -> Result<Vec<Mapping>, &'static str> {
+        if qual.is_some_and(|qual| qual.len() != seq.len()) {
+            return Err("Quality string and sequence lengths differ");
+        }
+
+        let (kept, query) = match (qual, quality) {
+            (Some(qual), Some(quality)) => quality.apply(seq, qual),
+            _ => (0..seq.len(), seq.into()),
+        };
+        if kept.is_empty() && !seq.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut mappings = self.map(&query, cs, md, None, None, query_name)?;
+        let query_qual = qual.map(|qual| Arc::new(String::from_utf8_lossy(qual).into_owned()));
+        let masked_seq = match &query {
+            Cow::Owned(masked) if masked[..] != seq[kept.clone()] => {
+                let mut full = seq.to_vec();
+                full[kept.clone()].copy_from_slice(masked);
+                Some(Arc::new(String::from_utf8_lossy(&full).into_owned()))
+            }
+            _ => None,
+        };
+        for mapping in mappings.iter_mut() {
+            quality::untrim(mapping, &kept, seq.len(), self.cigar_clipping);
+            mapping.query_qual = query_qual.clone();
+            mapping.masked_seq = masked_seq.clone();
+        }
+        Ok(mappings)
+    }
+
     // This is in the python module, so copied here...
     pub fn has_index(&self) -> bool {
         self.idx.is_some()
@@ -2096,6 +2685,53 @@ impl Aligner<Built> {
     }
 }
 
+/// minimap2's name table, `khash_t(str)` with `uint32_t` ids
+#[repr(C)]
+struct NameTable {
+    n_buckets: u32,
+    size: u32,
+    n_occupied: u32,
+    upper_bound: u32,
+    flags: *mut u32,
+    keys: *mut *const libc::c_char,
+    vals: *mut u32,
+}
+
+/// `mm_idx_str` adds the names to the name table but not their ids, so every name would
+/// look up as the first sequence: replace the table with one built by `mm_idx_index_name`
+unsafe fn rebuild_name_table(idx: *mut mm_idx_t) {
+    unsafe {
+        let h = (*idx).h as *mut NameTable;
+        if !h.is_null() {
+            // kh_destroy(str, h)
+            libc::free((*h).keys as *mut libc::c_void);
+            libc::free((*h).flags as *mut libc::c_void);
+            libc::free((*h).vals as *mut libc::c_void);
+            libc::free(h as *mut libc::c_void);
+            (*idx).h = std::ptr::null_mut();
+        }
+        mm_idx_index_name(idx);
+    }
+}
+
+/// Look up the id of contig `name` in `idx`
+///
+/// The name table is built when the index is loaded, so lookups do not modify the index.
+fn contig_id(idx: *const mm_idx_t, name: &str) -> Option<i32> {
+    let c_name = CString::new(name).ok()?;
+    let id = unsafe { mm_idx_name2id(idx, c_name.as_ptr()) };
+    (id >= 0).then_some(id)
+}
+
+/// Number of contigs in `idx` marked as ALT contigs
+fn count_alt(idx: *const mm_idx_t) -> usize {
+    unsafe {
+        (0..(*idx).n_seq as isize)
+            .filter(|&i| (*(*idx).seq.offset(i)).is_alt != 0)
+            .count()
+    }
+}
+
 /// Utility function to reverse complement a splice junction
 ///
 /// Adapted from https://github.com/lh3/minimap2/blob/1fd85be6e2515c9194740e1d2e6a2625be36f508/format.c#L256
@@ -2328,6 +2964,7 @@ mod tests {
             idx_parts: Vec::new(),
             idx_reader,
             cigar_clipping: false,
+            presets: Vec::new(),
             _state: Unset,
         };
     }
@@ -3414,4 +4051,387 @@ mod tests {
         assert!(align.cs.is_none());
         assert!(align.md.is_none());
     }
+
+    /// Test multi-segment mapping with map_fragment()
+    #[test]
+    fn test_map_fragment() {
+        let aligner = Aligner::builder()
+            .sr()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+
+        let read1 = b"ATTGCTGCCAAGTATTCGATGCATCTGTTACCCAGAGGTGCTCCTCACTA";
+        let read2 = b"GATCCTCTCGGCCGATAGTCCAGCTCGTAGTTGCGACGAGAAGGCGAGAG";
+        let read3 = b"TGCCGCACAACTTCCATATTGCTGGTTATTACTACGGTACTGACCGCGCG";
+
+        // Two segments behave exactly like map_pair
+        let fragment = aligner
+            .map_fragment(
+                &[read1, read2],
+                false,
+                false,
+                Some(500),
+                None,
+                Some(b"pair"),
+            )
+            .unwrap();
+        let (mappings1, mappings2) = aligner
+            .map_pair(read1, read2, false, false, Some(500), None, Some(b"pair"))
+            .unwrap();
+        assert_eq!(fragment, vec![mappings1, mappings2]);
+
+        // Three segments, one per read
+        let fragment = aligner
+            .map_fragment(
+                &[read1, read2, read3],
+                false,
+                false,
+                None,
+                None,
+                Some(b"three_segments"),
+            )
+            .unwrap();
+        assert_eq!(fragment.len(), 3);
+        for (seg_id, mappings) in fragment.iter().enumerate() {
+            assert!(!mappings.is_empty());
+            assert!(mappings.iter().all(|m| m.segment_id == seg_id as u8));
+            assert_eq!(
+                mappings[0].query_name,
+                Some(Arc::new(String::from("three_segments")))
+            );
+        }
+        assert_eq!(
+            fragment[0][0].target_name,
+            Some(Arc::new(String::from("chr1")))
+        );
+        assert_eq!(fragment[0][0].target_start, 22);
+        assert_eq!(
+            fragment[2][0].target_name,
+            Some(Arc::new(String::from("chr2")))
+        );
+        assert_eq!(fragment[2][0].target_start, 23);
+
+        // Error handling
+        let result = aligner.map_fragment(&[], false, false, None, None, None);
+        assert_eq!(result.unwrap_err(), "No segments");
+
+        let result = aligner.map_fragment(&[read1, b""], false, false, None, None, None);
+        assert_eq!(result.unwrap_err(), "Sequence is empty");
+
+        let too_many: Vec<&[u8]> = vec![read1; MM_MAX_SEG as usize + 1];
+        let result = aligner.map_fragment(&too_many, false, false, None, None, None);
+        assert_eq!(result.unwrap_err(), "Too many segments");
+    }
+
+    #[test]
+    fn test_alt_contigs() {
+        // Synthetic primary/ALT pair: the ALT copy matches the query exactly, the primary
+        // assembly has one mismatch in it
+        let mut state: u64 = 42;
+        let mut random_seq = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    b"ACGT"[(state >> 33) as usize % 4]
+                })
+                .collect()
+        };
+        let alt = random_seq(1500);
+        let mut primary = random_seq(1000);
+        primary.extend_from_slice(&alt);
+        primary.extend(random_seq(1000));
+        primary[1000 + 700] = if alt[700] == b'A' { b'C' } else { b'A' };
+        let query = alt[400..1000].to_vec();
+
+        let build = || {
+            Aligner::builder()
+                .map_ont()
+                .with_index_threads(1)
+                .with_cigar()
+                .with_seqs_and_ids(
+                    &[primary.clone(), alt.clone()],
+                    &[b"chr1".to_vec(), b"chr1_alt".to_vec()],
+                )
+                .unwrap()
+        };
+
+        let primary_of = |aligner: &Aligner<Built>| {
+            aligner
+                .map(&query, false, false, None, None, Some(b"alt_query"))
+                .unwrap()
+                .into_iter()
+                .find(|m| m.is_primary)
+                .unwrap()
+        };
+
+        // Without an ALT list the exact ALT hit wins
+        let aligner = build();
+        let best = primary_of(&aligner);
+        assert_eq!(best.target_name.as_deref().unwrap(), "chr1_alt");
+        assert!(!best.is_alt);
+
+        // Marked by name, the primary assembly is preferred
+        assert_eq!(aligner.load_alt_contigs(["chr1_alt", "chrUn"]).unwrap(), 1);
+        assert_eq!(aligner.load_alt_contigs(["chr1_alt"]).unwrap(), 0);
+        assert!(!aligner.is_alt_contig(0));
+        assert!(aligner.is_alt_contig(1));
+        let best = primary_of(&aligner);
+        assert_eq!(best.target_name.as_deref().unwrap(), "chr1");
+        assert!(!best.is_alt);
+        let alt_hits: Vec<Mapping> = aligner
+            .map(&query, false, false, None, None, None)
+            .unwrap()
+            .into_iter()
+            .filter(|m| m.target_id == 1)
+            .collect();
+        assert!(alt_hits.iter().all(|m| m.is_alt && !m.is_primary));
+
+        // Same from a file
+        let aligner = build();
+        let path = std::env::temp_dir().join("minimap2_rs_test.alt");
+        std::fs::write(&path, "# ALT contigs\nchr1_alt\tALT_REF_LOCI_1\n").unwrap();
+        assert_eq!(aligner.read_alt_contigs(path.to_str().unwrap()).unwrap(), 1);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(primary_of(&aligner).target_name.as_deref().unwrap(), "chr1");
+
+        assert!(aligner.read_alt_contigs("does/not/exist.alt").is_err());
+
+        // chr1 fills the first index part, chr2 goes to the second
+        let mut builder = Aligner::builder().map_ont().with_index_threads(1);
+        builder.idxopt.batch_size = 1000;
+        let aligner = builder.with_index("test_data/genome.fa", None).unwrap();
+        assert_eq!(aligner.idx_parts.len(), 2);
+        let n_alt = |part: usize| unsafe { (*aligner.idx_parts[part].idx).n_alt };
+
+        assert_eq!(aligner.load_alt_contigs(["chr2"]).unwrap(), 1);
+        assert!(!aligner.is_alt_contig(0));
+        assert!(aligner.is_alt_contig(1));
+        assert!(!aligner.is_alt_contig(2));
+
+        // Reading a list keeps the contigs marked before and counts each contig once
+        let path = std::env::temp_dir().join("minimap2_rs_test_parts.alt");
+        std::fs::write(&path, "chr1\nchr1\n").unwrap();
+        assert_eq!(aligner.read_alt_contigs(path.to_str().unwrap()).unwrap(), 1);
+        assert_eq!(aligner.read_alt_contigs(path.to_str().unwrap()).unwrap(), 0);
+        std::fs::remove_file(&path).unwrap();
+        assert!(aligner.is_alt_contig(0));
+        assert_eq!((n_alt(0), n_alt(1)), (1, 1));
+
+        let builder = Aligner::builder().map_ont();
+        let aligner = builder.clone().with_alt_drop_fraction(0.3).unwrap();
+        assert_eq!(aligner.mapopt.alt_drop, 0.3);
+        for fraction in [-0.1, 1.5, f32::NAN] {
+            let err = builder.clone().with_alt_drop_fraction(fraction).err();
+            assert_eq!(err.unwrap().flag, Some("--alt-drop"));
+        }
+    }
+
+    #[test]
+    fn test_with_mapopt() {
+        let aligner = Aligner::builder()
+            .map_ont()
+            .with_cigar()
+            .with_index("test_data/MT-human.fa", None)
+            .unwrap();
+        let query = b"GTTTATGTAGCTTATTCTATCCAAAGCAATGCACTGAAAATGTCTCGACGGGCCCACACGCCCCATAAACAAATAGGTTTGGTCCTAGCCTTTCTATTAGCTCTTAGTGAGGTTACACATGCAAGCATCCCCGCCCCAGTGAGTCGCCCTCCAAGTCACTCTGACTAAGAGGAGCAAGCATCAAGCACGCAACAGCGCAG";
+
+        // A chain score the query cannot reach
+        let strict = aligner
+            .with_mapopt(|opt| opt.min_chain_score = 10_000)
+            .unwrap();
+        assert!(Arc::ptr_eq(
+            aligner.idx.as_ref().unwrap(),
+            strict.idx.as_ref().unwrap()
+        ));
+        assert_eq!(aligner.idx_parts.len(), strict.idx_parts.len());
+
+        assert!(
+            strict
+                .map(query, false, false, None, None, None)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            !aligner
+                .map(query, false, false, None, None, None)
+                .unwrap()
+                .is_empty()
+        );
+        assert_ne!(
+            aligner.mapopt.min_chain_score,
+            strict.mapopt.min_chain_score
+        );
+
+        // Updated from the index like after loading it
+        let frac = aligner.with_mapopt(|opt| opt.mid_occ_frac = 0.01).unwrap();
+        assert!(frac.mapopt.mid_occ > 0);
+
+        let err = aligner
+            .with_mapopt(|opt| opt.pri_ratio = 1.5)
+            .err()
+            .unwrap();
+        assert_eq!(err.option, "pri_ratio");
+    }
+
+    #[test]
+    fn test_map_with_qual() {
+        let aligner = Aligner::builder()
+            .map_ont()
+            .with_cigar()
+            .with_cigar_clipping()
+            .with_index("test_data/MT-human.fa", None)
+            .unwrap();
+        let query = b"GTTTATGTAGCTTATTCTATCCAAAGCAATGCACTGAAAATGTCTCGACGGGCCCACACGCCCCATAAACAAATAGGTTTGGTCCTAGCCTTTCTATTAGCTCTTAGTGAGGTTACACATGCAAGCATCCCCGCCCCAGTGAGTCGCCCTCCAAGTCACTCTGACTAAGAGGAGCAAGCATCAAGCACGCAACAGCGCAG";
+        // Phred 2 on the first 20 and last 30 bases, 40 elsewhere
+        let mut qual = vec![b'I'; query.len()];
+        qual[..20].fill(b'#');
+        qual[query.len() - 30..].fill(b'#');
+
+        let mappings = aligner
+            .map_with_qual(query, Some(&qual), false, false, None, None)
+            .unwrap();
+        assert_eq!(
+            mappings[0].query_qual.as_deref().map(String::as_bytes),
+            Some(&qual[..])
+        );
+
+        let quality = QualityOptions::new().trim_below(10);
+        let trimmed = aligner
+            .map_with_qual(query, Some(&qual), false, false, None, Some(&quality))
+            .unwrap();
+        let m = &trimmed[0];
+        assert_eq!(m.query_len, NonZeroI32::new(query.len() as i32));
+        assert!(m.query_start >= 20 && m.query_end as usize <= query.len() - 30);
+        let aln = m.alignment.as_ref().unwrap();
+        let cigar = aln.cigar.as_ref().unwrap();
+        let query_ops: u32 = cigar
+            .iter()
+            .filter(|(_, op)| matches!(op, 0 | 1 | 4 | 7 | 8))
+            .map(|(len, _)| len)
+            .sum();
+        assert_eq!(query_ops as usize, query.len());
+        assert!(
+            aln.cigar_str
+                .as_ref()
+                .unwrap()
+                .starts_with(&format!("{}S", cigar[0].0))
+        );
+
+        // Masking keeps the coordinates
+        let masked = aligner
+            .map_with_qual(
+                query,
+                Some(&qual),
+                false,
+                false,
+                None,
+                Some(&QualityOptions::new().mask_below(10)),
+            )
+            .unwrap();
+        assert_eq!(masked[0].query_len, NonZeroI32::new(query.len() as i32));
+        let masked_seq = masked[0].masked_seq.as_deref().unwrap().as_bytes();
+        assert_eq!(&masked_seq[..20], &[b'N'; 20]);
+        assert_eq!(
+            &masked_seq[20..query.len() - 30],
+            &query[20..query.len() - 30]
+        );
+        assert!(mappings[0].masked_seq.is_none() && trimmed[0].masked_seq.is_none());
+
+        // Nothing left after trimming, or mismatched lengths
+        let all_low = vec![b'#'; query.len()];
+        assert!(
+            aligner
+                .map_with_qual(query, Some(&all_low), false, false, None, Some(&quality))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            aligner
+                .map_with_qual(query, Some(&qual[1..]), false, false, None, None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "map-file")]
+    fn test_map_file_preprocessed() {
+        let aligner = Aligner::builder()
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+        let file = "test_data/gDNA_reads.fq";
+
+        let all = aligner.map_file(file, false, false).unwrap();
+        let (mappings, stats) = aligner
+            .map_file_preprocessed(file, false, false, &Preprocessor::new())
+            .unwrap();
+        assert_eq!(mappings, all);
+        assert_eq!((stats.reads, stats.passed, stats.bases), (6, 6, 680));
+
+        // Only the 180 bp split read is long enough
+        let (mappings, stats) = aligner
+            .map_file_preprocessed(file, false, false, &Preprocessor::new().min_len(150))
+            .unwrap();
+        assert!(
+            mappings
+                .iter()
+                .all(|m| m.query_name.as_deref().unwrap() == "split_read")
+        );
+        assert_eq!((stats.passed, stats.too_short), (1, 5));
+
+        // Trimmed bases are clipped, coordinates stay on the whole read
+        let preprocessor = Preprocessor::new().trim_head(10).trim_tail(10);
+        let (mappings, stats) = aligner
+            .map_file_preprocessed(file, false, false, &preprocessor)
+            .unwrap();
+        assert_eq!(stats.trimmed_bases, 120);
+        for m in mappings {
+            let len = m.query_len.unwrap().get();
+            assert!(m.query_start >= 10 && m.query_end <= len - 10);
+            assert!(m.query_qual.is_some());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "map-file")]
+    fn test_map_file_header_comment() {
+        // The query name is the header up to the first space or tab, the rest is the comment
+        let seq = std::fs::read_to_string("test_data/gDNA_reads.fq").unwrap();
+        let seq = seq.lines().nth(1).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("minimap2-rs-comment-{}.fa", std::process::id()));
+        std::fs::write(
+            &path,
+            format!(">read1 some comment\n{seq}\n>read2\tRG:Z:x\n{seq}\n"),
+        )
+        .unwrap();
+        let file = path.to_str().unwrap();
+
+        let aligner = Aligner::builder()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+        let mappings = aligner.map_file(file, false, false).unwrap();
+        let names: Vec<_> = mappings
+            .iter()
+            .map(|m| m.query_name.as_deref().unwrap().as_str())
+            .collect();
+        assert!(names.contains(&"read1") && names.contains(&"read2"));
+        assert!(mappings.iter().all(|m| m.comment.is_none()));
+
+        let aligner = Aligner::builder()
+            .with_copy_comment()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+        for m in aligner.map_file(file, false, false).unwrap() {
+            let comment = m.comment.as_deref().map(String::as_str);
+            match m.query_name.as_deref().unwrap().as_str() {
+                "read1" => assert_eq!(comment, Some("some comment")),
+                "read2" => assert_eq!(comment, Some("RG:Z:x")),
+                name => panic!("unexpected query name {name}"),
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
 }
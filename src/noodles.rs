@@ -0,0 +1,613 @@
+//! Pure-Rust SAM/BAM records via [noodles](https://github.com/zaeleus/noodles)
+//!
+//! An alternative to the `htslib` feature that needs no C dependencies besides minimap2
+//! itself. [`Aligner::map_to_noodles_record`] maps a read to `RecordBuf`s, with the same
+//! flags, clipping and aux tags (`NM`, `AS`, `ts`, `tp`, `MD`, `cs`) as minimap2's SAM output,
+//! and [`BamWriter`] writes them to a BAM file with a header built from the index.
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//! use minimap2::noodles::BamWriter;
+//!
+//! let aligner = Aligner::builder()
+//!     .map_ont()
+//!     .with_cigar()
+//!     .with_index("ref.fa", None)
+//!     .unwrap();
+//! let file = std::fs::File::create("out.bam").unwrap();
+//! let mut writer = BamWriter::new(file, &aligner).unwrap();
+//!
+//! let records = aligner
+//!     .map_to_noodles_record(b"ACGGTAGAGAGGAAGCAAT", None, Some(b"read1"), None, None, None)
+//!     .unwrap();
+//! writer.write_all(&records).unwrap();
+//! writer.finish().unwrap();
+//! ```
+
+use std::ffi::CStr;
+use std::io::{self, Write};
+
+use noodles_core::Position;
+use noodles_sam::alignment::RecordBuf;
+use noodles_sam::alignment::io::Write as _;
+use noodles_sam::alignment::record::cigar::{Op, op::Kind};
+use noodles_sam::alignment::record::data::field::Tag;
+use noodles_sam::alignment::record::{Flags, MappingQuality};
+use noodles_sam::alignment::record_buf::data::field::Value;
+use noodles_sam::alignment::record_buf::data::field::value::Array;
+use noodles_sam::alignment::record_buf::{Cigar, Data, QualityScores, Sequence};
+
+use crate::flags::MapFlags;
+use crate::sam::{Clipping, ReadGroup, TagValue, comment_tags, masked_seq, record_comment};
+use crate::{Aligner, Built, Mapping, Strand, reverse_complement};
+
+/// A SAM header with `@SQ` lines for every sequence in the index
+///
+/// The `@HD` line is `VN:1.6 SO:unsorted GO:query`, as written by minimap2.
+pub fn header(aligner: &Aligner<Built>) -> noodles_sam::Header {
+    header_with_read_groups(aligner, &[])
+}
+
+/// The [`header`] of `aligner` with an `@RG` line for each read group
+pub fn header_with_read_groups(
+    aligner: &Aligner<Built>,
+    read_groups: &[ReadGroup],
+) -> noodles_sam::Header {
+    let mut text = String::from("@HD\tVN:1.6\tSO:unsorted\tGO:query\n");
+    for i in 0..aligner.n_seq() as usize {
+        let seq = aligner.get_seq(i).unwrap();
+        let name = unsafe { CStr::from_ptr(seq.name) }.to_string_lossy();
+        text.push_str(&format!("@SQ\tSN:{}\tLN:{}\n", name, seq.len));
+    }
+    for read_group in read_groups {
+        text.push_str(&read_group.header_line());
+        text.push('\n');
+    }
+    text.parse().expect("Invalid SAM header")
+}
+
+/// Convert a mapping of `seq` to a record; `None` gives an unmapped record
+///
+/// `qual` is phred+33 encoded and defaults to [`Mapping::query_qual`], `flags` are the
+/// mapping flags of the aligner, deciding between hard and soft clipping and whether
+/// secondary records keep their sequence.
+/// SEQ is [`Mapping::masked_seq`] when set, as `NM`, `MD` and `cs` describe it.
+/// With `MapFlags::COPY_COMMENT` the tags of [`Mapping::comment`] are added as well,
+/// `MM`/`ML` clipped for hard-clipped records.
+pub fn mapping_to_record(
+    mapping: Option<&Mapping>,
+    seq: &[u8],
+    qual: Option<&[u8]>,
+    query_name: Option<&[u8]>,
+    flags: MapFlags,
+) -> RecordBuf {
+    let name = query_name.unwrap_or(b"query").to_vec();
+    let qual = qual.or_else(|| {
+        mapping
+            .and_then(|m| m.query_qual.as_deref())
+            .map(String::as_bytes)
+    });
+    let qual: Vec<u8> = qual
+        .map(|qual| qual.iter().map(|q| q.saturating_sub(33)).collect())
+        .unwrap_or_default();
+
+    let Some(m) = mapping else {
+        return RecordBuf::builder()
+            .set_name(name)
+            .set_flags(Flags::UNMAPPED)
+            .set_mapping_quality(MappingQuality::new(0).unwrap())
+            .set_sequence(Sequence::from(seq.to_vec()))
+            .set_quality_scores(QualityScores::from(qual))
+            .build();
+    };
+
+    let reverse = m.strand == Strand::Reverse;
+    let secondary = !m.is_primary && !m.is_supplementary;
+    let mut record_flags = Flags::empty();
+    if reverse {
+        record_flags |= Flags::REVERSE_COMPLEMENTED;
+    }
+    if secondary {
+        record_flags |= Flags::SECONDARY;
+    }
+    if m.is_supplementary {
+        record_flags |= Flags::SUPPLEMENTARY;
+    }
+
+    // SEQ and QUAL are on the forward strand of the target
+    let read = seq;
+    let seq = masked_seq(m, seq);
+    let (mut seq, mut qual) = if reverse {
+        (reverse_complement(seq), qual.into_iter().rev().collect())
+    } else {
+        (seq.to_vec(), qual)
+    };
+
+    let mut builder = RecordBuf::builder()
+        .set_name(name)
+        .set_flags(record_flags)
+        .set_reference_sequence_id(m.target_id as usize)
+        .set_alignment_start(Position::try_from(m.target_start as usize + 1).unwrap());
+    if let Some(mapq) = MappingQuality::new(m.mapq as u8) {
+        builder = builder.set_mapping_quality(mapq);
+    }
+
+    let alignment = m.alignment.as_ref();
+    let clipping = Clipping::new(m, seq.len(), flags);
+    let mut hard_clipped = false;
+    if let Some(cigar) = alignment.and_then(|aln| aln.cigar.as_ref()) {
+        let ops = clipping
+            .cigar(cigar)
+            .into_iter()
+            .map(|(len, op)| Op::new(cigar_kind(op), len as usize));
+        builder = builder.set_cigar(Cigar::from(ops.collect::<Vec<_>>()));
+
+        if clipping.hard {
+            let kept = clipping.kept(seq.len());
+            seq = seq[kept.clone()].to_vec();
+            if !qual.is_empty() {
+                qual = qual[kept].to_vec();
+            }
+            hard_clipped = true;
+        }
+    }
+
+    if !secondary || flags.contains(MapFlags::SECONDARY_SEQ) {
+        builder = builder
+            .set_sequence(Sequence::from(seq))
+            .set_quality_scores(QualityScores::from(qual));
+    }
+
+    let mut data: Vec<(Tag, Value)> = Vec::new();
+    if let Some(aln) = alignment {
+        data.push((Tag::EDIT_DISTANCE, Value::from(aln.nm)));
+        if let Some(score) = aln.alignment_score {
+            data.push((Tag::ALIGNMENT_SCORE, Value::from(score)));
+        }
+    }
+    if let Some(trans_strand) = m.trans_strand {
+        let strand = match trans_strand {
+            Strand::Forward => b'+',
+            Strand::Reverse => b'-',
+        };
+        data.push((Tag::new(b't', b's'), Value::Character(strand)));
+    }
+    let tp = match (secondary, m.is_inversion) {
+        (false, false) => b'P',
+        (false, true) => b'I',
+        (true, false) => b'S',
+        (true, true) => b'i',
+    };
+    data.push((Tag::new(b't', b'p'), Value::Character(tp)));
+    if let Some(aln) = alignment {
+        if let Some(md) = &aln.md {
+            data.push((Tag::MISMATCHED_POSITIONS, Value::from(md.as_str())));
+        }
+        if let Some(cs) = &aln.cs {
+            data.push((Tag::new(b'c', b's'), Value::from(cs.as_str())));
+        }
+    }
+    if let Some(comment) = m
+        .comment
+        .as_ref()
+        .filter(|_| flags.contains(MapFlags::COPY_COMMENT))
+    {
+        let comment = record_comment(m, comment, read, hard_clipped);
+        for (tag, value) in comment_tags(&comment) {
+            if let Some(value) = comment_value(value) {
+                data.push((Tag::new(tag[0], tag[1]), value));
+            }
+        }
+    }
+
+    builder.set_data(data.into_iter().collect::<Data>()).build()
+}
+
+/// The noodles value of a copied comment field, `None` if an integer is out of range
+fn comment_value(value: TagValue) -> Option<Value> {
+    let value = match value {
+        TagValue::Char(c) => Value::Character(c),
+        TagValue::Int(i) => match i32::try_from(i) {
+            Ok(i) => Value::from(i),
+            Err(_) => Value::from(u32::try_from(i).ok()?),
+        },
+        TagValue::Float(f) => Value::Float(f),
+        TagValue::String(s) => Value::from(s),
+        TagValue::Hex(h) => Value::Hex(h.into()),
+        TagValue::Int8Array(v) => Value::Array(Array::Int8(v)),
+        TagValue::UInt8Array(v) => Value::Array(Array::UInt8(v)),
+        TagValue::Int16Array(v) => Value::Array(Array::Int16(v)),
+        TagValue::UInt16Array(v) => Value::Array(Array::UInt16(v)),
+        TagValue::Int32Array(v) => Value::Array(Array::Int32(v)),
+        TagValue::UInt32Array(v) => Value::Array(Array::UInt32(v)),
+        TagValue::FloatArray(v) => Value::Array(Array::Float(v)),
+    };
+    Some(value)
+}
+
+fn cigar_kind(op: u8) -> Kind {
+    match op {
+        0 => Kind::Match,
+        1 => Kind::Insertion,
+        2 => Kind::Deletion,
+        3 => Kind::Skip,
+        4 => Kind::SoftClip,
+        5 => Kind::HardClip,
+        6 => Kind::Pad,
+        7 => Kind::SequenceMatch,
+        8 => Kind::SequenceMismatch,
+        _ => panic!("Unexpected cigar operation"),
+    }
+}
+
+impl Aligner<Built> {
+    /// Map a read and return noodles records, the counterpart of `map_to_sam()` in the
+    /// `htslib` feature
+    ///
+    /// `qual` is phred+33 encoded. Unmapped reads give a single unmapped record unless
+    /// `MM_F_SAM_HIT_ONLY` is set. `MD` and `cs` tags are added when `MM_F_OUT_MD` /
+    /// `MM_F_OUT_CS` are set on the aligner or in `extra_flags`. With a `read_group`, every
+    /// record is tagged with its `RG:Z`, see [`header_with_read_groups`].
+    pub fn map_to_noodles_record(
+        &self,
+        seq: &[u8],
+        qual: Option<&[u8]>,
+        name: Option<&[u8]>,
+        max_frag_len: Option<usize>,
+        extra_flags: Option<&[u64]>,
+        read_group: Option<&ReadGroup>,
+    ) -> Result<Vec<RecordBuf>, &'static str> {
+        if qual.is_some_and(|qual| qual.len() != seq.len()) {
+            return Err("Sequence and quality strings are different length");
+        }
+
+        let flags = extra_flags
+            .into_iter()
+            .flatten()
+            .fold(self.map_flags(), |flags, &flag| {
+                flags | MapFlags::from_bits_retain(flag)
+            });
+        let mappings = self.map(
+            seq,
+            flags.contains(MapFlags::OUT_CS),
+            flags.contains(MapFlags::OUT_MD),
+            max_frag_len,
+            extra_flags,
+            name,
+        )?;
+
+        let mut records = if mappings.is_empty() {
+            if flags.contains(MapFlags::SAM_HIT_ONLY) {
+                return Ok(Vec::new());
+            }
+            vec![mapping_to_record(None, seq, qual, name, flags)]
+        } else {
+            mappings
+                .iter()
+                .map(|m| mapping_to_record(Some(m), seq, qual, name, flags))
+                .collect()
+        };
+        if let Some(read_group) = read_group {
+            for record in &mut records {
+                let rg = Value::from(read_group.id.as_str());
+                record.data_mut().insert(Tag::READ_GROUP, rg);
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Writes records to a BGZF-compressed BAM file
+///
+/// The header is written on creation. Records are written in the order given; the output
+/// is ready for coordinate sorting (e.g. `samtools sort`).
+pub struct BamWriter<W: Write> {
+    header: noodles_sam::Header,
+    inner: noodles_bam::io::Writer<noodles_bgzf::Writer<W>>,
+}
+
+impl<W: Write> BamWriter<W> {
+    /// Write a BAM file with the [`header`] of `aligner`
+    pub fn new(inner: W, aligner: &Aligner<Built>) -> io::Result<Self> {
+        Self::with_header(inner, header(aligner))
+    }
+
+    /// Write a BAM file with a custom header, e.g. [`header`] plus `@RG` / `@PG` lines
+    pub fn with_header(inner: W, header: noodles_sam::Header) -> io::Result<Self> {
+        let mut inner = noodles_bam::io::Writer::new(inner);
+        inner.write_header(&header)?;
+        Ok(BamWriter { header, inner })
+    }
+
+    pub fn header(&self) -> &noodles_sam::Header {
+        &self.header
+    }
+
+    /// Write a single record
+    pub fn write(&mut self, record: &RecordBuf) -> io::Result<()> {
+        self.inner.write_alignment_record(&self.header, record)
+    }
+
+    /// Write every record of `records`
+    pub fn write_all<'a>(
+        &mut self,
+        records: impl IntoIterator<Item = &'a RecordBuf>,
+    ) -> io::Result<()> {
+        records
+            .into_iter()
+            .try_for_each(|record| self.write(record))
+    }
+
+    /// Write the BGZF end-of-file marker and return the underlying writer
+    pub fn finish(self) -> io::Result<W> {
+        self.inner.into_inner().finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quality::QualityOptions;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    fn aligner(spliced: bool) -> Aligner<Built> {
+        match spliced {
+            false => Aligner::builder()
+                .with_index_threads(1)
+                .with_cigar()
+                .with_index("test_data/genome.fa", None)
+                .unwrap(),
+            true => Aligner::builder()
+                .splice()
+                .with_index_threads(1)
+                .with_cigar()
+                .with_index("test_data/genome.fa", None)
+                .unwrap(),
+        }
+    }
+
+    /// All records for a query in the truth set from the minimap2 CLI
+    fn expected_records(query_name: &str, spliced: bool) -> Vec<RecordBuf> {
+        let sam_path = match spliced {
+            true => "test_data/cDNA_vs_genome.sam",
+            false => "test_data/gDNA_vs_genome.sam",
+        };
+        let mut reader =
+            noodles_sam::io::Reader::new(BufReader::new(File::open(sam_path).unwrap()));
+        let header = reader.read_header().unwrap();
+        reader
+            .record_bufs(&header)
+            .map(|r| r.unwrap())
+            .filter(|r| r.name().is_some_and(|name| name == query_name.as_bytes()))
+            .collect()
+    }
+
+    /// The query sequence and phred+33 qualities, from the primary record
+    fn query(records: &[RecordBuf]) -> (Vec<u8>, Vec<u8>) {
+        let primary = records
+            .iter()
+            .find(|r| !(r.flags().is_secondary() || r.flags().is_supplementary()))
+            .unwrap();
+        let mut seq = primary.sequence().as_ref().to_vec();
+        let mut qual: Vec<u8> = primary
+            .quality_scores()
+            .as_ref()
+            .iter()
+            .map(|q| q + 33)
+            .collect();
+        if primary.flags().is_reverse_complemented() {
+            seq = reverse_complement(&seq);
+            qual.reverse();
+        }
+        (seq, qual)
+    }
+
+    fn map_test_case(
+        query_name: &str,
+        spliced: bool,
+        extra_flags: Option<&[u64]>,
+    ) -> (Vec<RecordBuf>, Vec<RecordBuf>) {
+        let expected = expected_records(query_name, spliced);
+        let (seq, qual) = query(&expected);
+        let observed = aligner(spliced)
+            .map_to_noodles_record(
+                &seq,
+                Some(&qual),
+                Some(query_name.as_bytes()),
+                None,
+                extra_flags,
+                None,
+            )
+            .unwrap();
+        (observed, expected)
+    }
+
+    fn fields(records: &[RecordBuf]) -> Vec<(Option<usize>, Option<Position>, Flags)> {
+        records
+            .iter()
+            .map(|r| (r.reference_sequence_id(), r.alignment_start(), r.flags()))
+            .collect()
+    }
+
+    fn check_single_mapper(expected: &[RecordBuf], observed: &[RecordBuf]) {
+        assert_eq!(expected.len(), observed.len());
+        let (e, o) = (&expected[0], &observed[0]);
+        assert_eq!(o.name(), e.name());
+        assert_eq!(o.flags(), e.flags());
+        assert_eq!(o.reference_sequence_id(), e.reference_sequence_id());
+        assert_eq!(o.alignment_start(), e.alignment_start());
+        assert_eq!(o.mapping_quality(), e.mapping_quality());
+        assert_eq!(o.cigar(), e.cigar());
+        assert_eq!(o.sequence(), e.sequence());
+        assert_eq!(o.quality_scores(), e.quality_scores());
+    }
+
+    #[test]
+    fn test_header() {
+        let header = header(&aligner(false));
+        let seqs: Vec<_> = header
+            .reference_sequences()
+            .iter()
+            .map(|(name, seq)| (name.to_string(), seq.length().get()))
+            .collect();
+        assert_eq!(
+            seqs,
+            vec![("chr1".to_string(), 1720), ("chr2".to_string(), 460)]
+        );
+    }
+
+    #[test]
+    fn test_fwd_rev() {
+        for query_name in ["perfect_read.fwd", "perfect_read.rev"] {
+            let (o, e) = map_test_case(query_name, false, None);
+            check_single_mapper(&e, &o);
+        }
+    }
+
+    /// `AS` and `tp` of `record`, `AS` as an integer of any width
+    fn score_and_type(record: &RecordBuf) -> (Option<i64>, Option<&Value>) {
+        let data = record.data();
+        (
+            data.get(&Tag::ALIGNMENT_SCORE).and_then(|v| v.as_int()),
+            data.get(&Tag::new(b't', b'p')),
+        )
+    }
+
+    #[test]
+    fn test_aux_tags() {
+        let query_name = "imperfect_read.fwd";
+        let extra_flags = [(MapFlags::OUT_MD | MapFlags::OUT_CS).bits()];
+        let (o, e) = map_test_case(query_name, false, Some(&extra_flags));
+        check_single_mapper(&e, &o);
+
+        let (o, e) = (&o[0], &e[0]);
+        let nm = o.data().get(&Tag::EDIT_DISTANCE).and_then(|v| v.as_int());
+        assert_eq!(nm, Some(5));
+        assert_eq!(score_and_type(o), score_and_type(e));
+        assert_eq!(
+            score_and_type(o),
+            (Some(170), Some(&Value::Character(b'P')))
+        );
+        let md = Tag::MISMATCHED_POSITIONS;
+        assert_eq!(o.data().get(&md), e.data().get(&md));
+        // The truth set was written without --cs
+        let cs = o.data().get(&Tag::new(b'c', b's'));
+        assert_eq!(
+            cs,
+            Some(&Value::String(":34*gt:10*ca:6*ag:6*gt:33*tg:6".into()))
+        );
+    }
+
+    #[test]
+    fn test_masked_seq() {
+        // SEQ is the masked read, whose Ns are the mismatches of NM and MD
+        let aligner = aligner(false);
+        let (seq, mut qual) = query(&expected_records("perfect_read.rev", false));
+        qual[50..55].fill(b'#');
+        let quality = QualityOptions::new().mask_below(10);
+        let mappings = aligner
+            .map_with_qual(&seq, Some(&qual), false, true, None, Some(&quality))
+            .unwrap();
+        let o = mapping_to_record(mappings.first(), &seq, None, None, aligner.map_flags());
+        let masked: Vec<usize> = o
+            .sequence()
+            .as_ref()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == b'N')
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(masked, (45..50).collect::<Vec<_>>());
+        let nm = o.data().get(&Tag::EDIT_DISTANCE).and_then(|v| v.as_int());
+        assert_eq!(nm, Some(5));
+        let md = o.data().get(&Tag::MISMATCHED_POSITIONS);
+        assert_eq!(md, Some(&Value::String("45C0T0G0G0A50".into())));
+    }
+
+    #[test]
+    fn test_unmapped() {
+        let (o, e) = map_test_case("unmappable_read", false, None);
+        check_single_mapper(&e, &o);
+        assert!(o[0].flags().is_unmapped());
+
+        let hit_only = [MapFlags::SAM_HIT_ONLY.bits()];
+        let (o, _) = map_test_case("unmappable_read", false, Some(&hit_only));
+        assert!(o.is_empty());
+    }
+
+    #[test]
+    fn test_secondary_supplementary() {
+        let (o, e) = map_test_case("perfect_inv_duplicate", false, None);
+        assert_eq!(o.len(), 2);
+        assert_eq!(fields(&o), fields(&e));
+        assert!(o[1].flags().is_secondary());
+        assert!(o[1].sequence().is_empty());
+        for (o, e) in o.iter().zip(&e) {
+            assert_eq!(score_and_type(o), score_and_type(e));
+        }
+        assert_eq!(score_and_type(&o[1]).1, Some(&Value::Character(b'S')));
+
+        let (o, e) = map_test_case("split_read", false, None);
+        assert_eq!(o.len(), 2);
+        assert_eq!(fields(&o), fields(&e));
+        assert!(o[1].flags().is_supplementary());
+        assert_eq!(o[1].cigar(), e[1].cigar());
+        assert_eq!(o[1].sequence(), e[1].sequence());
+        for (o, e) in o.iter().zip(&e) {
+            assert_eq!(score_and_type(o), score_and_type(e));
+        }
+    }
+
+    #[test]
+    fn test_spliced() {
+        for query_name in ["cdna.fwd", "cdna.rev"] {
+            let (o, e) = map_test_case(query_name, true, None);
+            check_single_mapper(&e, &o);
+            let ts = o[0].data().get(&Tag::new(b't', b's'));
+            assert_eq!(ts, e[0].data().get(&Tag::new(b't', b's')));
+        }
+    }
+
+    #[test]
+    fn test_bam_round_trip() {
+        let aligner = aligner(false);
+        let read_group = ReadGroup::new("rg1").with_sample("sample1");
+        let mut records = Vec::new();
+        for query_name in ["perfect_read.fwd", "split_read", "unmappable_read"] {
+            let (seq, qual) = query(&expected_records(query_name, false));
+            records.extend(
+                aligner
+                    .map_to_noodles_record(
+                        &seq,
+                        Some(&qual),
+                        Some(query_name.as_bytes()),
+                        None,
+                        None,
+                        Some(&read_group),
+                    )
+                    .unwrap(),
+            );
+        }
+
+        let header = header_with_read_groups(&aligner, std::slice::from_ref(&read_group));
+        let mut writer = BamWriter::with_header(Vec::new(), header).unwrap();
+        writer.write_all(&records).unwrap();
+        let bam = writer.finish().unwrap();
+
+        let mut reader = noodles_bam::io::Reader::new(bam.as_slice());
+        let header = reader.read_header().unwrap();
+        assert_eq!(header.reference_sequences().len(), 2);
+        assert!(header.read_groups().contains_key(&b"rg1"[..]));
+        let read: Vec<RecordBuf> = reader.record_bufs(&header).map(|r| r.unwrap()).collect();
+        assert_eq!(fields(&read), fields(&records));
+        for (r, o) in read.iter().zip(&records) {
+            assert_eq!(r.name(), o.name());
+            assert_eq!(r.cigar(), o.cigar());
+            assert_eq!(r.sequence(), o.sequence());
+            assert_eq!(r.quality_scores(), o.quality_scores());
+            let rg = r.data().get(&Tag::READ_GROUP);
+            assert_eq!(rg, Some(&Value::String("rg1".into())));
+        }
+    }
+}
@@ -0,0 +1,631 @@
+//! Typed, validated indexing and mapping options
+//!
+//! Every field of [`IdxOpt`] and [`MapOpt`] can be set directly, but nothing checks the
+//! values until minimap2 misbehaves. [`IndexOptions`] and [`MapOptions`] collect typed
+//! settings, are applied on top of the preset with
+//! [`Aligner::with_index_options`](crate::Aligner::with_index_options) and
+//! [`Aligner::with_map_options`](crate::Aligner::with_map_options), and report the
+//! offending option (and its minimap2 command line flag) if the result is invalid.
+//!
+//! Only the options that were set are written, so everything else keeps the preset's value.
+//!
+//! ```
+//! use minimap2::Aligner;
+//! use minimap2::options::{IndexOptions, MapOptions};
+//!
+//! let aligner = Aligner::builder()
+//!     .map_ont()
+//!     .with_index_options(&IndexOptions::new().kmer(17).window(11))
+//!     .unwrap()
+//!     .with_map_options(
+//!         &MapOptions::new()
+//!             .bandwidth(500, Some(10_000))
+//!             .zdrop(200, Some(100))
+//!             .best_n(3),
+//!     )
+//!     .unwrap();
+//!
+//! let err = Aligner::builder()
+//!     .map_ont()
+//!     .with_map_options(&MapOptions::new().pri_ratio(1.5))
+//!     .err().unwrap();
+//! assert_eq!(err.option, "pri_ratio");
+//! assert_eq!(err.flag, Some("-p"));
+//! ```
+
+use minimap2_sys::*;
+
+use crate::{IdxOpt, MapOpt};
+
+/// An option value minimap2 would reject or misbehave with
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionError {
+    /// The `mm_idxopt_t` / `mm_mapopt_t` field at fault
+    pub option: &'static str,
+    /// The equivalent minimap2 command line flag, if there is one
+    pub flag: Option<&'static str>,
+    pub reason: String,
+}
+
+fn err(option: &'static str, flag: Option<&'static str>, reason: impl Into<String>) -> OptionError {
+    OptionError {
+        option,
+        flag,
+        reason: reason.into(),
+    }
+}
+
+impl std::fmt::Display for OptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.flag {
+            Some(flag) => write!(f, "Invalid {} ({}): {}", self.option, flag, self.reason),
+            None => write!(f, "Invalid {}: {}", self.option, self.reason),
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+/// Generates a struct of optional settings with one setter per field and `apply_to`
+macro_rules! typed_options {
+    (
+        $(#[$struct_meta:meta])*
+        $name:ident for $target:ty {
+            $(
+                $(#[$meta:meta])*
+                $setter:ident => $field:ident: $ty:ty,
+            )*
+        }
+        // Written by `apply_to`, set by hand-written setters
+        $( paired { $( $paired:ident: $paired_ty:ty, )* } )?
+        // Neither, handled by hand
+        $( extra { $( $extra:ident: $extra_ty:ty, )* } )?
+    ) => {
+        $(#[$struct_meta])*
+        #[derive(Debug, Clone, Default, PartialEq)]
+        pub struct $name {
+            $( $field: Option<$ty>, )*
+            $( $( $paired: Option<$paired_ty>, )* )?
+            $( $( $extra: $extra_ty, )* )?
+        }
+
+        impl $name {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            $(
+                $(#[$meta])*
+                pub fn $setter(mut self, value: $ty) -> Self {
+                    self.$field = Some(value);
+                    self
+                }
+            )*
+
+            /// Write the options that were set into `opt`, leaving the others untouched
+            pub fn apply_to(&self, opt: &mut $target) {
+                $(
+                    if let Some(value) = self.$field {
+                        opt.$field = value;
+                    }
+                )*
+                $($(
+                    if let Some(value) = self.$paired {
+                        opt.$paired = value;
+                    }
+                )*)?
+            }
+        }
+    };
+}
+
+typed_options! {
+    /// Indexing options (`mm_idxopt_t`)
+    ///
+    /// Index options only take effect when the index is built from sequences; a prebuilt
+    /// `.mmi` keeps the options it was built with.
+    IndexOptions for IdxOpt {
+        /// k-mer size, 1 to 28. minimap2 `-k`
+        kmer => k: i16,
+        /// Minimizer window size, 1 to 255. minimap2 `-w`
+        window => w: i16,
+        /// Bits for the minimizer hash table buckets, 10 to 32. minimap2 `--bucket-bits`
+        bucket_bits => bucket_bits: i16,
+        /// Number of bases loaded into memory at once while indexing. No CLI flag.
+        index_mini_batch_size => mini_batch_size: i64,
+        /// Number of bases per index part; larger references are split. minimap2 `-I`
+        batch_size => batch_size: u64,
+    }
+    extra {
+        hpc: Option<bool>,
+    }
+}
+
+impl IndexOptions {
+    /// Use homopolymer-compressed k-mers. minimap2 `-H`
+    pub fn hpc(mut self, hpc: bool) -> Self {
+        self.hpc = Some(hpc);
+        self
+    }
+
+    pub(crate) fn apply(&self, opt: &mut IdxOpt) {
+        self.apply_to(opt);
+        match self.hpc {
+            Some(true) => opt.set_hpc(),
+            Some(false) => opt.unset_hpc(),
+            None => {}
+        }
+    }
+}
+
+typed_options! {
+    /// Mapping options (`mm_mapopt_t`)
+    ///
+    /// Flags (`MM_F_*`) are not covered here; see the `with_*` builder methods and the
+    /// `set_*`/`unset_*` methods on [`MapOpt`].
+    MapOptions for MapOpt {
+        /// Random seed, used to break ties. minimap2 `--seed`
+        seed => seed: i32,
+        /// Score threshold for SDUST low-complexity masking, 0 disables it. minimap2 `-T`
+        sdust_thres => sdust_thres: i32,
+        /// Skip queries longer than this, 0 for no limit. minimap2 `--max-qlen`
+        max_qlen => max_qlen: i32,
+        /// Stop chain elongation if there are no minimizers in this many bases. minimap2 `-g`
+        max_gap => max_gap: i32,
+        /// Maximum gap on the reference, or maximum intron length in splice mode. minimap2 `-G`
+        max_gap_ref => max_gap_ref: i32,
+        /// Maximum fragment length for paired-end mapping. minimap2 `-F`
+        max_frag_len => max_frag_len: i32,
+        /// Stop chaining after skipping this many seeds. minimap2 `--max-chain-skip`
+        max_chain_skip => max_chain_skip: i32,
+        /// Maximum number of predecessors tried when chaining. minimap2 `--max-chain-iter`
+        max_chain_iter => max_chain_iter: i32,
+        /// Minimum number of minimizers in a chain. minimap2 `-n`
+        min_cnt => min_cnt: i32,
+        /// Minimum chaining score. minimap2 `-m`
+        min_chain_score => min_chain_score: i32,
+        /// Scale of the chaining gap cost. minimap2 `--chain-gap-scale`
+        chain_gap_scale => chain_gap_scale: f32,
+        /// Scale of the chaining skip cost. minimap2 `--chain-skip-scale`
+        chain_skip_scale => chain_skip_scale: f32,
+        /// Size cap of the RMQ-based chaining. Set by presets, no CLI flag.
+        rmq_size_cap => rmq_size_cap: i32,
+        /// Inner distance of RMQ-based chaining. Set by presets, no CLI flag.
+        rmq_inner_dist => rmq_inner_dist: i32,
+        /// Minimum chain size for RMQ rescue. Set by presets, no CLI flag.
+        rmq_rescue_size => rmq_rescue_size: i32,
+        /// Minimum chain fraction for RMQ rescue. Set by presets, no CLI flag.
+        rmq_rescue_ratio => rmq_rescue_ratio: f32,
+        /// Overlap fraction at which a chain is masked by a better one, 0 to 1. minimap2 `-M`
+        mask_level => mask_level: f32,
+        /// Only mask chains overlapping by at least this many bases. minimap2 `--mask-len`
+        mask_len => mask_len: i32,
+        /// Minimum secondary-to-primary score ratio, 0 to 1. minimap2 `-p`
+        pri_ratio => pri_ratio: f32,
+        /// Number of secondary alignments to retain. minimap2 `-N`
+        best_n => best_n: i32,
+        /// Score drop fraction for ALT contig hits, 0 to 1. minimap2 `--alt-drop`
+        alt_drop => alt_drop: f32,
+        /// Matching score. minimap2 `-A`
+        match_score => a: i32,
+        /// Mismatch penalty. minimap2 `-B`
+        mismatch_penalty => b: i32,
+        /// Transition mismatch penalty in splice mode. minimap2 `-b`
+        transition => transition: i32,
+        /// Score of ambiguous bases (N), must be below the mismatch penalty. minimap2 `--score-N`
+        sc_ambi => sc_ambi: i32,
+        /// Cost of non-canonical splicing. minimap2 `-C`
+        noncan => noncan: i32,
+        /// Score bonus for annotated junctions. minimap2 `--junc-bonus`
+        junc_bonus => junc_bonus: i32,
+        /// Penalty for junctions missing from the annotation. minimap2 `--junc-pen`
+        junc_pen => junc_pen: i32,
+        /// Score bonus when the alignment reaches the end of the query. minimap2 `--end-bonus`
+        end_bonus => end_bonus: i32,
+        /// Minimum DP alignment score. minimap2 `-s`
+        min_dp_max => min_dp_max: i32,
+        /// Minimum length of a region to be aligned with DP. minimap2 `--min-dp-len`
+        min_ksw_len => min_ksw_len: i32,
+        /// Extension length when aligning anchors. Set by presets, no CLI flag.
+        anchor_ext_len => anchor_ext_len: i32,
+        /// Extension shift when aligning anchors. Set by presets, no CLI flag.
+        anchor_ext_shift => anchor_ext_shift: i32,
+        /// Maximum ratio of clipped to aligned bases. minimap2 `--max-clip-ratio`
+        max_clip_ratio => max_clip_ratio: f32,
+        /// Minimum length for rank-based filtering. Set by presets, no CLI flag.
+        rank_min_len => rank_min_len: i32,
+        /// Fraction for rank-based filtering, 0 to 1. Set by presets, no CLI flag.
+        rank_frac => rank_frac: f32,
+        /// Expected orientation of paired reads (bit 0 for read 1, bit 1 for read 2 reversed).
+        /// Set by the `sr` preset, no CLI flag.
+        pe_ori => pe_ori: i32,
+        /// Score bonus for a properly paired alignment. Set by presets, no CLI flag.
+        pe_bonus => pe_bonus: i32,
+        /// Minimum matching bases to follow a junction jump. Set by presets, no CLI flag.
+        jump_min_match => jump_min_match: i32,
+        /// Fraction of the most frequent minimizers to ignore, 0 to 1. minimap2 `-f FLOAT`
+        mid_occ_frac => mid_occ_frac: f32,
+        /// Ignore query minimizers occurring more than this fraction of the query, 0 to 1.
+        /// minimap2 `--q-occ-frac`
+        q_occ_frac => q_occ_frac: f32,
+        /// Lower bound of the minimizer occurrence cutoff. minimap2 `-U INT`
+        min_mid_occ => min_mid_occ: i32,
+        /// Upper bound of the minimizer occurrence cutoff. minimap2 `-U ,INT`
+        max_mid_occ => max_mid_occ: i32,
+        /// Ignore minimizers occurring more than this many times. minimap2 `-f INT`
+        mid_occ => mid_occ: i32,
+        /// Hard minimizer occurrence cutoff. Set by presets, no CLI flag.
+        max_occ => max_occ: i32,
+        /// Cap for the minimizer occurrence cutoff. Set by presets, no CLI flag.
+        max_max_occ => max_max_occ: i32,
+        /// Distance between high-occurrence minimizers kept. Set by presets, no CLI flag.
+        occ_dist => occ_dist: i32,
+        /// Number of query bases loaded into memory at once. minimap2 `-K`
+        mini_batch_size => mini_batch_size: i64,
+        /// Cap on the DP matrix size. minimap2 `--cap-sw-mem`
+        max_sw_mat => max_sw_mat: i64,
+        /// Free the thread-local memory pool when it grows larger than this. minimap2 `--cap-kalloc`
+        cap_kalloc => cap_kalloc: i64,
+    }
+    paired {
+        bw: i32,
+        bw_long: i32,
+        q: i32,
+        q2: i32,
+        e: i32,
+        e2: i32,
+        zdrop: i32,
+        zdrop_inv: i32,
+    }
+}
+
+impl MapOptions {
+    /// Bandwidth for chaining and DP, and for long joins. minimap2 `-r INT[,INT]`
+    pub fn bandwidth(mut self, bw: i32, bw_long: Option<i32>) -> Self {
+        self.bw = Some(bw);
+        self.bw_long = bw_long.or(self.bw_long);
+        self
+    }
+
+    /// Gap open penalty, and long-gap open penalty. minimap2 `-O INT[,INT]`
+    ///
+    /// Without a second value both penalties are set, as minimap2 does.
+    pub fn gap_open(mut self, q: i32, q2: Option<i32>) -> Self {
+        self.q = Some(q);
+        self.q2 = Some(q2.unwrap_or(q));
+        self
+    }
+
+    /// Gap extension penalty, and long-gap extension penalty. minimap2 `-E INT[,INT]`
+    ///
+    /// Without a second value both penalties are set, as minimap2 does.
+    pub fn gap_extend(mut self, e: i32, e2: Option<i32>) -> Self {
+        self.e = Some(e);
+        self.e2 = Some(e2.unwrap_or(e));
+        self
+    }
+
+    /// Z-drop score, and Z-drop score for inversions. minimap2 `-z INT[,INT]`
+    pub fn zdrop(mut self, zdrop: i32, zdrop_inv: Option<i32>) -> Self {
+        self.zdrop = Some(zdrop);
+        self.zdrop_inv = zdrop_inv.or(self.zdrop_inv);
+        self
+    }
+}
+
+/// Check a complete set of options, naming the first offending option
+///
+/// Covers the checks of minimap2's `mm_check_opt` as well as value ranges it does not
+/// check, then runs `mm_check_opt` itself as a final guard.
+pub fn validate(idxopt: &IdxOpt, mapopt: &MapOpt) -> Result<(), OptionError> {
+    if !(1..=28).contains(&idxopt.k) {
+        return Err(err("k", Some("-k"), "must be between 1 and 28"));
+    }
+    if !(1..=255).contains(&idxopt.w) {
+        return Err(err("w", Some("-w"), "must be between 1 and 255"));
+    }
+    if !(10..=32).contains(&idxopt.bucket_bits) {
+        return Err(err(
+            "bucket_bits",
+            Some("--bucket-bits"),
+            "must be between 10 and 32",
+        ));
+    }
+    if idxopt.mini_batch_size <= 0 {
+        return Err(err("mini_batch_size (index)", None, "must be positive"));
+    }
+    if idxopt.batch_size == 0 {
+        return Err(err("batch_size", Some("-I"), "must be positive"));
+    }
+
+    let fractions = [
+        ("mask_level", Some("-M"), mapopt.mask_level),
+        ("pri_ratio", Some("-p"), mapopt.pri_ratio),
+        ("alt_drop", Some("--alt-drop"), mapopt.alt_drop),
+        ("rank_frac", None, mapopt.rank_frac),
+        ("rmq_rescue_ratio", None, mapopt.rmq_rescue_ratio),
+        ("mid_occ_frac", Some("-f"), mapopt.mid_occ_frac),
+        ("q_occ_frac", Some("--q-occ-frac"), mapopt.q_occ_frac),
+    ];
+    for (option, flag, value) in fractions {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(err(option, flag, format!("{value} is not between 0 and 1")));
+        }
+    }
+
+    let non_negative_f32 = [
+        (
+            "chain_gap_scale",
+            Some("--chain-gap-scale"),
+            mapopt.chain_gap_scale,
+        ),
+        (
+            "chain_skip_scale",
+            Some("--chain-skip-scale"),
+            mapopt.chain_skip_scale,
+        ),
+        (
+            "max_clip_ratio",
+            Some("--max-clip-ratio"),
+            mapopt.max_clip_ratio,
+        ),
+    ];
+    for (option, flag, value) in non_negative_f32 {
+        if value.is_nan() || value < 0.0 {
+            return Err(err(option, flag, format!("{value} is negative")));
+        }
+    }
+
+    let non_negative = [
+        ("sdust_thres", Some("-T"), mapopt.sdust_thres),
+        ("max_qlen", Some("--max-qlen"), mapopt.max_qlen),
+        ("bw", Some("-r"), mapopt.bw),
+        ("bw_long", Some("-r"), mapopt.bw_long),
+        ("max_gap", Some("-g"), mapopt.max_gap),
+        (
+            "max_chain_skip",
+            Some("--max-chain-skip"),
+            mapopt.max_chain_skip,
+        ),
+        (
+            "max_chain_iter",
+            Some("--max-chain-iter"),
+            mapopt.max_chain_iter,
+        ),
+        ("min_cnt", Some("-n"), mapopt.min_cnt),
+        ("mask_len", Some("--mask-len"), mapopt.mask_len),
+        ("best_n", Some("-N"), mapopt.best_n),
+        ("transition", Some("-b"), mapopt.transition),
+        ("noncan", Some("-C"), mapopt.noncan),
+        ("zdrop_inv", Some("-z"), mapopt.zdrop_inv),
+        ("min_ksw_len", Some("--min-dp-len"), mapopt.min_ksw_len),
+        ("mid_occ", Some("-f"), mapopt.mid_occ),
+    ];
+    for (option, flag, value) in non_negative {
+        if value < 0 {
+            return Err(err(option, flag, format!("{value} is negative")));
+        }
+    }
+
+    if !(0..=3).contains(&mapopt.pe_ori) {
+        return Err(err("pe_ori", None, "must be between 0 and 3"));
+    }
+    if mapopt.mini_batch_size <= 0 {
+        return Err(err("mini_batch_size", Some("-K"), "must be positive"));
+    }
+    if mapopt.max_sw_mat < 0 {
+        return Err(err("max_sw_mat", Some("--cap-sw-mem"), "is negative"));
+    }
+    if mapopt.cap_kalloc < 0 {
+        return Err(err("cap_kalloc", Some("--cap-kalloc"), "is negative"));
+    }
+
+    // The checks of mm_check_opt, in its order
+    if mapopt.bw > mapopt.bw_long {
+        return Err(err(
+            "bw",
+            Some("-r"),
+            format!(
+                "bandwidth {} is larger than the long-join bandwidth {}",
+                mapopt.bw, mapopt.bw_long
+            ),
+        ));
+    }
+    if (mapopt.flag & MM_F_RMQ as i64) != 0 && (mapopt.flag & (MM_F_SR | MM_F_SPLICE) as i64) != 0 {
+        return Err(err(
+            "flag",
+            Some("--rmq"),
+            "RMQ chaining does not work with short-read or spliced mode",
+        ));
+    }
+    if (mapopt.flag & MM_F_FOR_ONLY as i64) != 0 && (mapopt.flag & MM_F_REV_ONLY as i64) != 0 {
+        return Err(err(
+            "flag",
+            Some("--for-only/--rev-only"),
+            "forward-only and reverse-only mapping cannot be used together",
+        ));
+    }
+    if mapopt.a <= 0 {
+        return Err(err("a", Some("-A"), "must be positive"));
+    }
+    if mapopt.b <= 0 {
+        return Err(err("b", Some("-B"), "must be positive"));
+    }
+    if mapopt.q <= 0 {
+        return Err(err("q", Some("-O"), "must be positive"));
+    }
+    if mapopt.e <= 0 {
+        return Err(err("e", Some("-E"), "must be positive"));
+    }
+    if mapopt.q2 < 0 || mapopt.e2 < 0 {
+        return Err(err("q2/e2", Some("-O/-E"), "must not be negative"));
+    }
+    if (mapopt.q != mapopt.q2 || mapopt.e != mapopt.e2)
+        && !(mapopt.e > mapopt.e2 && mapopt.q + mapopt.e < mapopt.q2 + mapopt.e2)
+    {
+        return Err(err(
+            "q2/e2",
+            Some("-O/-E"),
+            "dual gap penalties must satisfy e > e2 and q + e < q2 + e2",
+        ));
+    }
+    if (mapopt.q + mapopt.e) + (mapopt.q2 + mapopt.e2) > 127 {
+        return Err(err(
+            "q/e/q2/e2",
+            Some("-O/-E"),
+            "(q + e) + (q2 + e2) must not exceed 127",
+        ));
+    }
+    if mapopt.sc_ambi < 0 || mapopt.sc_ambi >= mapopt.b {
+        return Err(err(
+            "sc_ambi",
+            Some("--score-N"),
+            format!("must be between 0 and the mismatch penalty {}", mapopt.b),
+        ));
+    }
+    if mapopt.zdrop < mapopt.zdrop_inv {
+        return Err(err(
+            "zdrop",
+            Some("-z"),
+            format!(
+                "Z-drop {} is smaller than the inversion Z-drop {}",
+                mapopt.zdrop, mapopt.zdrop_inv
+            ),
+        ));
+    }
+    if (mapopt.flag & MM_F_NO_PRINT_2ND as i64) != 0 && (mapopt.flag & MM_F_ALL_CHAINS as i64) != 0
+    {
+        return Err(err(
+            "flag",
+            Some("-P/--secondary=no"),
+            "all chains and no secondary alignments cannot be used together",
+        ));
+    }
+    if (mapopt.flag & MM_F_QSTRAND as i64) != 0
+        && ((mapopt.flag & (MM_F_OUT_SAM | MM_F_SPLICE | MM_F_FRAG_MODE) as i64) != 0
+            || (idxopt.flag & MM_I_HPC as i16) != 0)
+    {
+        return Err(err(
+            "flag",
+            Some("--qstrand"),
+            "query strand mode does not work with SAM output, HPC, fragment or spliced mode",
+        ));
+    }
+
+    let ret = unsafe { mm_check_opt(idxopt, mapopt) };
+    if ret != 0 {
+        return Err(err(
+            "options",
+            None,
+            format!("rejected by mm_check_opt (code {ret})"),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Aligner, Preset};
+
+    #[test]
+    fn test_options_apply_only_what_is_set() {
+        let preset = Aligner::builder().map_ont();
+        let aligner = Aligner::builder()
+            .map_ont()
+            .with_map_options(
+                &MapOptions::new()
+                    .best_n(2)
+                    .gap_open(6, Some(30))
+                    .bandwidth(300, None),
+            )
+            .unwrap()
+            .with_index_options(&IndexOptions::new().kmer(19).hpc(true))
+            .unwrap();
+
+        assert_eq!(aligner.mapopt.best_n, 2);
+        assert_eq!(aligner.mapopt.q, 6);
+        assert_eq!(aligner.mapopt.q2, 30);
+        assert_eq!(aligner.mapopt.bw, 300);
+        assert_eq!(aligner.mapopt.bw_long, preset.mapopt.bw_long);
+        assert_eq!(aligner.mapopt.zdrop, preset.mapopt.zdrop);
+        assert_eq!(aligner.idxopt.k, 19);
+        assert_eq!(aligner.idxopt.w, preset.idxopt.w);
+        assert_eq!(aligner.idxopt.flag & MM_I_HPC as i16, MM_I_HPC as i16);
+
+        let aligner = aligner
+            .with_index_options(&IndexOptions::new().hpc(false))
+            .unwrap();
+        assert_eq!(aligner.idxopt.flag & MM_I_HPC as i16, 0);
+    }
+
+    #[test]
+    fn test_options_name_offender() {
+        let check = |options: MapOptions| {
+            Aligner::builder()
+                .map_ont()
+                .with_map_options(&options)
+                .err()
+                .unwrap()
+        };
+
+        let e = check(MapOptions::new().bandwidth(600, Some(500)));
+        assert_eq!((e.option, e.flag), ("bw", Some("-r")));
+
+        let e = check(MapOptions::new().zdrop(100, Some(200)));
+        assert_eq!((e.option, e.flag), ("zdrop", Some("-z")));
+
+        let e = check(MapOptions::new().mismatch_penalty(4).sc_ambi(4));
+        assert_eq!(e.option, "sc_ambi");
+
+        let e = check(MapOptions::new().gap_open(60, None).gap_extend(10, None));
+        assert_eq!(e.option, "q/e/q2/e2");
+
+        let e = check(MapOptions::new().mask_level(-0.5));
+        assert_eq!(e.option, "mask_level");
+        assert_eq!(
+            e.to_string(),
+            "Invalid mask_level (-M): -0.5 is not between 0 and 1"
+        );
+
+        let e = Aligner::builder()
+            .map_ont()
+            .with_index_options(&IndexOptions::new().kmer(29))
+            .err()
+            .unwrap();
+        assert_eq!((e.option, e.flag), ("k", Some("-k")));
+
+        let e = check(MapOptions::new().best_n(-1));
+        assert_eq!((e.option, e.flag), ("best_n", Some("-N")));
+    }
+
+    #[test]
+    fn test_presets_validate() {
+        for preset in [
+            Preset::LrHqae,
+            Preset::LrHq,
+            Preset::Splice,
+            Preset::SpliceHq,
+            Preset::SpliceSr,
+            Preset::Asm,
+            Preset::Asm5,
+            Preset::Asm10,
+            Preset::Asm20,
+            Preset::Sr,
+            Preset::MapPb,
+            Preset::MapHifi,
+            Preset::MapOnt,
+            Preset::AvaPb,
+            Preset::AvaOnt,
+            Preset::Short,
+            Preset::Map10k,
+            Preset::Cdna,
+        ] {
+            let aligner = Aligner::builder().preset(preset.clone());
+            if let Err(e) = validate(&aligner.idxopt, &aligner.mapopt) {
+                panic!("{preset:?}: {e}");
+            }
+        }
+    }
+}
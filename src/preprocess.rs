@@ -0,0 +1,474 @@
+//! Read preprocessing before mapping
+//!
+//! Long-read workflows usually drop short or low quality reads and trim adapters with
+//! separate tools before mapping. A [`Preprocessor`] does the same in-process: head/tail
+//! trimming, adapter trimming, length and mean quality filters and low-complexity filtering
+//! with minimap2's `sdust`, counting what each step did in [`PreprocessStats`].
+//!
+//! [`Aligner::map_file_preprocessed`](crate::Aligner::map_file_preprocessed) runs it on each
+//! read of a FASTA/FASTQ file; trimmed bases show up as clipping, as with
+//! [`QualityOptions`] trimming, which the preprocessor can also apply.
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//! use minimap2::preprocess::Preprocessor;
+//!
+//! let aligner = Aligner::builder()
+//!     .map_ont()
+//!     .with_cigar()
+//!     .with_index("reference.fa", None)
+//!     .expect("Unable to build index");
+//!
+//! let preprocessor = Preprocessor::new()
+//!     .min_len(500)
+//!     .min_mean_qual(10.0)
+//!     .adapter(b"AATGTACTTCGTTCAGTTACGTATTGCT")
+//!     .max_low_complexity(0.5);
+//! let (mappings, stats) = aligner
+//!     .map_file_preprocessed("reads.fastq.gz", false, false, &preprocessor)
+//!     .unwrap();
+//! eprint!("{stats}");
+//! ```
+
+use std::ops::Range;
+
+use minimap2_sys::sdust;
+
+use crate::quality::QualityOptions;
+
+/// Where and how closely adapters must match, see [`Preprocessor::adapter_match`]
+#[derive(Debug, Clone, PartialEq)]
+struct AdapterMatch {
+    window: usize,
+    min_overlap: usize,
+    max_error_rate: f64,
+}
+
+/// Read trimming and filtering, applied in the order of the builder methods below
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preprocessor {
+    head: usize,
+    tail: usize,
+    adapters: Vec<Vec<u8>>,
+    adapter_match: AdapterMatch,
+    min_len: usize,
+    max_len: Option<usize>,
+    min_mean_qual: Option<f64>,
+    max_low_complexity: Option<f64>,
+    sdust_threshold: i32,
+    sdust_window: i32,
+    quality: Option<QualityOptions>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self {
+            head: 0,
+            tail: 0,
+            adapters: Vec::new(),
+            adapter_match: AdapterMatch {
+                window: 150,
+                min_overlap: 10,
+                max_error_rate: 0.15,
+            },
+            min_len: 1,
+            max_len: None,
+            min_mean_qual: None,
+            max_low_complexity: None,
+            // sdust defaults, as minimap2's `-T`
+            sdust_threshold: 20,
+            sdust_window: 64,
+            quality: None,
+        }
+    }
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trim a fixed number of bases from the start of each read
+    pub fn trim_head(mut self, bases: usize) -> Self {
+        self.head = bases;
+        self
+    }
+
+    /// Trim a fixed number of bases from the end of each read
+    pub fn trim_tail(mut self, bases: usize) -> Self {
+        self.tail = bases;
+        self
+    }
+
+    /// Trim an adapter found near either end of the read, together with everything beyond
+    /// it; may be called for several adapters
+    ///
+    /// The adapter is matched as given; add its reverse complement too for adapters that
+    /// can appear on either strand.
+    pub fn adapter(mut self, adapter: &[u8]) -> Self {
+        self.adapters.push(adapter.to_ascii_uppercase());
+        self
+    }
+
+    /// Search adapters within `window` bases of the read ends, with at most
+    /// `max_error_rate` mismatches per aligned base; adapters overhanging the read end must
+    /// still overlap it by `min_overlap` bases (default: 150, 10, 0.15)
+    pub fn adapter_match(mut self, window: usize, min_overlap: usize, max_error_rate: f64) -> Self {
+        self.adapter_match = AdapterMatch {
+            window,
+            min_overlap,
+            max_error_rate,
+        };
+        self
+    }
+
+    /// Drop reads shorter than `len` after trimming
+    pub fn min_len(mut self, len: usize) -> Self {
+        self.min_len = len.max(1);
+        self
+    }
+
+    /// Drop reads longer than `len` after trimming
+    pub fn max_len(mut self, len: usize) -> Self {
+        self.max_len = Some(len);
+        self
+    }
+
+    /// Drop reads with a mean quality below `qual` after trimming
+    ///
+    /// The mean is taken over error probabilities and converted back to a phred score, as
+    /// ONT and PacBio tools report it. Reads without qualities (FASTA) are kept.
+    pub fn min_mean_qual(mut self, qual: f64) -> Self {
+        self.min_mean_qual = Some(qual);
+        self
+    }
+
+    /// Drop reads with more than `fraction` of their bases in low-complexity regions found
+    /// by `sdust`
+    pub fn max_low_complexity(mut self, fraction: f64) -> Self {
+        self.max_low_complexity = Some(fraction);
+        self
+    }
+
+    /// `sdust` score threshold and window size (default: 20, 64)
+    pub fn sdust_params(mut self, threshold: i32, window: i32) -> Self {
+        self.sdust_threshold = threshold;
+        self.sdust_window = window;
+        self
+    }
+
+    /// Quality masking and trimming applied when mapping reads that pass
+    pub fn quality(mut self, quality: QualityOptions) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    /// The quality options set with [`quality`](Self::quality)
+    pub fn quality_options(&self) -> Option<&QualityOptions> {
+        self.quality.as_ref()
+    }
+
+    /// Trim and filter a read, returning the part of it to map or `None` if it is dropped
+    ///
+    /// `qual` is phred+33 encoded. Every read is counted in `stats`.
+    pub fn process(
+        &self,
+        seq: &[u8],
+        qual: Option<&[u8]>,
+        stats: &mut PreprocessStats,
+    ) -> Option<Range<usize>> {
+        stats.reads += 1;
+        stats.bases += seq.len() as u64;
+
+        let mut kept = self.head.min(seq.len())..seq.len().saturating_sub(self.tail);
+        if kept.start > kept.end {
+            kept.end = kept.start;
+        }
+        stats.trimmed_bases += (seq.len() - kept.len()) as u64;
+
+        let mut adapter_found = false;
+        for adapter in &self.adapters {
+            let read = &seq[kept.clone()];
+            if let Some(len) = self.find_adapter(read.iter().copied(), adapter.iter().copied()) {
+                kept.start += len;
+                adapter_found = true;
+            }
+            let read = &seq[kept.clone()];
+            let rev = read.iter().rev().copied();
+            if let Some(len) = self.find_adapter(rev, adapter.iter().rev().copied()) {
+                kept.end -= len;
+                adapter_found = true;
+            }
+        }
+        if adapter_found {
+            stats.adapter_reads += 1;
+        }
+
+        let read = &seq[kept.clone()];
+        if read.len() < self.min_len {
+            stats.too_short += 1;
+            return None;
+        }
+        if self.max_len.is_some_and(|max_len| read.len() > max_len) {
+            stats.too_long += 1;
+            return None;
+        }
+        let low_quality = match (self.min_mean_qual, qual) {
+            (Some(min_qual), Some(qual)) => mean_qual(&qual[kept.clone()]) < min_qual,
+            _ => false,
+        };
+        if low_quality {
+            stats.low_quality += 1;
+            return None;
+        }
+        if let Some(max_fraction) = self.max_low_complexity {
+            let masked = low_complexity_bases(read, self.sdust_threshold, self.sdust_window);
+            if masked as f64 > max_fraction * read.len() as f64 {
+                stats.low_complexity += 1;
+                return None;
+            }
+        }
+
+        stats.passed += 1;
+        stats.passed_bases += read.len() as u64;
+        Some(kept)
+    }
+
+    /// Bases to trim for the best match of `adapter` at the start of `read`, up to and
+    /// including the adapter; the 3' end is searched with both sequences reversed
+    fn find_adapter(
+        &self,
+        read: impl Iterator<Item = u8>,
+        adapter: impl Iterator<Item = u8>,
+    ) -> Option<usize> {
+        let AdapterMatch {
+            window,
+            min_overlap,
+            max_error_rate,
+        } = self.adapter_match;
+        let read: Vec<u8> = read.take(window).map(|b| b.to_ascii_uppercase()).collect();
+        let adapter: Vec<u8> = adapter.collect();
+        let min_overlap = min_overlap.clamp(1, adapter.len().max(1));
+
+        // `end` is where the adapter ends in the read; it may overhang the read start
+        let mut best: Option<(i64, usize)> = None;
+        for end in min_overlap..=read.len() {
+            let start = end.saturating_sub(adapter.len());
+            let overlap = end - start;
+            let mismatches = read[start..end]
+                .iter()
+                .zip(&adapter[adapter.len() - overlap..])
+                .filter(|(r, a)| r != a)
+                .count();
+            if mismatches as f64 > max_error_rate * overlap as f64 {
+                continue;
+            }
+            let score = overlap as i64 - 2 * mismatches as i64;
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, end));
+            }
+        }
+        best.map(|(_, end)| end)
+    }
+}
+
+/// Mean quality of a phred+33 quality string, averaged over error probabilities
+fn mean_qual(qual: &[u8]) -> f64 {
+    if qual.is_empty() {
+        return 0.0;
+    }
+    let error: f64 = qual
+        .iter()
+        .map(|q| 10f64.powf(-(q.saturating_sub(33) as f64) / 10.0))
+        .sum();
+    -10.0 * (error / qual.len() as f64).log10()
+}
+
+/// Number of bases in the low-complexity intervals `sdust` finds in `seq`
+fn low_complexity_bases(seq: &[u8], threshold: i32, window: i32) -> usize {
+    let mut n = 0;
+    let intervals = unsafe {
+        sdust(
+            std::ptr::null_mut(),
+            seq.as_ptr(),
+            seq.len() as i32,
+            threshold,
+            window,
+            &mut n,
+        )
+    };
+    if intervals.is_null() {
+        return 0;
+    }
+    // Each interval is packed as start << 32 | end
+    let masked = unsafe { std::slice::from_raw_parts(intervals, n as usize) }
+        .iter()
+        .map(|&interval| ((interval as u32) - (interval >> 32) as u32) as usize)
+        .sum();
+    unsafe { libc::free(intervals as *mut libc::c_void) };
+    masked
+}
+
+/// Counts of what a [`Preprocessor`] did, per filter
+///
+/// Each dropped read is counted for the first filter it failed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PreprocessStats {
+    pub reads: u64,
+    pub bases: u64,
+    pub passed: u64,
+    pub passed_bases: u64,
+    /// Bases removed by head/tail trimming
+    pub trimmed_bases: u64,
+    /// Reads with at least one adapter trimmed
+    pub adapter_reads: u64,
+    pub too_short: u64,
+    pub too_long: u64,
+    pub low_quality: u64,
+    pub low_complexity: u64,
+}
+
+impl PreprocessStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the counts of another run, e.g. from another thread
+    pub fn merge(&mut self, other: &PreprocessStats) {
+        self.reads += other.reads;
+        self.bases += other.bases;
+        self.passed += other.passed;
+        self.passed_bases += other.passed_bases;
+        self.trimmed_bases += other.trimmed_bases;
+        self.adapter_reads += other.adapter_reads;
+        self.too_short += other.too_short;
+        self.too_long += other.too_long;
+        self.low_quality += other.low_quality;
+        self.low_complexity += other.low_complexity;
+    }
+
+    /// Number of reads dropped by any filter
+    pub fn dropped(&self) -> u64 {
+        self.reads - self.passed
+    }
+}
+
+/// One `name<TAB>count` line per counter
+impl std::fmt::Display for PreprocessStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let counters = [
+            ("reads", self.reads),
+            ("bases", self.bases),
+            ("passed", self.passed),
+            ("passed_bases", self.passed_bases),
+            ("trimmed_bases", self.trimmed_bases),
+            ("adapter_reads", self.adapter_reads),
+            ("too_short", self.too_short),
+            ("too_long", self.too_long),
+            ("low_quality", self.low_quality),
+            ("low_complexity", self.low_complexity),
+        ];
+        for (name, count) in counters {
+            writeln!(f, "{name}\t{count}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADAPTER: &[u8] = b"AATGTACTTCGTTCAGTTACGTATTGCT";
+    const INSERT: &[u8] = b"GATTACAGGCTTCCAAGTCGTACGGATCCATGGCA";
+
+    #[test]
+    fn trim_and_filter() {
+        let mut stats = PreprocessStats::new();
+        let preprocessor = Preprocessor::new().trim_head(2).trim_tail(3).min_len(10);
+        assert_eq!(preprocessor.process(INSERT, None, &mut stats), Some(2..32));
+        assert_eq!(preprocessor.process(b"ACGTACGT", None, &mut stats), None);
+        assert_eq!(preprocessor.process(b"", None, &mut stats), None);
+        assert_eq!(
+            stats,
+            PreprocessStats {
+                reads: 3,
+                bases: 43,
+                passed: 1,
+                passed_bases: 30,
+                trimmed_bases: 5 + 5,
+                too_short: 2,
+                ..Default::default()
+            }
+        );
+
+        let preprocessor = Preprocessor::new().max_len(20);
+        assert_eq!(preprocessor.process(INSERT, None, &mut stats), None);
+        assert_eq!(stats.too_long, 1);
+        assert_eq!(stats.dropped(), 3);
+
+        // Phred 20 throughout, then one base at phred 0 (error rate 1)
+        let preprocessor = Preprocessor::new().min_mean_qual(15.0);
+        let qual = vec![b'5'; INSERT.len()];
+        assert_eq!(
+            preprocessor.process(INSERT, Some(&qual), &mut stats),
+            Some(0..35)
+        );
+        let mut qual = qual;
+        qual[0] = b'!';
+        assert_eq!(preprocessor.process(INSERT, Some(&qual), &mut stats), None);
+        assert_eq!(stats.low_quality, 1);
+        assert!((mean_qual(b"5555") - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn adapters() {
+        let preprocessor = Preprocessor::new().adapter(ADAPTER);
+        let mut stats = PreprocessStats::new();
+
+        // Full adapter with a mismatch after some leading bases, partial adapter at the end
+        let mut read = b"CCGT".to_vec();
+        let mut adapter = ADAPTER.to_vec();
+        adapter[5] = b'G';
+        read.extend(&adapter);
+        read.extend(INSERT);
+        read.extend(&ADAPTER[..15]);
+        let kept = preprocessor.process(&read, None, &mut stats).unwrap();
+        assert_eq!(&read[kept], INSERT);
+
+        // Adapter overhanging the read start
+        let mut read = ADAPTER[12..].to_vec();
+        read.extend(INSERT);
+        let kept = preprocessor.process(&read, None, &mut stats).unwrap();
+        assert_eq!(&read[kept], INSERT);
+
+        // No adapter
+        assert_eq!(preprocessor.process(INSERT, None, &mut stats), Some(0..35));
+        assert_eq!((stats.reads, stats.adapter_reads), (3, 2));
+    }
+
+    #[test]
+    fn low_complexity() {
+        let preprocessor = Preprocessor::new().max_low_complexity(0.5);
+        let mut stats = PreprocessStats::new();
+        let repeat = b"CA".repeat(60);
+        assert_eq!(preprocessor.process(&repeat, None, &mut stats), None);
+        assert_eq!(preprocessor.process(INSERT, None, &mut stats), Some(0..35));
+        assert_eq!(stats.low_complexity, 1);
+    }
+
+    #[test]
+    fn merge_and_report() {
+        let mut stats = PreprocessStats {
+            reads: 2,
+            passed: 1,
+            too_short: 1,
+            ..Default::default()
+        };
+        stats.merge(&stats.clone());
+        assert_eq!((stats.reads, stats.passed, stats.too_short), (4, 2, 2));
+        let report = stats.to_string();
+        assert!(report.starts_with("reads\t4\nbases\t0\npassed\t2\n"));
+        assert!(report.contains("too_short\t2\n"));
+    }
+}
@@ -0,0 +1,212 @@
+//! Quality-aware mapping
+//!
+//! [`Aligner::map`](crate::Aligner::map) only sees the sequence, so FASTQ qualities are lost
+//! before output. [`Aligner::map_with_qual`](crate::Aligner::map_with_qual) also takes the
+//! phred+33 quality string and keeps it on the mappings ([`Mapping::query_qual`]), where the
+//! SAM/BAM record converters pick it up. With [`QualityOptions`] it can mask and/or trim low
+//! quality bases before mapping; coordinates and clipping still refer to the whole read, so
+//! trimmed bases show up as clipping.
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//! use minimap2::quality::QualityOptions;
+//!
+//! let aligner = Aligner::builder()
+//!     .map_ont()
+//!     .with_cigar()
+//!     .with_index("reference.fa", None)
+//!     .expect("Unable to build index");
+//!
+//! let quality = QualityOptions::new().mask_below(5).trim_below(10);
+//! let mappings = aligner
+//!     .map_with_qual(b"ACGT", Some(b"+5?I"), false, false, Some(b"read1"), Some(&quality))
+//!     .unwrap();
+//! ```
+
+use std::borrow::Cow;
+use std::num::NonZeroI32;
+use std::ops::Range;
+
+use crate::{Mapping, Strand};
+
+/// Masking and trimming of low quality bases before mapping
+///
+/// Qualities are phred scores; the quality strings they apply to are phred+33 encoded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QualityOptions {
+    mask: Option<u8>,
+    trim: Option<u8>,
+}
+
+impl QualityOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace bases with a quality below `min_qual` by `N`, so they neither seed nor match
+    pub fn mask_below(mut self, min_qual: u8) -> Self {
+        self.mask = Some(min_qual);
+        self
+    }
+
+    /// Trim low quality ends: keep the part of the read maximising the sum of
+    /// `qual - min_qual`, as BWA's `-q` does for the 3' end, but on both ends
+    pub fn trim_below(mut self, min_qual: u8) -> Self {
+        self.trim = Some(min_qual);
+        self
+    }
+
+    /// The part of a read with quality string `qual` kept by trimming, empty if nothing is
+    pub fn trim_range(&self, qual: &[u8]) -> Range<usize> {
+        let Some(min_qual) = self.trim else {
+            return 0..qual.len();
+        };
+
+        // Maximum-sum segment (Kadane)
+        let (mut best, mut best_range) = (0, 0..0);
+        let (mut sum, mut start) = (0i64, 0);
+        for (i, &q) in qual.iter().enumerate() {
+            let score = q.saturating_sub(33) as i64 - min_qual as i64;
+            if sum <= 0 {
+                sum = 0;
+                start = i;
+            }
+            sum += score;
+            if sum > best {
+                best = sum;
+                best_range = start..i + 1;
+            }
+        }
+        best_range
+    }
+
+    /// The part of `seq` kept by trimming and the sequence to map, trimmed and masked
+    pub fn apply<'a>(&self, seq: &'a [u8], qual: &[u8]) -> (Range<usize>, Cow<'a, [u8]>) {
+        let kept = self.trim_range(qual);
+        let trimmed = &seq[kept.clone()];
+        let Some(min_qual) = self.mask else {
+            return (kept, Cow::Borrowed(trimmed));
+        };
+
+        let masked = trimmed
+            .iter()
+            .zip(&qual[kept.clone()])
+            .map(|(&base, &q)| {
+                if q.saturating_sub(33) < min_qual {
+                    b'N'
+                } else {
+                    base
+                }
+            })
+            .collect();
+        (kept, Cow::Owned(masked))
+    }
+}
+
+/// Move a mapping of `read[kept]` back to the whole read of length `len`: the trimmed bases
+/// become clipping, added to the CIGAR too with `cigar_clipping`
+pub(crate) fn untrim(mapping: &mut Mapping, kept: &Range<usize>, len: usize, cigar_clipping: bool) {
+    if kept.start == 0 && kept.end == len {
+        return;
+    }
+    mapping.query_start += kept.start as i32;
+    mapping.query_end += kept.start as i32;
+    mapping.query_len = NonZeroI32::new(len as i32);
+
+    let (qs, qe) = (mapping.query_start as u32, mapping.query_end as u32);
+    let (clip5, clip3) = match mapping.strand {
+        Strand::Forward => (qs, len as u32 - qe),
+        Strand::Reverse => (len as u32 - qe, qs),
+    };
+    let Some(aln) = mapping.alignment.as_mut() else {
+        return;
+    };
+    let Some(cigar) = aln.cigar.as_mut() else {
+        return;
+    };
+
+    cigar.retain(|&(_, op)| op != 4);
+    let mut cigar_str = String::new();
+    if clip5 > 0 {
+        cigar_str.push_str(&format!("{clip5}S"));
+    }
+    for (len, op) in cigar.iter() {
+        cigar_str.push_str(&format!("{len}{}", b"MIDNSHP=X"[*op as usize] as char));
+    }
+    if clip3 > 0 {
+        cigar_str.push_str(&format!("{clip3}S"));
+    }
+    if cigar_clipping {
+        if clip5 > 0 {
+            cigar.insert(0, (clip5, 4));
+        }
+        if clip3 > 0 {
+            cigar.push((clip3, 4));
+        }
+    }
+    aln.cigar_str = Some(cigar_str);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Alignment;
+
+    #[test]
+    fn trim_and_mask() {
+        let seq = b"ACGTACGTAC";
+        // Phred 2 2 30 30 5 30 30 30 2 2
+        let qual = b"##??&???##";
+
+        let options = QualityOptions::new();
+        assert_eq!(options.apply(seq, qual), (0..10, Cow::Borrowed(&seq[..])));
+
+        let options = QualityOptions::new().trim_below(10);
+        assert_eq!(options.trim_range(qual), 2..8);
+        assert_eq!(options.apply(seq, qual).1.as_ref(), b"GTACGT");
+
+        let options = options.mask_below(10);
+        assert_eq!(options.apply(seq, qual).1.as_ref(), b"GTNCGT");
+
+        // Nothing worth keeping
+        assert_eq!(QualityOptions::new().trim_below(40).trim_range(qual), 0..0);
+    }
+
+    #[test]
+    fn untrim_mapping() {
+        let mapping = Mapping {
+            query_len: NonZeroI32::new(6),
+            query_start: 1,
+            query_end: 6,
+            strand: Strand::Reverse,
+            alignment: Some(Alignment {
+                nm: 0,
+                cigar: Some(vec![(5, 0)]),
+                cigar_str: Some("5M1S".to_string()),
+                md: None,
+                cs: None,
+                alignment_score: None,
+            }),
+            ..Default::default()
+        };
+
+        // Mapped read[2..8] of a 10 bp read
+        let mut m = mapping.clone();
+        untrim(&mut m, &(2..8), 10, false);
+        assert_eq!((m.query_start, m.query_end), (3, 8));
+        assert_eq!(m.query_len, NonZeroI32::new(10));
+        let aln = m.alignment.unwrap();
+        assert_eq!(aln.cigar, Some(vec![(5, 0)]));
+        assert_eq!(aln.cigar_str.as_deref(), Some("2S5M3S"));
+
+        let mut m = mapping.clone();
+        untrim(&mut m, &(2..8), 10, true);
+        let aln = m.alignment.unwrap();
+        assert_eq!(aln.cigar, Some(vec![(2, 4), (5, 0), (3, 4)]));
+
+        // Nothing trimmed
+        let mut m = mapping.clone();
+        untrim(&mut m, &(0..6), 6, true);
+        assert_eq!(m, mapping);
+    }
+}
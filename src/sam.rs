@@ -0,0 +1,377 @@
+//! SAM header and tag helpers shared by the `htslib` and `noodles` features
+//!
+//! [`ReadGroup`] describes an `@RG` header line (as given to `minimap2 -R`); the SAM/BAM
+//! converters of both features tag their records with its `RG:Z` when one is given. Both
+//! also share the clipping of mapped reads and the typed parsing of copied comments.
+//!
+//! ```
+//! use minimap2::sam::ReadGroup;
+//!
+//! let rg = ReadGroup::parse("@RG\\tID:rg1\\tSM:sample1").unwrap();
+//! assert_eq!(rg, ReadGroup::new("rg1").with_sample("sample1"));
+//! assert_eq!(rg.header_line(), "@RG\tID:rg1\tSM:sample1");
+//! ```
+
+#[cfg(any(feature = "htslib", feature = "noodles"))]
+pub(crate) use records::{Clipping, TagValue, comment_tags, masked_seq, record_comment};
+
+/// Errors from parsing a [`ReadGroup`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadGroupError {
+    /// The line does not start with `@RG`
+    NotReadGroup(String),
+    /// The line has no `ID` field
+    MissingId(String),
+    /// A field is not of the form `XX:value`
+    InvalidField(String),
+}
+
+impl std::fmt::Display for ReadGroupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadGroupError::NotReadGroup(line) => write!(f, "Not an @RG line: {line}"),
+            ReadGroupError::MissingId(line) => write!(f, "@RG line without ID: {line}"),
+            ReadGroupError::InvalidField(field) => write!(f, "Invalid @RG field: {field}"),
+        }
+    }
+}
+
+impl std::error::Error for ReadGroupError {}
+
+/// A read group, written as an `@RG` header line and tagged on records as `RG:Z`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadGroup {
+    /// `ID`
+    pub id: String,
+    /// `SM`
+    pub sample: Option<String>,
+    /// `PL`, e.g. `ONT`, `PACBIO` or `ILLUMINA`
+    pub platform: Option<String>,
+    /// `LB`
+    pub library: Option<String>,
+    /// Any other fields (`PU`, `CN`, `DS`, ...), in order
+    pub other: Vec<(String, String)>,
+}
+
+impl ReadGroup {
+    pub fn new(id: impl Into<String>) -> Self {
+        ReadGroup {
+            id: id.into(),
+            sample: None,
+            platform: None,
+            library: None,
+            other: Vec::new(),
+        }
+    }
+
+    pub fn with_sample(mut self, sample: impl Into<String>) -> Self {
+        self.sample = Some(sample.into());
+        self
+    }
+
+    pub fn with_platform(mut self, platform: impl Into<String>) -> Self {
+        self.platform = Some(platform.into());
+        self
+    }
+
+    pub fn with_library(mut self, library: impl Into<String>) -> Self {
+        self.library = Some(library.into());
+        self
+    }
+
+    /// Add another field, e.g. `with_field("PU", "flowcell1.lane2")`
+    pub fn with_field(mut self, tag: impl Into<String>, value: impl Into<String>) -> Self {
+        self.other.push((tag.into(), value.into()));
+        self
+    }
+
+    /// Parse an `@RG` line as given to `minimap2 -R`, e.g. `@RG\tID:rg1\tSM:sample1`;
+    /// fields are separated by tabs or by a literal `\t`
+    pub fn parse(line: &str) -> Result<Self, ReadGroupError> {
+        let line = line.trim_end().replace("\\t", "\t");
+        let mut fields = line.split('\t');
+        if fields.next() != Some("@RG") {
+            return Err(ReadGroupError::NotReadGroup(line));
+        }
+
+        let mut id = None;
+        let mut rg = ReadGroup::new("");
+        for field in fields {
+            let Some((tag, value)) = field.split_once(':') else {
+                return Err(ReadGroupError::InvalidField(field.to_string()));
+            };
+            if tag.len() != 2 {
+                return Err(ReadGroupError::InvalidField(field.to_string()));
+            }
+            match tag {
+                "ID" => id = Some(value.to_string()),
+                "SM" => rg.sample = Some(value.to_string()),
+                "PL" => rg.platform = Some(value.to_string()),
+                "LB" => rg.library = Some(value.to_string()),
+                _ => rg.other.push((tag.to_string(), value.to_string())),
+            }
+        }
+        rg.id = id.ok_or(ReadGroupError::MissingId(line))?;
+        Ok(rg)
+    }
+
+    /// The fields in header order: `ID`, `SM`, `PL`, `LB`, then the others
+    pub fn fields(&self) -> Vec<(&str, &str)> {
+        let mut fields = vec![("ID", self.id.as_str())];
+        let named = [
+            ("SM", &self.sample),
+            ("PL", &self.platform),
+            ("LB", &self.library),
+        ];
+        for (tag, value) in named {
+            if let Some(value) = value {
+                fields.push((tag, value));
+            }
+        }
+        fields.extend(
+            self.other
+                .iter()
+                .map(|(tag, value)| (tag.as_str(), value.as_str())),
+        );
+        fields
+    }
+
+    /// The `@RG` header line, without a trailing newline
+    pub fn header_line(&self) -> String {
+        let mut line = String::from("@RG");
+        for (tag, value) in self.fields() {
+            line.push_str(&format!("\t{tag}:{value}"));
+        }
+        line
+    }
+}
+
+/// Record helpers of the SAM/BAM converters of both features
+#[cfg(any(feature = "htslib", feature = "noodles"))]
+mod records {
+    use std::ops::Range;
+
+    use crate::base_mods::{clip_comment, split_comment};
+    use crate::flags::MapFlags;
+    use crate::{Mapping, Strand};
+
+    /// The clipping of a mapped read in its SAM record
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub(crate) struct Clipping {
+        pub hard: bool,
+        /// Bases clipped at the start and the end of the record, on the target strand
+        pub start: usize,
+        pub end: usize,
+    }
+
+    impl Clipping {
+        pub(crate) fn new(mapping: &Mapping, read_len: usize, flags: MapFlags) -> Self {
+            let secondary = !mapping.is_primary && !mapping.is_supplementary;
+            // Same rule as write_sam_cigar() in minimap2's format.c
+            let hard = (mapping.is_supplementary
+                || (secondary && flags.contains(MapFlags::SECONDARY_SEQ)))
+                && !flags.contains(MapFlags::SOFTCLIP);
+            let (qs, qe) = (mapping.query_start as usize, mapping.query_end as usize);
+            let (start, end) = if mapping.strand == Strand::Reverse {
+                (read_len - qe, qs)
+            } else {
+                (qs, read_len - qe)
+            };
+            Clipping { hard, start, end }
+        }
+
+        /// `cigar` with the clipping added, as `(length, BAM op code)` pairs
+        pub(crate) fn cigar(&self, cigar: &[(u32, u8)]) -> Vec<(u32, u8)> {
+            let op = if self.hard { 5 } else { 4 };
+            let mut ops = Vec::with_capacity(cigar.len() + 2);
+            if self.start > 0 {
+                ops.push((self.start as u32, op));
+            }
+            // Clipping from `with_cigar_clipping()` is replaced by this one
+            ops.extend(cigar.iter().filter(|(_, op)| *op != 4 && *op != 5).copied());
+            if self.end > 0 {
+                ops.push((self.end as u32, op));
+            }
+            ops
+        }
+
+        /// The part of SEQ (on the target strand) kept in the record
+        pub(crate) fn kept(&self, len: usize) -> Range<usize> {
+            if self.hard {
+                self.start..len - self.end
+            } else {
+                0..len
+            }
+        }
+    }
+
+    /// The comment of `mapping` as copied into its record: `MM`/`ML` are clipped to the part of
+    /// `read` a hard-clipped record keeps, and left as they are if invalid
+    pub(crate) fn record_comment(
+        mapping: &Mapping,
+        comment: &str,
+        read: &[u8],
+        hard_clipped: bool,
+    ) -> String {
+        if !hard_clipped {
+            return comment.to_string();
+        }
+        let (start, end) = (mapping.query_start as usize, mapping.query_end as usize);
+        clip_comment(comment, read, start, end).unwrap_or_else(|_| comment.to_string())
+    }
+
+    /// The sequence written as SEQ for a mapping of `read`: [`Mapping::masked_seq`] if it is
+    /// a masked copy of `read`, as the `NM`, `MD` and `cs` tags describe it
+    pub(crate) fn masked_seq<'a>(mapping: &'a Mapping, read: &'a [u8]) -> &'a [u8] {
+        match mapping.masked_seq.as_deref() {
+            Some(masked) if masked.len() == read.len() => masked.as_bytes(),
+            _ => read,
+        }
+    }
+
+    /// The value of a SAM tag copied from a comment
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) enum TagValue<'a> {
+        Char(u8),
+        Int(i64),
+        Float(f32),
+        String(&'a str),
+        Hex(&'a str),
+        Int8Array(Vec<i8>),
+        UInt8Array(Vec<u8>),
+        Int16Array(Vec<i16>),
+        UInt16Array(Vec<u16>),
+        Int32Array(Vec<i32>),
+        UInt32Array(Vec<u32>),
+        FloatArray(Vec<f32>),
+    }
+
+    /// The SAM tags of a copied comment with their typed values, skipping fields that are not
+    /// SAM tags or whose value does not parse
+    pub(crate) fn comment_tags(comment: &str) -> Vec<([u8; 2], TagValue<'_>)> {
+        fn array<T: std::str::FromStr>(values: &str) -> Option<Vec<T>> {
+            values
+                .split(',')
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse().ok())
+                .collect()
+        }
+
+        fn typed<'a>(ty: &str, value: &'a str) -> Option<TagValue<'a>> {
+            match ty {
+                "A" if value.len() == 1 => Some(TagValue::Char(value.as_bytes()[0])),
+                "i" => value.parse().ok().map(TagValue::Int),
+                "f" => value.parse().ok().map(TagValue::Float),
+                "Z" => Some(TagValue::String(value)),
+                "H" => Some(TagValue::Hex(value)),
+                "B" => {
+                    let (subtype, values) = value.split_once(',').unwrap_or((value, ""));
+                    match subtype {
+                        "c" => array(values).map(TagValue::Int8Array),
+                        "C" => array(values).map(TagValue::UInt8Array),
+                        "s" => array(values).map(TagValue::Int16Array),
+                        "S" => array(values).map(TagValue::UInt16Array),
+                        "i" => array(values).map(TagValue::Int32Array),
+                        "I" => array(values).map(TagValue::UInt32Array),
+                        "f" => array(values).map(TagValue::FloatArray),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        }
+
+        split_comment(comment)
+            .into_iter()
+            .filter_map(|(tag, ty, v)| {
+                let tag = tag.as_bytes();
+                Some(([tag[0], tag[1]], typed(ty, v)?))
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_comment_tags() {
+            let tags = comment_tags("BC:Z:ACGT\tXi:i:-3\tML:B:C,1,255\tXf:f:x\tnot a tag\tXa:A:q");
+            assert_eq!(
+                tags,
+                vec![
+                    (*b"BC", TagValue::String("ACGT")),
+                    (*b"Xi", TagValue::Int(-3)),
+                    (*b"ML", TagValue::UInt8Array(vec![1, 255])),
+                    (*b"Xa", TagValue::Char(b'q')),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_clipping() {
+            let mapping = Mapping {
+                query_start: 10,
+                query_end: 90,
+                strand: Strand::Reverse,
+                is_supplementary: true,
+                ..Default::default()
+            };
+            let clipping = Clipping::new(&mapping, 100, MapFlags::empty());
+            assert_eq!(
+                clipping,
+                Clipping {
+                    hard: true,
+                    start: 10,
+                    end: 10
+                }
+            );
+            assert_eq!(
+                clipping.cigar(&[(3, 4), (80, 0)]),
+                vec![(10, 5), (80, 0), (10, 5)]
+            );
+            assert_eq!(clipping.kept(100), 10..90);
+
+            let soft = Clipping::new(&mapping, 100, MapFlags::SOFTCLIP);
+            assert!(!soft.hard);
+            assert_eq!(soft.kept(100), 0..100);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_group() {
+        let rg = ReadGroup::parse("@RG\\tID:rg1\\tSM:sample1\\tPL:ONT\\tPU:flowcell1").unwrap();
+        assert_eq!(
+            rg,
+            ReadGroup::new("rg1")
+                .with_sample("sample1")
+                .with_platform("ONT")
+                .with_field("PU", "flowcell1")
+        );
+        assert_eq!(
+            rg.header_line(),
+            "@RG\tID:rg1\tSM:sample1\tPL:ONT\tPU:flowcell1"
+        );
+        assert_eq!(
+            ReadGroup::parse("@RG\tID:rg1").unwrap(),
+            ReadGroup::new("rg1")
+        );
+        assert!(matches!(
+            ReadGroup::parse("@RG\tSM:sample1"),
+            Err(ReadGroupError::MissingId(_))
+        ));
+        assert!(matches!(
+            ReadGroup::parse("@SQ\tID:rg1"),
+            Err(ReadGroupError::NotReadGroup(_))
+        ));
+        assert!(matches!(
+            ReadGroup::parse("@RG\tID:rg1\tSM"),
+            Err(ReadGroupError::InvalidField(_))
+        ));
+    }
+}
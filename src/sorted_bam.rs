@@ -0,0 +1,533 @@
+//! Coordinate-sorted, indexed BAM output
+//!
+//! [`SortedBamWriter`] replaces `samtools sort` + `samtools index` at the end of a mapping
+//! pipeline. Records are buffered up to a memory budget; a full buffer is sorted and spilled
+//! to a temporary BAM file next to the output. [`finish`](SortedBamWriter::finish) merges the
+//! spilled chunks into the final BAM (`@HD SO:coordinate`) and builds a BAI or CSI index.
+//!
+//! Records are ordered like `samtools sort`: by reference, position and strand, with
+//! unmapped reads last; ties keep their input order.
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//! use minimap2::htslib::MMIndex;
+//! use minimap2::sorted_bam::{IndexType, SortedBamWriter};
+//! use rust_htslib::bam::HeaderView;
+//!
+//! let aligner = Aligner::builder()
+//!     .map_ont()
+//!     .with_cigar()
+//!     .with_index("ref.fa", None)
+//!     .unwrap();
+//! let header = MMIndex::from(&aligner).get_header();
+//! let header_view = HeaderView::from_header(&header);
+//!
+//! let mut writer = SortedBamWriter::new("out.bam", &header)
+//!     .with_memory_limit(256 << 20)
+//!     .with_index(IndexType::Csi(14));
+//! let records = aligner
+//!     .map_to_sam(b"ACGGTAGAGAGGAAGCAAT", None, Some(b"read1"), &header_view, None, None)
+//!     .unwrap();
+//! writer.push_all(records).unwrap();
+//! writer.finish().unwrap();
+//! ```
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+
+use rust_htslib::bam::{self, CompressionLevel, Format, Header, HeaderView, Read, Record};
+
+use crate::Mapping;
+use crate::flags::MapFlags;
+use crate::htslib::{ReadGroup, mapping_to_record};
+
+/// Default memory budget for buffered records, as `samtools sort -m 768M`
+pub const DEFAULT_MEMORY_LIMIT: usize = 768 << 20;
+
+/// Errors from [`SortedBamWriter`]
+#[derive(Debug)]
+pub enum SortError {
+    /// Reading, writing or indexing a BAM file failed
+    Htslib(rust_htslib::errors::Error),
+    /// A temporary file could not be removed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for SortError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortError::Htslib(e) => write!(f, "{e}"),
+            SortError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SortError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SortError::Htslib(e) => Some(e),
+            SortError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<rust_htslib::errors::Error> for SortError {
+    fn from(e: rust_htslib::errors::Error) -> Self {
+        SortError::Htslib(e)
+    }
+}
+
+impl From<std::io::Error> for SortError {
+    fn from(e: std::io::Error) -> Self {
+        SortError::Io(e)
+    }
+}
+
+/// Index written next to the sorted BAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexType {
+    /// `.bai`, for references up to 512 Mbp
+    Bai,
+    /// `.csi` with the given minimum shift (`samtools index -c -m`, usually 14)
+    Csi(u32),
+}
+
+/// Sort key: reference (unmapped last), position, strand
+fn sort_key(record: &Record) -> (u32, i64, bool) {
+    let tid = if record.tid() < 0 {
+        u32::MAX
+    } else {
+        record.tid() as u32
+    };
+    (tid, record.pos(), record.is_reverse())
+}
+
+/// Approximate memory held by a buffered record
+fn record_size(record: &Record) -> usize {
+    std::mem::size_of::<Record>() + record.inner().l_data.max(0) as usize
+}
+
+/// Writes records to a coordinate-sorted BAM file, see the [module docs](self)
+pub struct SortedBamWriter {
+    path: PathBuf,
+    header: Header,
+    header_view: HeaderView,
+    map_flags: MapFlags,
+    read_group: Option<ReadGroup>,
+    tmp_dir: Option<PathBuf>,
+    memory_limit: usize,
+    index: Option<IndexType>,
+    threads: usize,
+    buffer: Vec<Record>,
+    buffered: usize,
+    chunks: Vec<PathBuf>,
+    n_records: u64,
+}
+
+impl SortedBamWriter {
+    /// Write a sorted BAM to `path`, with the `@SQ` lines etc. of `header`
+    ///
+    /// Any `@HD` line of `header` is replaced by `@HD VN:1.6 SO:coordinate`.
+    pub fn new<P: AsRef<Path>>(path: P, header: &Header) -> Self {
+        let text = String::from_utf8_lossy(&header.to_bytes()).into_owned();
+        let mut sorted = String::from("@HD\tVN:1.6\tSO:coordinate\n");
+        for line in text.lines().filter(|line| !line.starts_with("@HD")) {
+            sorted.push_str(line);
+            sorted.push('\n');
+        }
+        let header_view = HeaderView::from_bytes(sorted.as_bytes());
+        let header = Header::from_template(&header_view);
+
+        SortedBamWriter {
+            path: path.as_ref().to_path_buf(),
+            header,
+            header_view,
+            map_flags: MapFlags::empty(),
+            read_group: None,
+            tmp_dir: None,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            index: Some(IndexType::Bai),
+            threads: 1,
+            buffer: Vec::new(),
+            buffered: 0,
+            chunks: Vec::new(),
+            n_records: 0,
+        }
+    }
+
+    /// Memory budget for buffered records before a chunk is spilled to disk (default 768 MiB)
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
+    /// Directory for spilled chunks (default: the directory of the output file)
+    pub fn with_tmp_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.tmp_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Index to build after sorting (default BAI)
+    pub fn with_index(mut self, index: IndexType) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Do not build an index
+    pub fn without_index(mut self) -> Self {
+        self.index = None;
+        self
+    }
+
+    /// Mapping flags used by [`push_mapping`](Self::push_mapping), usually
+    /// [`Aligner::map_flags`](crate::Aligner::map_flags)
+    pub fn with_map_flags(mut self, flags: MapFlags) -> Self {
+        self.map_flags = flags;
+        self
+    }
+
+    /// Tag every record added with the `RG:Z` of `read_group`; `header` should have its
+    /// `@RG` line, e.g. from [`Aligner::sam_header`](crate::Aligner::sam_header)
+    pub fn with_read_group(mut self, read_group: &ReadGroup) -> Self {
+        self.read_group = Some(read_group.clone());
+        self
+    }
+
+    /// Threads for BGZF compression of the output and for indexing
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// The header of the sorted output
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Number of records pushed so far
+    pub fn len(&self) -> u64 {
+        self.n_records
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.n_records == 0
+    }
+
+    /// Add a record, e.g. from [`Aligner::map_to_sam`](crate::Aligner::map_to_sam)
+    pub fn push(&mut self, mut record: Record) -> Result<(), SortError> {
+        if let Some(read_group) = &self.read_group {
+            read_group.tag_record(&mut record)?;
+        }
+        self.buffered += record_size(&record);
+        self.buffer.push(record);
+        self.n_records += 1;
+        if self.buffered >= self.memory_limit {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Add every record of `records`
+    pub fn push_all(&mut self, records: impl IntoIterator<Item = Record>) -> Result<(), SortError> {
+        records.into_iter().try_for_each(|record| self.push(record))
+    }
+
+    /// Add a mapping of a read, converted with [`mapping_to_record`]; `None` adds the read
+    /// as unmapped
+    pub fn push_mapping(
+        &mut self,
+        mapping: Option<&Mapping>,
+        seq: &[u8],
+        qual: Option<&[u8]>,
+        query_name: Option<&[u8]>,
+    ) -> Result<(), SortError> {
+        let record = mapping_to_record(
+            mapping,
+            seq,
+            &self.header_view,
+            qual,
+            query_name,
+            self.map_flags,
+        );
+        self.push(record)
+    }
+
+    /// Sort the buffer and write it to a temporary chunk
+    fn spill(&mut self) -> Result<(), SortError> {
+        let dir = match &self.tmp_dir {
+            Some(dir) => dir.clone(),
+            None => self
+                .path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+        };
+        let name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        let chunk = dir.join(format!("{}.tmp.{:04}.bam", name, self.chunks.len()));
+        // Registered first, so a failed write is still cleaned up on drop
+        self.chunks.push(chunk.clone());
+
+        let mut writer = bam::Writer::from_path(&chunk, &self.header, Format::Bam)?;
+        writer.set_compression_level(CompressionLevel::Fastest)?;
+        self.buffer.sort_by_key(sort_key);
+        for record in self.buffer.drain(..) {
+            writer.write(&record)?;
+        }
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Merge all records into the sorted BAM and build its index
+    ///
+    /// Returns the number of records written.
+    pub fn finish(mut self) -> Result<u64, SortError> {
+        {
+            let mut writer = bam::Writer::from_path(&self.path, &self.header, Format::Bam)?;
+            if self.threads > 1 {
+                writer.set_threads(self.threads)?;
+            }
+
+            self.buffer.sort_by_key(sort_key);
+            if self.chunks.is_empty() {
+                for record in &self.buffer {
+                    writer.write(record)?;
+                }
+            } else {
+                let mut runs = self
+                    .chunks
+                    .iter()
+                    .map(|chunk| Ok(Run::Chunk(bam::Reader::from_path(chunk)?)))
+                    .collect::<Result<Vec<_>, SortError>>()?;
+                runs.push(Run::Buffer(std::mem::take(&mut self.buffer).into_iter()));
+                merge(runs, |record| writer.write(record))?;
+            }
+        }
+
+        match self.index {
+            Some(IndexType::Bai) => {
+                bam::index::build(&self.path, None, bam::index::Type::Bai, self.threads as u32)?
+            }
+            Some(IndexType::Csi(min_shift)) => bam::index::build(
+                &self.path,
+                None,
+                bam::index::Type::Csi(min_shift),
+                self.threads as u32,
+            )?,
+            None => {}
+        }
+
+        for chunk in std::mem::take(&mut self.chunks) {
+            std::fs::remove_file(chunk)?;
+        }
+        Ok(self.n_records)
+    }
+}
+
+impl Drop for SortedBamWriter {
+    fn drop(&mut self) {
+        for chunk in &self.chunks {
+            let _ = std::fs::remove_file(chunk);
+        }
+    }
+}
+
+/// A sorted run of records: a spilled chunk or the in-memory remainder
+enum Run {
+    Chunk(bam::Reader),
+    Buffer(std::vec::IntoIter<Record>),
+}
+
+impl Run {
+    fn next(&mut self) -> Result<Option<Record>, SortError> {
+        match self {
+            Run::Chunk(reader) => {
+                let mut record = Record::new();
+                match reader.read(&mut record) {
+                    None => Ok(None),
+                    Some(result) => result.map(|_| Some(record)).map_err(SortError::from),
+                }
+            }
+            Run::Buffer(records) => Ok(records.next()),
+        }
+    }
+}
+
+/// Heap entry; runs are in input order, so ties go to the earlier run
+struct Head {
+    key: (u32, i64, bool),
+    run: usize,
+    record: Record,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key, self.run).cmp(&(other.key, other.run))
+    }
+}
+
+/// K-way merge of sorted runs
+fn merge<F>(mut runs: Vec<Run>, mut write: F) -> Result<(), SortError>
+where
+    F: FnMut(&Record) -> rust_htslib::errors::Result<()>,
+{
+    let mut heap = BinaryHeap::with_capacity(runs.len());
+    for (run, records) in runs.iter_mut().enumerate() {
+        if let Some(record) = records.next()? {
+            heap.push(Reverse(Head {
+                key: sort_key(&record),
+                run,
+                record,
+            }));
+        }
+    }
+    while let Some(Reverse(head)) = heap.pop() {
+        write(&head.record)?;
+        if let Some(record) = runs[head.run].next()? {
+            heap.push(Reverse(Head {
+                key: sort_key(&record),
+                run: head.run,
+                record,
+            }));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Aligner;
+    use crate::htslib::MMIndex;
+    use rust_htslib::bam::IndexedReader;
+    use rust_htslib::bam::record::Aux;
+
+    /// Map every read of a FASTQ with map_to_sam
+    fn mapped_records(aligner: &crate::Aligner<crate::Built>, header: &HeaderView) -> Vec<Record> {
+        let text = std::fs::read_to_string("test_data/gDNA_reads.fq").unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        lines
+            .chunks(4)
+            .flat_map(|read| {
+                let name = read[0][1..].split_whitespace().next().unwrap();
+                aligner
+                    .map_to_sam(
+                        read[1].as_bytes(),
+                        Some(read[3].as_bytes()),
+                        Some(name.as_bytes()),
+                        header,
+                        None,
+                        None,
+                    )
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn sorted_keys(path: &Path) -> Vec<(u32, i64, bool)> {
+        let mut reader = bam::Reader::from_path(path).unwrap();
+        let text = String::from_utf8(reader.header().as_bytes().to_vec()).unwrap();
+        assert!(text.starts_with("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:chr1"));
+        reader.records().map(|r| sort_key(&r.unwrap())).collect()
+    }
+
+    #[test]
+    fn sort_and_index() {
+        let aligner = Aligner::builder()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+        let header = MMIndex::from(&aligner).get_header();
+        let header_view = HeaderView::from_header(&header);
+        let records = mapped_records(&aligner, &header_view);
+        let mut expected: Vec<_> = records.iter().map(sort_key).collect();
+        expected.sort();
+        assert!(
+            expected.last().unwrap().0 == u32::MAX,
+            "expected an unmapped read"
+        );
+
+        let dir = std::env::temp_dir().join(format!("minimap2-rs-sort-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Everything in memory
+        let path = dir.join("in_memory.bam");
+        let mut writer = SortedBamWriter::new(&path, &header);
+        writer.push_all(records.iter().cloned()).unwrap();
+        assert_eq!(writer.finish().unwrap(), records.len() as u64);
+        assert_eq!(sorted_keys(&path), expected);
+        assert!(dir.join("in_memory.bam.bai").exists());
+
+        // A tiny budget spills every few records; the chunks are merged and removed
+        let path = dir.join("spilled.bam");
+        let mut writer = SortedBamWriter::new(&path, &header)
+            .with_memory_limit(1024)
+            .with_index(IndexType::Csi(14))
+            .with_threads(2);
+        writer.push_all(records.iter().cloned()).unwrap();
+        assert!(writer.chunks.len() > 1);
+        writer.finish().unwrap();
+        assert_eq!(sorted_keys(&path), expected);
+        let mut indexed = IndexedReader::from_path(&path).unwrap();
+        indexed.fetch("chr1").unwrap();
+        assert!(indexed.records().count() > 0);
+
+        let leftovers: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.contains(".tmp."))
+            .collect();
+        assert!(leftovers.is_empty(), "{leftovers:?}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn push_mappings() {
+        let aligner = Aligner::builder()
+            .with_index_threads(1)
+            .with_cigar()
+            .with_index("test_data/genome.fa", None)
+            .unwrap();
+        let read_group = ReadGroup::new("rg1");
+        let header = aligner.sam_header(std::slice::from_ref(&read_group), &[]);
+        let seq = b"TACGCCACACGGGCTACACTCTCGCCTTCTCGTCTCAACTACGAGATGGACTGTCGGCCTAGAGGATCTAACACGAGAAGTACTTGCCGGCAAGCCCTAA";
+        let mappings = aligner.map(seq, false, false, None, None, None).unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("minimap2-rs-push-{}.bam", std::process::id()));
+        let mut writer = SortedBamWriter::new(&path, &header)
+            .with_map_flags(aligner.map_flags())
+            .with_read_group(&read_group)
+            .without_index();
+        writer
+            .push_mapping(None, seq, None, Some(b"unmapped"))
+            .unwrap();
+        writer
+            .push_mapping(mappings.first(), seq, None, Some(b"read1"))
+            .unwrap();
+        writer.finish().unwrap();
+
+        let keys = sorted_keys(&path);
+        assert_eq!(keys, vec![(0, 180, false), (u32::MAX, -1, false)]);
+        let mut reader = bam::Reader::from_path(&path).unwrap();
+        for record in reader.records() {
+            assert_eq!(record.unwrap().aux(b"RG").unwrap(), Aux::String("rg1"));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
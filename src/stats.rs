@@ -0,0 +1,365 @@
+//! Mapping summary statistics, like `samtools flagstat` / `samtools stats`
+//!
+//! [`MappingStats`] accumulates the mappings of each query (and queries that did not map)
+//! into read, record and per-contig counts, MAPQ, identity and read length distributions,
+//! so QC does not need a second pass over a BAM file. Accumulators can be merged (e.g. one
+//! per thread) and exported as TSV, or JSON with the `serde` feature.
+//!
+//! ```no_run
+//! use minimap2::Aligner;
+//! use minimap2::stats::MappingStats;
+//!
+//! let aligner = Aligner::builder()
+//!     .map_ont()
+//!     .with_index("reference.fa", None)
+//!     .expect("Unable to build index");
+//!
+//! let mut stats = MappingStats::new();
+//! let seq = b"ACGTACGTACGT";
+//! let mappings = aligner.map(seq, false, false, None, None, None).unwrap();
+//! stats.add_read(seq.len(), &mappings);
+//!
+//! println!("{:.1}% mapped, N50 {}", 100.0 * stats.mapped_fraction(), stats.n50());
+//! stats.write_tsv(std::io::stdout()).unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::Mapping;
+
+/// Mappings and aligned bases on one target contig
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ContigStats {
+    pub name: String,
+    pub length: u64,
+    /// Primary mappings
+    pub mappings: u64,
+    /// Target bases covered by primary and supplementary mappings
+    pub aligned_bases: u64,
+}
+
+impl ContigStats {
+    /// Mean depth over the contig, counting each mapping's whole target span
+    pub fn mean_depth(&self) -> f64 {
+        if self.length == 0 {
+            return 0.0;
+        }
+        self.aligned_bases as f64 / self.length as f64
+    }
+}
+
+/// Summary statistics accumulator for mapping results
+///
+/// A read is mapped if it has a primary mapping. The MAPQ and identity distributions are
+/// over primary mappings; identity is `match_len / block_len` (the BLAST identity of PAF
+/// columns 10 and 11), binned by whole percent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MappingStats {
+    reads: u64,
+    read_bases: u64,
+    mapped_reads: u64,
+    mapped_read_bases: u64,
+    primary: u64,
+    secondary: u64,
+    supplementary: u64,
+    mapq: BTreeMap<u32, u64>,
+    identity: BTreeMap<u32, u64>,
+    read_lengths: BTreeMap<u64, u64>,
+    contigs: BTreeMap<i32, ContigStats>,
+}
+
+impl MappingStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one query of length `read_len` and all its mappings (none if it did not map)
+    pub fn add_read(&mut self, read_len: usize, mappings: &[Mapping]) {
+        let read_len = read_len as u64;
+        self.reads += 1;
+        self.read_bases += read_len;
+        *self.read_lengths.entry(read_len).or_insert(0) += 1;
+        if mappings.iter().any(|m| m.is_primary) {
+            self.mapped_reads += 1;
+            self.mapped_read_bases += read_len;
+        }
+        for mapping in mappings {
+            self.add_mapping(mapping);
+        }
+    }
+
+    /// Add a single mapping record, without counting its query
+    pub fn add_mapping(&mut self, mapping: &Mapping) {
+        if mapping.is_primary {
+            self.primary += 1;
+            *self.mapq.entry(mapping.mapq).or_insert(0) += 1;
+            if mapping.block_len > 0 {
+                let identity = 100 * mapping.match_len as u32 / mapping.block_len as u32;
+                *self.identity.entry(identity).or_insert(0) += 1;
+            }
+        } else if mapping.is_supplementary {
+            self.supplementary += 1;
+        } else {
+            self.secondary += 1;
+            return;
+        }
+
+        let contig = self
+            .contigs
+            .entry(mapping.target_id)
+            .or_insert_with(|| ContigStats {
+                name: mapping
+                    .target_name
+                    .as_ref()
+                    .map_or_else(String::new, |name| name.to_string()),
+                length: mapping.target_len as u64,
+                ..Default::default()
+            });
+        if mapping.is_primary {
+            contig.mappings += 1;
+        }
+        contig.aligned_bases += (mapping.target_end - mapping.target_start) as u64;
+    }
+
+    /// Combine the counts of another accumulator into this one (e.g. one per thread)
+    pub fn merge(&mut self, other: &MappingStats) {
+        self.reads += other.reads;
+        self.read_bases += other.read_bases;
+        self.mapped_reads += other.mapped_reads;
+        self.mapped_read_bases += other.mapped_read_bases;
+        self.primary += other.primary;
+        self.secondary += other.secondary;
+        self.supplementary += other.supplementary;
+        for (mapq, count) in &other.mapq {
+            *self.mapq.entry(*mapq).or_insert(0) += count;
+        }
+        for (identity, count) in &other.identity {
+            *self.identity.entry(*identity).or_insert(0) += count;
+        }
+        for (len, count) in &other.read_lengths {
+            *self.read_lengths.entry(*len).or_insert(0) += count;
+        }
+        for (id, other) in &other.contigs {
+            let contig = self.contigs.entry(*id).or_insert_with(|| ContigStats {
+                name: other.name.clone(),
+                length: other.length,
+                ..Default::default()
+            });
+            contig.mappings += other.mappings;
+            contig.aligned_bases += other.aligned_bases;
+        }
+    }
+
+    /// Number of queries added
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Number of queries with a primary mapping
+    pub fn mapped_reads(&self) -> u64 {
+        self.mapped_reads
+    }
+
+    /// Fraction of queries with a primary mapping
+    pub fn mapped_fraction(&self) -> f64 {
+        if self.reads == 0 {
+            return 0.0;
+        }
+        self.mapped_reads as f64 / self.reads as f64
+    }
+
+    /// Primary, secondary and supplementary mapping counts
+    pub fn records(&self) -> (u64, u64, u64) {
+        (self.primary, self.secondary, self.supplementary)
+    }
+
+    /// MAPQ of primary mappings as `MAPQ -> count`
+    pub fn mapq(&self) -> &BTreeMap<u32, u64> {
+        &self.mapq
+    }
+
+    /// Identity of primary mappings as `percent -> count`
+    pub fn identity(&self) -> &BTreeMap<u32, u64> {
+        &self.identity
+    }
+
+    /// Mean identity of primary mappings, from the percent bins
+    pub fn mean_identity(&self) -> f64 {
+        let count: u64 = self.identity.values().sum();
+        if count == 0 {
+            return 0.0;
+        }
+        let sum: u64 = self.identity.iter().map(|(pct, n)| *pct as u64 * n).sum();
+        sum as f64 / count as f64 / 100.0
+    }
+
+    /// Read lengths as `length -> count`
+    pub fn read_lengths(&self) -> &BTreeMap<u64, u64> {
+        &self.read_lengths
+    }
+
+    /// Read length N50: half of all bases are in reads at least this long
+    pub fn n50(&self) -> u64 {
+        let mut cumulative = 0;
+        for (len, count) in self.read_lengths.iter().rev() {
+            cumulative += len * count;
+            if 2 * cumulative >= self.read_bases {
+                return *len;
+            }
+        }
+        0
+    }
+
+    /// Per-contig counts, in index order
+    pub fn contigs(&self) -> impl Iterator<Item = &ContigStats> {
+        self.contigs.values()
+    }
+
+    /// Write a `samtools stats`-like report: a `SN` summary section then `MAPQ`, `ID`
+    /// (identity), `RL` (read length) and `CONTIG` sections, one tab-separated row each
+    pub fn write_tsv<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let summary: [(&str, String); 11] = [
+            ("reads", self.reads.to_string()),
+            ("bases", self.read_bases.to_string()),
+            ("mapped reads", self.mapped_reads.to_string()),
+            ("mapped bases", self.mapped_read_bases.to_string()),
+            ("mapped fraction", format!("{:.4}", self.mapped_fraction())),
+            ("primary", self.primary.to_string()),
+            ("secondary", self.secondary.to_string()),
+            ("supplementary", self.supplementary.to_string()),
+            ("mean identity", format!("{:.4}", self.mean_identity())),
+            ("N50", self.n50().to_string()),
+            ("contigs with mappings", self.contigs.len().to_string()),
+        ];
+        for (key, value) in summary {
+            writeln!(w, "SN\t{key}\t{value}")?;
+        }
+        for (mapq, count) in &self.mapq {
+            writeln!(w, "MAPQ\t{mapq}\t{count}")?;
+        }
+        for (identity, count) in &self.identity {
+            writeln!(w, "ID\t{identity}\t{count}")?;
+        }
+        for (len, count) in &self.read_lengths {
+            writeln!(w, "RL\t{len}\t{count}")?;
+        }
+        for contig in self.contigs() {
+            writeln!(
+                w,
+                "CONTIG\t{}\t{}\t{}\t{}\t{:.4}",
+                contig.name,
+                contig.length,
+                contig.mappings,
+                contig.aligned_bases,
+                contig.mean_depth()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write the statistics as a JSON object
+    #[cfg(feature = "serde")]
+    pub fn write_json<W: Write>(&self, w: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(w, self).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn mapping(target: i32, primary: bool, supplementary: bool, mapq: u32) -> Mapping {
+        Mapping {
+            target_id: target,
+            target_name: Some(Arc::new(format!("chr{}", target + 1))),
+            target_len: 1000,
+            target_start: 100,
+            target_end: 300,
+            match_len: 190,
+            block_len: 200,
+            mapq,
+            is_primary: primary,
+            is_supplementary: supplementary,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn accumulate() {
+        let mut stats = MappingStats::new();
+        stats.add_read(
+            400,
+            &[
+                mapping(0, true, false, 60),
+                mapping(1, false, true, 60),
+                mapping(1, false, false, 0),
+            ],
+        );
+        stats.add_read(100, &[mapping(1, true, false, 5)]);
+        stats.add_read(50, &[]);
+        stats.add_read(50, &[]);
+
+        assert_eq!((stats.reads(), stats.mapped_reads()), (4, 2));
+        assert_eq!(stats.mapped_fraction(), 0.5);
+        assert_eq!(stats.records(), (2, 1, 1));
+        assert_eq!(stats.mapq(), &BTreeMap::from([(5, 1), (60, 1)]));
+        assert_eq!(stats.identity(), &BTreeMap::from([(95, 2)]));
+        assert!((stats.mean_identity() - 0.95).abs() < 1e-9);
+        // 600 bases, the 400 bp read alone holds half
+        assert_eq!(stats.n50(), 400);
+
+        let contigs: Vec<_> = stats.contigs().collect();
+        assert_eq!(contigs.len(), 2);
+        assert_eq!((contigs[0].name.as_str(), contigs[0].mappings), ("chr1", 1));
+        assert_eq!((contigs[1].mappings, contigs[1].aligned_bases), (1, 400));
+        assert_eq!(contigs[1].mean_depth(), 0.4);
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = MappingStats::new();
+        a.add_read(100, &[mapping(0, true, false, 60)]);
+        let mut b = MappingStats::new();
+        b.add_read(300, &[mapping(0, true, false, 60)]);
+        b.add_read(10, &[]);
+
+        let mut all = MappingStats::new();
+        all.add_read(100, &[mapping(0, true, false, 60)]);
+        all.add_read(300, &[mapping(0, true, false, 60)]);
+        all.add_read(10, &[]);
+
+        a.merge(&b);
+        assert_eq!(a, all);
+    }
+
+    #[test]
+    fn tsv() {
+        let mut stats = MappingStats::new();
+        stats.add_read(200, &[mapping(0, true, false, 60)]);
+        let mut out = Vec::new();
+        stats.write_tsv(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("SN\treads\t1\nSN\tbases\t200\n"));
+        assert!(out.contains("SN\tmapped fraction\t1.0000\n"));
+        assert!(out.contains("MAPQ\t60\t1\nID\t95\t1\nRL\t200\t1\n"));
+        assert!(out.ends_with("CONTIG\tchr1\t1000\t1\t200\t0.2000\n"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        let mut stats = MappingStats::new();
+        stats.add_read(200, &[mapping(0, true, false, 60)]);
+        let mut out = Vec::new();
+        stats.write_json(&mut out).unwrap();
+        let restored: MappingStats = serde_json::from_slice(&out).unwrap();
+        assert_eq!(restored, stats);
+    }
+}